@@ -0,0 +1,174 @@
+//! Derive macros for [the `libffi` crate](https://crates.io/crates/libffi).
+//!
+//! This crate is an implementation detail of `libffi`. Enable its `derive`
+//! feature and use `libffi::high::CType` instead of depending on this crate
+//! directly.
+
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Member, Result};
+
+/// Derives `libffi::high::CType` for a `#[repr(C)]` struct.
+///
+/// The generated implementation describes the struct to libffi as a
+/// structure whose fields have the types of the Rust fields, in
+/// declaration order. Every field type must itself implement `CType`,
+/// except that array fields such as `[u8; 16]` are described with
+/// `libffi::middle::Type::array` and only need their element type to
+/// implement `CType`.
+///
+/// When the type is reified, the layout computed by libffi is compared
+/// against the layout chosen by the Rust compiler, and a mismatch causes a
+/// panic. With the `std` feature of `libffi`, the checked type of a struct
+/// without type or const parameters is built once and cached.
+#[proc_macro_derive(CType)]
+pub fn derive_ctype(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(mut input: DeriveInput) -> Result<TokenStream2> {
+    check_repr(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        Data::Enum(data) => {
+            return Err(Error::new_spanned(
+                data.enum_token,
+                "`CType` cannot be derived for enums",
+            ))
+        }
+        Data::Union(data) => {
+            return Err(Error::new_spanned(
+                data.union_token,
+                "`CType` cannot be derived for unions",
+            ))
+        }
+    };
+
+    let members: Vec<(Member, &syn::Type)> = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|field| (Member::Named(field.ident.clone().unwrap()), &field.ty))
+            .collect(),
+        Fields::Unnamed(unnamed) => unnamed
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(index, field)| (Member::from(index), &field.ty))
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    if members.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "`CType` cannot be derived for structs without fields",
+        ));
+    }
+
+    let name = &input.ident;
+    let count = members.len();
+    let (field_types, bounds): (Vec<TokenStream2>, Vec<&syn::Type>) =
+        members.iter().map(|(_, ty)| reify_field(ty)).unzip();
+    let field_members = members.iter().map(|(member, _)| member);
+
+    {
+        let where_clause = input.generics.make_where_clause();
+        for ty in bounds {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: ::libffi::high::CType));
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let self_ty = quote!(#name #ty_generics);
+
+    let reify = quote! {
+        ::libffi::high::types::__reify_struct::<Self, #count>(
+            [ #( #field_types ),* ],
+            [ #( ::core::mem::offset_of!(#self_ty, #field_members) ),* ],
+        )
+    };
+    // A static in a generic function is shared by all its instances, so
+    // only structs whose layout does not depend on their parameters cache
+    // their type.
+    let generic = input.generics.type_params().next().is_some()
+        || input.generics.const_params().next().is_some();
+    let body = if generic {
+        reify
+    } else {
+        quote! {
+            static TYPE: ::libffi::high::types::__StructCache =
+                ::libffi::high::types::__StructCache::new();
+            TYPE.get_or_init(|| #reify)
+        }
+    };
+
+    Ok(quote! {
+        unsafe impl #impl_generics ::libffi::high::CType for #self_ty #where_clause {
+            fn reify() -> ::libffi::high::Type<Self> {
+                #body
+            }
+            type RetType = Self;
+        }
+    })
+}
+
+/// Returns an expression for the `libffi::middle::Type` of a field of type
+/// `ty`, along with the type that must implement `CType` for it.
+fn reify_field(ty: &syn::Type) -> (TokenStream2, &syn::Type) {
+    match ty {
+        syn::Type::Array(array) => {
+            let (element, bound) = reify_field(&array.elem);
+            let len = &array.len;
+            (quote!(::libffi::middle::Type::array(#element, #len)), bound)
+        }
+        syn::Type::Group(group) => reify_field(&group.elem),
+        syn::Type::Paren(paren) => reify_field(&paren.elem),
+        _ => (
+            quote!(<#ty as ::libffi::high::CType>::reify().into_middle()),
+            ty,
+        ),
+    }
+}
+
+/// Ensures that the struct is `#[repr(C)]` and carries no other layout
+/// modifiers that libffi cannot describe.
+fn check_repr(input: &DeriveInput) -> Result<()> {
+    let mut repr_c = false;
+
+    for attr in &input.attrs {
+        if !attr.path().is_ident("repr") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+                Ok(())
+            } else {
+                Err(meta.error(format!(
+                    "`CType` cannot be derived for `#[repr({})]` structs",
+                    meta.path.to_token_stream()
+                )))
+            }
+        })?;
+    }
+
+    if repr_c {
+        Ok(())
+    } else {
+        Err(Error::new(
+            Span::call_site(),
+            "`CType` can only be derived for `#[repr(C)]` structs",
+        ))
+    }
+}
@@ -0,0 +1,87 @@
+//! Compares the cost of calling a C function through the different call
+//! paths: [`high::call::call`] prepares a CIF for every call,
+//! [`middle::Cif::call`] reuses a CIF, and [`high::call::PreparedCall`] and
+//! [`middle::CallFrame`] reuse a CIF and the storage of the arguments.
+//!
+//! Run with `cargo bench --bench call`. Set `ITERATIONS` to change how
+//! many calls each path makes.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use libffi::high::call::{self, PreparedCall};
+use libffi::middle::{self, CallFrame, Cif, CodePtr, Type};
+
+extern "C" fn add3(a: i64, b: i32, c: f64) -> f64 {
+    a as f64 + b as f64 + c
+}
+
+fn code_ptr() -> CodePtr {
+    CodePtr(add3 as *mut _)
+}
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut(u32) -> f64) {
+    // Warm up caches and the allocator.
+    for i in 0..iterations / 10 {
+        black_box(f(i));
+    }
+
+    let start = Instant::now();
+    for i in 0..iterations {
+        black_box(f(black_box(i)));
+    }
+    let elapsed = start.elapsed();
+
+    let per_call = elapsed.as_nanos() as f64 / f64::from(iterations);
+    println!(
+        "{name:<28} {per_call:>8.1} ns/call ({:?} total)",
+        round(elapsed)
+    );
+}
+
+fn round(duration: Duration) -> Duration {
+    Duration::from_micros(duration.as_micros() as u64)
+}
+
+fn main() {
+    let iterations = std::env::var("ITERATIONS")
+        .ok()
+        .and_then(|iterations| iterations.parse().ok())
+        .unwrap_or(1_000_000);
+
+    bench("high::call::call", iterations, |i| unsafe {
+        call::call::<f64>(
+            code_ptr(),
+            &[
+                call::arg(&i64::from(i)),
+                call::arg(&2i32),
+                call::arg(&0.5f64),
+            ],
+        )
+    });
+
+    let cif = Cif::new([Type::i64(), Type::i32(), Type::f64()], Type::f64());
+    bench("middle::Cif::call", iterations, |i| unsafe {
+        cif.call::<f64>(
+            code_ptr(),
+            &[
+                middle::arg(&i64::from(i)),
+                middle::arg(&2i32),
+                middle::arg(&0.5f64),
+            ],
+        )
+    });
+
+    let mut prepared = PreparedCall::<(i64, i32, f64), f64>::new(code_ptr());
+    bench("high::call::PreparedCall", iterations, |i| unsafe {
+        prepared.call((i64::from(i), 2, 0.5))
+    });
+
+    let mut frame = CallFrame::new(cif.clone());
+    frame.set(1, 2i32);
+    frame.set(2, 0.5f64);
+    bench("middle::CallFrame", iterations, |i| unsafe {
+        frame.set(0, i64::from(i));
+        frame.call::<f64>(code_ptr())
+    });
+}
@@ -2,8 +2,9 @@
 //! from a shared library at runtime. It loads the C standard library and calls the
 //! `isdigit` function of libc to check if a character is a digit.
 //!
-//! This example uses the `middle` level API because the high-level API does not
-//! currently support this use case.
+//! This example uses the `middle` level API together with libloading. On Unix,
+//! the `dlopen` feature provides `libffi::dylib`, which loads the function and
+//! builds its CIF in one step.
 //!
 //! Note: This example is platform-dependent and works on macOS, Linux, and Windows.
 
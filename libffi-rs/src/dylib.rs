@@ -0,0 +1,258 @@
+//! Loading functions from shared libraries at run time.
+//!
+//! This module is enabled by the `dlopen` feature. It opens libraries with
+//! the system’s `dlopen` and looks up functions with `dlsym`, and returns
+//! handles that combine a function with the CIF it is called through and
+//! keep the library loaded for as long as they exist.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use libffi::dylib::Library;
+//!
+//! let libc = Library::open("libc.so.6").unwrap();
+//! let isdigit = unsafe { libc.function::<(i32,), i32>("isdigit") }.unwrap();
+//!
+//! assert_ne!(0, isdigit.call('1' as i32));
+//! assert_eq!(0, isdigit.call('a' as i32));
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::c_void;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::string::{String, ToString};
+use std::sync::Arc;
+
+use crate::high::{for_each_tuple, CType, CTypeTuple, ForeignFn};
+use crate::middle::{Arg, Cif, CodePtr};
+
+/// An error from opening a library or looking up a symbol, with the
+/// message reported by `dlerror`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    message: String,
+}
+
+impl Error {
+    /// Takes the message of the last `dlopen` or `dlsym` failure, if it
+    /// has not been taken yet.
+    fn last() -> Option<Self> {
+        let message = unsafe { libc::dlerror() };
+        if message.is_null() {
+            return None;
+        }
+
+        let message = unsafe { CStr::from_ptr(message) };
+        Some(Error {
+            message: message.to_string_lossy().into_owned(),
+        })
+    }
+
+    fn nul(what: &str) -> Self {
+        Error {
+            message: std::format!("{what} contains a NUL byte"),
+        }
+    }
+
+    /// Returns the message describing the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug)]
+struct Handle(*mut c_void);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            libc::dlclose(self.0);
+        }
+    }
+}
+
+// The handle is only passed to `dlsym` and `dlclose`, which may be called
+// from any thread.
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+/// A shared library opened with `dlopen`.
+///
+/// Cloning a `Library` is cheap, and the library is closed when the last
+/// clone, and the last function looked up in it, is dropped.
+#[derive(Clone, Debug)]
+pub struct Library {
+    handle: Arc<Handle>,
+}
+
+impl Library {
+    /// Opens the library at `path`, which is searched for as by `dlopen`
+    /// if it contains no slash.
+    ///
+    /// Symbols are resolved when the library is opened, and are not made
+    /// available to libraries opened later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the library cannot be loaded.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|_| Error::nul("the library path"))?;
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(Error::last().unwrap_or_else(|| Error {
+                message: "dlopen failed".to_string(),
+            }));
+        }
+
+        Ok(Library {
+            handle: Arc::new(Handle(handle)),
+        })
+    }
+
+    /// Looks up the address of the symbol `name`.
+    ///
+    /// The address is only valid while the library is open, so prefer
+    /// [`Library::function`] or [`Library::function_dyn`], whose results
+    /// keep it open.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the library does not define `name`.
+    pub fn symbol(&self, name: &str) -> Result<CodePtr, Error> {
+        let name = CString::new(name).map_err(|_| Error::nul("the symbol name"))?;
+        // A symbol may legitimately be null, so failure is detected by
+        // `dlerror`, whose pending message is cleared first.
+        unsafe {
+            libc::dlerror();
+            let symbol = libc::dlsym(self.handle.0, name.as_ptr());
+            match Error::last() {
+                Some(error) if symbol.is_null() => Err(error),
+                _ => Ok(CodePtr(symbol)),
+            }
+        }
+    }
+
+    /// Looks up the function `name`, with argument types `Args` and result
+    /// type `R`, for calls with the default ABI.
+    ///
+    /// # Safety
+    ///
+    /// The symbol `name` must be a function that takes arguments of the
+    /// types `Args` and returns `R`, with the default ABI, as calls through
+    /// the returned [`Function`] are otherwise undefined behavior. The
+    /// function may only be called through the returned `Function`, or
+    /// pointers obtained from it while it exists, as the library may be
+    /// closed once it is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the library does not define `name`.
+    pub unsafe fn function<Args: CTypeTuple, R: CType>(
+        &self,
+        name: &str,
+    ) -> Result<Function<Args, R>, Error> {
+        let fun = self.symbol(name)?;
+        Ok(Function {
+            fun: ForeignFn::new(fun),
+            _library: self.clone(),
+        })
+    }
+
+    /// Looks up the function `name`, to be called through `cif`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the library does not define `name`.
+    pub fn function_dyn(&self, name: &str, cif: Cif) -> Result<DynFunction, Error> {
+        let fun = self.symbol(name)?;
+        Ok(DynFunction {
+            cif,
+            fun,
+            _library: self.clone(),
+        })
+    }
+}
+
+/// A function from a [`Library`] with statically known types, returned by
+/// [`Library::function`].
+///
+/// The library stays loaded while the `Function` exists.
+#[derive(Clone, Debug)]
+pub struct Function<Args, R> {
+    fun: ForeignFn<Args, R>,
+    _library: Library,
+}
+
+macro_rules! impl_function {
+    ($( $T:ident )*) => {
+        impl<$( $T: CType, )* R: CType> Function<($( $T, )*), R> {
+            /// Calls the function with the given arguments.
+            // The parameter names are the type parameter names, as in
+            // `ForeignFn::call`.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn call(&self, $( $T: $T, )*) -> R {
+                self.fun.call($( $T, )*)
+            }
+
+            /// Returns the function as an `extern "C" fn` pointer, or `None`
+            /// if it is called with an ABI other than the default one.
+            ///
+            /// # Safety
+            ///
+            /// The pointer may only be called while this `Function`, or a
+            /// clone of it, exists, as the library may be closed once they
+            /// are dropped.
+            pub unsafe fn as_extern_fn(&self) -> Option<extern "C" fn($( $T ),*) -> R> {
+                self.fun.as_extern_fn()
+            }
+        }
+    };
+}
+
+for_each_tuple!(impl_function);
+
+/// A function from a [`Library`] with a CIF built at run time, returned by
+/// [`Library::function_dyn`].
+///
+/// The library stays loaded while the `DynFunction` exists.
+#[derive(Clone, Debug)]
+pub struct DynFunction {
+    cif: Cif,
+    fun: CodePtr,
+    _library: Library,
+}
+
+impl DynFunction {
+    /// Returns the CIF the function is called through.
+    pub fn cif(&self) -> &Cif {
+        &self.cif
+    }
+
+    /// Returns the address of the function, which is valid while the
+    /// `DynFunction` exists.
+    pub fn code_ptr(&self) -> CodePtr {
+        self.fun
+    }
+
+    /// Calls the function with the given arguments.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Cif::call`], the function must have the signature of the
+    /// CIF, the arguments must match its argument types, and `R` must be
+    /// its result type.
+    pub unsafe fn call<R>(&self, args: &[Arg]) -> R {
+        self.cif.call(self.fun, args)
+    }
+}
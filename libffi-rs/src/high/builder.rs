@@ -0,0 +1,198 @@
+use core::any::Any;
+use core::marker::PhantomData;
+
+use super::{
+    ffi_abi_FFI_DEFAULT_ABI, CType, CTypeTuple, Cif, Closure, ClosureMut, ClosureOnce,
+    ClosureOwned, ClosureSync, FfiAbi, PanicPolicy, TupleFn, TupleFnMut, TupleFnOnce,
+};
+use crate::middle;
+
+/// Provides a builder-style API for constructing typed CIFs and closures.
+///
+/// The argument and result types are the type parameters `Args` and `R`.
+/// The default calling convention is
+/// [`ffi_abi_FFI_DEFAULT_ABI`](crate::low::ffi_abi_FFI_DEFAULT_ABI), and
+/// the default panic policy is [`PanicPolicy::Abort`]. Change them with
+/// [`Builder::abi`] and [`Builder::panic_policy`]. With the `std` feature,
+/// closures can take their allocations from a
+/// [`ClosurePool`](middle::ClosurePool) set with `Builder::pool`.
+///
+/// Once the builder is configured, construct a `Cif` with
+/// [`Builder::into_cif`] or a closure with [`Builder::into_closure`],
+/// [`into_closure_mut`](Builder::into_closure_mut),
+/// [`into_closure_sync`](Builder::into_closure_sync),
+/// [`into_closure_once`](Builder::into_closure_once), or
+/// [`into_closure_owned`](Builder::into_closure_owned).
+///
+/// # Examples
+///
+/// ```
+/// use libffi::high::{Builder, PanicPolicy};
+///
+/// let f = |x: u32, y: u32| x.checked_sub(y).expect("underflow");
+///
+/// let closure = Builder::<(u32, u32), u32>::new()
+///     .panic_policy(PanicPolicy::Return(u32::MAX))
+///     .into_closure(&f);
+/// let sub = closure.code_ptr();
+///
+/// assert_eq!(2, sub.call(5, 3));
+/// assert_eq!(u32::MAX, sub.call(3, 5));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Builder<Args, R> {
+    abi: FfiAbi,
+    panic_policy: PanicPolicy<R>,
+    #[cfg(feature = "std")]
+    pool: Option<middle::ClosurePool>,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<Args, R> Default for Builder<Args, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Args, R> Builder<Args, R> {
+    /// Constructs a `Builder`.
+    pub fn new() -> Self {
+        Self {
+            abi: ffi_abi_FFI_DEFAULT_ABI,
+            panic_policy: PanicPolicy::Abort,
+            #[cfg(feature = "std")]
+            pool: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the calling convention.
+    pub fn abi(mut self, abi: FfiAbi) -> Self {
+        self.abi = abi;
+        self
+    }
+
+    /// Sets what closures do when their Rust callback panics.
+    pub fn panic_policy(mut self, panic_policy: PanicPolicy<R>) -> Self {
+        self.panic_policy = panic_policy;
+        self
+    }
+
+    /// Sets a pool for closures to take their allocations from, and to
+    /// return them to when they are dropped.
+    ///
+    /// This method requires the `std` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::high::Builder;
+    /// use libffi::middle::ClosurePool;
+    ///
+    /// let pool = ClosurePool::new(4);
+    /// let builder = Builder::<(u32,), u32>::new().pool(pool.clone());
+    ///
+    /// for offset in 0..10 {
+    ///     let f = move |x: u32| x + offset;
+    ///     let closure = builder.clone().into_closure(&f);
+    ///     assert_eq!(offset + 5, closure.code_ptr().call(5));
+    /// }
+    ///
+    /// assert_eq!(1, pool.stats().allocated);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn pool(mut self, pool: middle::ClosurePool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+}
+
+impl<Args: CTypeTuple, R: CType> Builder<Args, R> {
+    /// Builds a CIF.
+    pub fn into_cif(self) -> Cif<Args, R> {
+        self.cif()
+    }
+
+    fn cif(&self) -> Cif<Args, R> {
+        Cif::from_middle(middle::Cif::new_with_abi(
+            Args::reify_types(),
+            R::reify().into_middle(),
+            self.abi,
+        ))
+    }
+
+    /// Builds an immutable closure.
+    pub fn into_closure<'a, Callback>(self, callback: &'a Callback) -> Closure<'a, Args, R>
+    where
+        Callback: TupleFn<Args, R>,
+        R: 'a,
+    {
+        let cif = self.cif();
+        #[cfg(feature = "std")]
+        if let Some(pool) = &self.pool {
+            return Closure::new_in_pool(cif, callback, self.panic_policy, pool);
+        }
+        Closure::new_with_cif_and_panic_policy(cif, callback, self.panic_policy)
+    }
+
+    /// Builds a mutable closure.
+    pub fn into_closure_mut<'a, Callback>(
+        self,
+        callback: &'a mut Callback,
+    ) -> ClosureMut<'a, Args, R>
+    where
+        Callback: TupleFnMut<Args, R>,
+        R: 'a,
+    {
+        let cif = self.cif();
+        #[cfg(feature = "std")]
+        if let Some(pool) = &self.pool {
+            return ClosureMut::new_in_pool(cif, callback, self.panic_policy, pool);
+        }
+        ClosureMut::new_with_cif_and_panic_policy(cif, callback, self.panic_policy)
+    }
+
+    /// Builds an immutable closure whose `Sync` Rust closure can be called
+    /// from other threads.
+    pub fn into_closure_sync<'a, Callback>(self, callback: &'a Callback) -> ClosureSync<'a, Args, R>
+    where
+        Callback: TupleFn<Args, R> + Sync,
+        R: 'a,
+    {
+        let cif = self.cif();
+        #[cfg(feature = "std")]
+        if let Some(pool) = &self.pool {
+            return ClosureSync::new_in_pool(cif, callback, self.panic_policy, pool);
+        }
+        ClosureSync::new_with_cif_and_panic_policy(cif, callback, self.panic_policy)
+    }
+}
+
+impl<Args: CTypeTuple, R: CType + Any> Builder<Args, R> {
+    /// Builds a one-shot closure.
+    pub fn into_closure_once<Callback>(self, callback: Callback) -> ClosureOnce<Args, R>
+    where
+        Callback: TupleFnOnce<Args, R> + Any,
+    {
+        let cif = self.cif();
+        #[cfg(feature = "std")]
+        if let Some(pool) = &self.pool {
+            return ClosureOnce::new_in_pool(cif, callback, self.panic_policy, pool);
+        }
+        ClosureOnce::new_with_cif_and_panic_policy(cif, callback, self.panic_policy)
+    }
+
+    /// Builds an owned closure.
+    pub fn into_closure_owned<Callback>(self, callback: Callback) -> ClosureOwned<Args, R>
+    where
+        Callback: TupleFn<Args, R> + Send + Sync + 'static,
+        R: Send + Sync,
+    {
+        let cif = self.cif();
+        #[cfg(feature = "std")]
+        if let Some(pool) = &self.pool {
+            return ClosureOwned::new_in_pool(cif, callback, self.panic_policy, pool);
+        }
+        ClosureOwned::new_with_cif_and_panic_policy(cif, callback, self.panic_policy)
+    }
+}
@@ -16,10 +16,33 @@
 //!
 //! assert!((result - 5f32).abs() < 0.0001);
 //! ```
+//!
+//! Variadic functions are called with [`call_variadic`], or by
+//! separating the fixed arguments from the variadic ones with a `;` in
+//! [`ffi_call!`](crate::ffi_call!):
+//!
+//! ```
+//! use core::ffi::{c_char, c_int};
+//! use libffi::ffi_call;
+//!
+//! extern "C" {
+//!     fn snprintf(s: *mut c_char, n: usize, format: *const c_char, ...) -> c_int;
+//! }
+//!
+//! let mut buffer = [0 as c_char; 16];
+//! let format = c"%d/%.1f";
+//! let len = unsafe {
+//!     ffi_call!{ snprintf(buffer.as_mut_ptr(), buffer.len(), format.as_ptr(); 7u8, 0.5f32) -> c_int }
+//! };
+//!
+//! assert_eq!(5, len);
+//! ```
 
 use core::convert::TryInto;
+use core::ffi::c_int;
+use core::marker::PhantomData;
 
-use crate::middle;
+use crate::{middle, raw};
 pub use middle::CodePtr;
 
 /// Encapsulates an argument with its type information.
@@ -54,7 +77,7 @@ pub fn arg<T: super::CType>(arg: &T) -> Arg<'_> {
 
 /// Performs a dynamic call to a C function.
 ///
-/// To reduce boilerplate, see [`ffi_call!`].
+/// To reduce boilerplate, see [`ffi_call!`](crate::ffi_call!).
 ///
 /// # Examples
 ///
@@ -91,6 +114,209 @@ pub unsafe fn call<R: super::CType>(fun: CodePtr, args: &[Arg]) -> R {
         .unwrap()
 }
 
+/// A call to a C function, prepared once to be made repeatedly without
+/// allocating.
+///
+/// [`fn@call`] prepares a CIF and an array of argument pointers for every
+/// call. A `PreparedCall` prepares them when it is created, in a
+/// [`middle::CallFrame`], and its [`call`](PreparedCall::call) method only
+/// copies the arguments into the frame before calling the function.
+///
+/// The argument types are given as a tuple, as for [`Cif`](super::Cif).
+///
+/// # Examples
+///
+/// ```
+/// extern "C" fn hypot(x: f32, y: f32) -> f32 {
+///     (x * x + y * y).sqrt()
+/// }
+///
+/// use libffi::high::call::*;
+///
+/// let mut hypot = PreparedCall::<(f32, f32), f32>::new(CodePtr(hypot as *mut _));
+///
+/// let result = unsafe { hypot.call((3.0, 4.0)) };
+/// assert!((result - 5f32).abs() < 0.0001);
+/// let result = unsafe { hypot.call((5.0, 12.0)) };
+/// assert!((result - 13f32).abs() < 0.0001);
+/// ```
+#[derive(Debug)]
+pub struct PreparedCall<Args, R> {
+    frame: middle::CallFrame,
+    fun: CodePtr,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<Args: super::CTypeTuple, R: super::CType> PreparedCall<Args, R> {
+    /// Prepares calls to `fun`, reifying the argument and result types.
+    pub fn new(fun: CodePtr) -> Self {
+        Self::with_cif(super::Cif::reify(), fun)
+    }
+
+    /// Prepares calls to `fun` through `cif`, such as one created for a
+    /// non-default ABI.
+    pub fn with_cif(cif: super::Cif<Args, R>, fun: CodePtr) -> Self {
+        PreparedCall {
+            frame: middle::CallFrame::new(cif.untyped),
+            fun,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Calls the function with the elements of `args` as its arguments.
+    ///
+    /// # Safety
+    /// The signature of the function pointer must match the types of the
+    /// arguments and the return type. If the types do not match, we get UB.
+    pub unsafe fn call(&mut self, args: Args) -> R {
+        args.store_args(&mut self.frame);
+        // See `call` for why the result is read as `R::RetType`.
+        self.frame
+            .call::<R::RetType>(self.fun)
+            .try_into()
+            .ok()
+            .unwrap()
+    }
+}
+
+/// Types that can be passed as variadic arguments.
+///
+/// C applies the *default argument promotions* to the variadic arguments
+/// of a call: integer types narrower than `int` are passed as `int`, and
+/// `float` is passed as `double`. [`VarArg::promote`] performs the same
+/// conversion in Rust, which is how [`ffi_call!`](crate::ffi_call!)
+/// passes variadic arguments. Types that cannot be passed to a variadic
+/// function, such as `()`, do not implement this trait and are rejected
+/// at compile time.
+pub trait VarArg: super::CType {
+    /// The type this type is promoted to when passed as a variadic
+    /// argument.
+    type Promoted: super::CType;
+
+    /// Converts the value to its promoted type.
+    fn promote(self) -> Self::Promoted;
+}
+
+macro_rules! impl_var_arg {
+    ($( $type_:ty => $promoted:ty ),* $(,)?) => {
+        $(
+            impl VarArg for $type_ {
+                type Promoted = $promoted;
+
+                fn promote(self) -> $promoted {
+                    self.into()
+                }
+            }
+        )*
+    };
+}
+
+impl_var_arg! {
+    i8 => c_int,
+    u8 => c_int,
+    i16 => c_int,
+    u16 => c_int,
+    i32 => i32,
+    u32 => u32,
+    i64 => i64,
+    u64 => u64,
+    isize => isize,
+    usize => usize,
+    f32 => f64,
+    f64 => f64,
+}
+
+impl<T> VarArg for *const T {
+    type Promoted = Self;
+
+    fn promote(self) -> Self {
+        self
+    }
+}
+
+impl<T> VarArg for *mut T {
+    type Promoted = Self;
+
+    fn promote(self) -> Self {
+        self
+    }
+}
+
+/// Performs a dynamic call to a variadic C function.
+///
+/// The `fixed` arguments correspond to the named parameters of the
+/// function, and the `variadic` arguments to the `...` part of its
+/// signature. The variadic arguments must already have undergone C’s
+/// default argument promotions (see [`VarArg`]);
+/// [`ffi_call!`](crate::ffi_call!) takes care of that automatically.
+///
+/// # Panics
+///
+/// Panics if any variadic argument has a type that C would have promoted,
+/// such as `u8` or `f32`.
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_char, c_int};
+/// use libffi::high::call::*;
+///
+/// extern "C" {
+///     fn snprintf(s: *mut c_char, n: usize, format: *const c_char, ...) -> c_int;
+/// }
+///
+/// let mut buffer = [0 as c_char; 16];
+/// let format = c"%s=%d";
+/// let len = unsafe {
+///     call_variadic::<c_int>(
+///         CodePtr(snprintf as *mut _),
+///         &[arg(&buffer.as_mut_ptr()), arg(&buffer.len()), arg(&format.as_ptr())],
+///         &[arg(&c"x".as_ptr()), arg(&42)],
+///     )
+/// };
+///
+/// assert_eq!(4, len);
+/// ```
+///
+/// # Safety
+/// The signature of the function pointer must match the types of the fixed
+/// arguments and the return type, and the function must accept the given
+/// variadic arguments. If the types do not match, we get UB.
+pub unsafe fn call_variadic<R: super::CType>(fun: CodePtr, fixed: &[Arg], variadic: &[Arg]) -> R {
+    for (index, arg) in variadic.iter().enumerate() {
+        let type_tag = (*arg.type_.as_raw_ptr()).type_;
+        assert!(
+            !matches!(
+                type_tag,
+                raw::FFI_TYPE_SINT8
+                    | raw::FFI_TYPE_UINT8
+                    | raw::FFI_TYPE_SINT16
+                    | raw::FFI_TYPE_UINT16
+                    | raw::FFI_TYPE_FLOAT
+            ),
+            "call_variadic: variadic argument {index} has not been promoted"
+        );
+    }
+
+    let types = fixed
+        .iter()
+        .chain(variadic)
+        .map(|arg| arg.type_.clone())
+        .collect::<alloc::vec::Vec<_>>();
+    let cif = middle::Cif::new_variadic(types, fixed.len(), R::reify().into_middle());
+
+    let values = fixed
+        .iter()
+        .chain(variadic)
+        .map(|arg| arg.value.clone())
+        .collect::<alloc::vec::Vec<_>>();
+    // See `call` for why the result is read as `R::RetType`.
+    cif.call::<R::RetType>(fun, &values)
+        .try_into()
+        .ok()
+        .unwrap()
+}
+
 /// Performs a dynamic call to a C function.
 ///
 /// This macro provides sugar for [`high::arg`](crate::high::arg) and
@@ -110,9 +336,52 @@ pub unsafe fn call<R: super::CType>(fun: CodePtr, args: &[Arg]) -> R {
 ///
 /// assert!((result - 5f32).abs() < 0.0001);
 /// ```
+///
+/// To call a variadic function, separate the fixed arguments from the
+/// variadic ones with a `;`. The variadic arguments are promoted with
+/// [`VarArg::promote`](crate::high::call::VarArg::promote) and passed to
+/// [`call_variadic`](crate::high::call_variadic):
+///
+/// ```
+/// use core::ffi::{c_char, c_int};
+/// use libffi::ffi_call;
+///
+/// extern "C" {
+///     fn snprintf(s: *mut c_char, n: usize, format: *const c_char, ...) -> c_int;
+/// }
+///
+/// let mut buffer = [0 as c_char; 16];
+/// let format = c"%c%c";
+/// let len = unsafe {
+///     ffi_call!{ snprintf(buffer.as_mut_ptr(), buffer.len(), format.as_ptr(); b'o', b'k') -> c_int }
+/// };
+///
+/// assert_eq!(2, len);
+/// ```
 #[macro_export]
 macro_rules! ffi_call {
 
+    { ( $fun:expr ) ( $( $arg:expr ),* ; $( $var:expr ),* ) -> $ty:ty }
+    =>
+    {
+        $crate::high::call::call_variadic::<$ty>(
+            $crate::high::call::CodePtr($fun as *mut _),
+            &[$($crate::high::call::arg(&$arg)),*],
+            &[$($crate::high::call::arg(&$crate::high::call::VarArg::promote($var))),*])
+    };
+
+    { $fun:ident ( $( $arg:expr ),* ; $( $var:expr ),* ) -> $ty:ty }
+    =>
+    { ffi_call!{ ($fun)($($arg),* ; $($var),*) -> $ty } };
+
+    { ( $fun:expr ) ( $( $arg:expr ),* ; $( $var:expr ),* ) }
+    =>
+    { ffi_call!{ ($fun)($($arg),* ; $($var),*) -> () } };
+
+    { $fun:ident ( $( $arg:expr ),* ; $( $var:expr ),* ) }
+    =>
+    { ffi_call!{ ($fun)($($arg),* ; $($var),*) -> () } };
+
     { ( $fun:expr ) ( $( $arg:expr ),* ) -> $ty:ty }
     =>
     {
@@ -134,3 +403,102 @@ macro_rules! ffi_call {
     { ffi_call!{ ($fun)($($arg),*) -> () } };
 
 }
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use core::ffi::c_char;
+    use std::ffi::CStr;
+
+    extern "C" {
+        fn snprintf(s: *mut c_char, n: usize, format: *const c_char, ...) -> c_int;
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot pass foreign function pointers through libffi"
+    )]
+    fn ffi_call_variadic_promotes_arguments() {
+        let mut buffer = [0 as c_char; 64];
+        let format = c"%d %d %u %.2f %s";
+
+        let len = unsafe {
+            ffi_call! {
+                snprintf(buffer.as_mut_ptr(), buffer.len(), format.as_ptr();
+                         -5i8, 300u16, 7u32, 2.25f32, c"end".as_ptr()) -> c_int
+            }
+        };
+
+        let expected = c"-5 300 7 2.25 end";
+        let output = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+        assert_eq!(expected, output);
+        assert_eq!(expected.to_bytes().len(), len as usize);
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot pass foreign function pointers through libffi"
+    )]
+    fn call_variadic_without_variadic_arguments() {
+        let mut buffer = [0 as c_char; 8];
+        let format = c"plain";
+
+        let len: c_int = unsafe {
+            call_variadic(
+                CodePtr(snprintf as *mut _),
+                &[
+                    arg(&buffer.as_mut_ptr()),
+                    arg(&buffer.len()),
+                    arg(&format.as_ptr()),
+                ],
+                &[],
+            )
+        };
+
+        assert_eq!(5, len);
+        assert_eq!(format, unsafe { CStr::from_ptr(buffer.as_ptr()) });
+    }
+
+    extern "C" fn mix(a: u8, b: i16, c: f64) -> i16 {
+        a as i16 * b + c as i16
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn prepared_call() {
+        let mut prepared = PreparedCall::<(u8, i16, f64), i16>::new(CodePtr(mix as *mut _));
+
+        for a in 0..4u8 {
+            let expected =
+                unsafe { call::<i16>(CodePtr(mix as *mut _), &[arg(&a), arg(&-3i16), arg(&2.5)]) };
+            assert_eq!(expected, unsafe { prepared.call((a, -3, 2.5)) });
+        }
+
+        let mut nullary = PreparedCall::<(), c_int>::with_cif(
+            crate::high::Cif::reify(),
+            CodePtr(seven as *mut _),
+        );
+        assert_eq!(7, unsafe { nullary.call(()) });
+    }
+
+    extern "C" fn seven() -> c_int {
+        7
+    }
+
+    #[test]
+    #[should_panic(expected = "variadic argument 1 has not been promoted")]
+    fn call_variadic_rejects_unpromoted_arguments() {
+        unsafe {
+            call_variadic::<c_int>(
+                CodePtr(snprintf as *mut _),
+                &[],
+                &[arg(&1i32), arg(&1.0f32)],
+            );
+        }
+    }
+}
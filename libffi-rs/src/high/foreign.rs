@@ -0,0 +1,221 @@
+//! Typed wrappers for calling C function pointers through libffi.
+
+use core::convert::TryInto;
+use core::ffi::c_void;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+
+use super::{CType, CTypeTuple, CodePtr, FfiAbi};
+use crate::{low, middle};
+
+/// A C function pointer with statically known argument and result types,
+/// called through a CIF prepared once.
+///
+/// The argument types are given as a tuple, so
+/// `ForeignFn<(u32, f64), u64>` calls a C function of type
+/// `uint64_t (*)(uint32_t, double)`. Unlike [`ffi_call!`](crate::ffi_call!),
+/// which prepares a CIF for every call, a `ForeignFn` reifies its CIF when
+/// it is created, and its `call` method only builds an array of argument
+/// pointers on the stack. The ABI can be chosen with
+/// [`ForeignFn::with_abi`], which is where a `ForeignFn` is more flexible
+/// than an `extern "C" fn` pointer.
+///
+/// # Examples
+///
+/// ```
+/// use libffi::high::{CodePtr, ForeignFn};
+///
+/// extern "C" fn hypot(x: f32, y: f32) -> f32 {
+///     (x * x + y * y).sqrt()
+/// }
+///
+/// let hypot = unsafe { ForeignFn::<(f32, f32), f32>::new(CodePtr(hypot as *mut _)) };
+///
+/// assert!((hypot.call(3.0, 4.0) - 5.0).abs() < 0.0001);
+/// assert!((hypot.call(5.0, 12.0) - 13.0).abs() < 0.0001);
+///
+/// let native: extern "C" fn(f32, f32) -> f32 = hypot.as_extern_fn().unwrap();
+/// assert!((native(6.0, 8.0) - 10.0).abs() < 0.0001);
+/// ```
+pub struct ForeignFn<Args, R> {
+    cif: middle::Cif,
+    fun: CodePtr,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<Args: CTypeTuple, R: CType> ForeignFn<Args, R> {
+    /// Wraps `fun` for calls with the default ABI.
+    ///
+    /// # Safety
+    ///
+    /// `fun` must point to a C function with the argument types `Args` and
+    /// the result type `R`, that remains valid for as long as the
+    /// `ForeignFn` or its clones are called.
+    pub unsafe fn new(fun: CodePtr) -> Self {
+        Self::with_abi(fun, middle::ffi_abi_FFI_DEFAULT_ABI)
+    }
+
+    /// Wraps `fun` for calls with the given ABI.
+    ///
+    /// # Safety
+    ///
+    /// `fun` must point to a function with the argument types `Args` and
+    /// the result type `R` following the calling convention `abi`, that
+    /// remains valid for as long as the `ForeignFn` or its clones are
+    /// called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if libffi does not support `abi` for these types.
+    pub unsafe fn with_abi(fun: CodePtr, abi: FfiAbi) -> Self {
+        let cif = middle::Cif::new_with_abi(Args::reify_types(), R::reify().into_middle(), abi);
+        ForeignFn {
+            cif,
+            fun,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Args, R> ForeignFn<Args, R> {
+    /// Returns the wrapped code pointer.
+    pub fn code_ptr(&self) -> CodePtr {
+        self.fun
+    }
+
+    /// Returns the ABI the function is called with.
+    pub fn abi(&self) -> FfiAbi {
+        self.cif.abi()
+    }
+
+    /// Returns the CIF the function is called through.
+    pub fn cif(&self) -> &middle::Cif {
+        &self.cif
+    }
+}
+
+impl<Args, R> Clone for ForeignFn<Args, R> {
+    fn clone(&self) -> Self {
+        ForeignFn {
+            cif: self.cif.clone(),
+            fun: self.fun,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Args, R> fmt::Debug for ForeignFn<Args, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ForeignFn")
+            .field("cif", &self.cif)
+            .field("fun", &self.fun)
+            .finish()
+    }
+}
+
+// A `ForeignFn` is a code pointer and a CIF, neither of which is written
+// by a call; `new` requires the code to remain callable.
+unsafe impl<Args, R> Send for ForeignFn<Args, R> {}
+unsafe impl<Args, R> Sync for ForeignFn<Args, R> {}
+
+macro_rules! impl_foreign_fn {
+    ($( $T:ident )*) => {
+        impl<$( $T: CType, )* R: CType> ForeignFn<($( $T, )*), R> {
+            /// Calls the function with the given arguments.
+            // The parameter names are the type parameter names, as in
+            // `FnPtr::call`.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn call(&self, $( $T: $T, )*) -> R {
+                let mut args = [$( &$T as *const $T as *mut c_void ),*];
+                // Safety: `new` requires `fun` to have this signature. If
+                // `R` is a small integer type, libffi extends it, so the
+                // result is read as `R::RetType`, as in `high::call::call`.
+                let result = unsafe {
+                    low::call::<R::RetType>(self.cif.as_raw_ptr(), self.fun, args.as_mut_ptr())
+                };
+                result.try_into().ok().unwrap()
+            }
+
+            /// Returns the function as an `extern "C" fn` pointer, or `None`
+            /// if it is called with an ABI other than the default one,
+            /// which is the one `extern "C"` denotes.
+            pub fn as_extern_fn(&self) -> Option<extern "C" fn($( $T ),*) -> R> {
+                if self.cif.abi() != middle::ffi_abi_FFI_DEFAULT_ABI {
+                    return None;
+                }
+
+                // Safety: `new` requires `fun` to have this signature.
+                Some(unsafe { mem::transmute::<*mut c_void, extern "C" fn($( $T ),*) -> R>(self.fun.0) })
+            }
+        }
+    };
+}
+
+for_each_tuple!(impl_foreign_fn);
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    extern "C" fn weigh(tag: u8, value: f64, scale: i16) -> i16 {
+        (tag as f64 * value) as i16 * scale
+    }
+
+    extern "C" fn answer() -> u64 {
+        42
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn call_foreign_fn() {
+        let weigh = unsafe { ForeignFn::<(u8, f64, i16), i16>::new(CodePtr(weigh as *mut _)) };
+        assert_eq!(-30, weigh.call(2, 5.0, -3));
+        assert_eq!(-30, weigh.clone().call(2, 5.0, -3));
+        assert_eq!(-30, weigh.as_extern_fn().unwrap()(2, 5.0, -3));
+
+        let answer = unsafe { ForeignFn::<(), u64>::new(CodePtr(answer as *mut _)) };
+        assert_eq!(42, answer.call());
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| assert_eq!(42, answer.call()));
+        });
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", unix))]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn call_foreign_fn_with_abi() {
+        extern "sysv64" fn sysv(x: i32, y: i32) -> i32 {
+            x - y
+        }
+        extern "win64" fn win64(x: i32, y: i32) -> i32 {
+            x - y
+        }
+
+        let sysv = unsafe {
+            ForeignFn::<(i32, i32), i32>::with_abi(
+                CodePtr(sysv as *mut _),
+                crate::raw::ffi_abi_FFI_UNIX64,
+            )
+        };
+        assert_eq!(3, sysv.call(5, 2));
+        assert!(sysv.as_extern_fn().is_some());
+
+        let win64 = unsafe {
+            ForeignFn::<(i32, i32), i32>::with_abi(
+                CodePtr(win64 as *mut _),
+                crate::raw::ffi_abi_FFI_WIN64,
+            )
+        };
+        assert_eq!(crate::raw::ffi_abi_FFI_WIN64, win64.abi());
+        assert_eq!(3, win64.call(5, 2));
+        assert!(win64.as_extern_fn().is_none());
+    }
+}
@@ -1,27 +1,36 @@
 //! High layer providing automatic marshalling of Rust closures
 //! as C function pointers.
 //!
-//! The main facility here is given by the structs
-//! <code>Closure<em>N</em></code>,
-//! <code>Closure<span></span>Mut<em>N</em></code>,
-//! and <code>Closure<span></span>Once<em>N</em></code>,
-//! for natural numbers *`N`*
-//! from `0` to `12` (as of
-//! now). These represent C closures of *`N`* arguments, which can be
+//! The main facility here is given by the generic structs [`Closure`],
+//! [`ClosureMut`] and [`ClosureOnce`], whose argument types are given as a
+//! tuple of up to 32 [`CType`]s. These represent C closures, which can be
 //! used to turn Rust lambdas (or in generally, anything that implements
 //! `Fn` or `FnMut`) into ordinary C function pointers. For example, a
 //! Rust value of type `Fn(u32, u32) -> u64` can be turned into a
-//! closure of type [`Closure2<u32, u32, u64>`] using
-//! [`Closure2::new`]. Then a C
+//! closure of type `Closure<(u32, u32), u64>` using
+//! [`Closure::new`]. Then a C
 //! function pointer of type `extern "C" fn(u32, u32) -> u64` can be
 //! borrowed from the closure and passed to C.
 //!
+//! [`ClosureOwned`] owns a `Send + Sync` Rust closure and can be handed
+//! to C indefinitely; see [`ClosureOwned::into_raw`].
+//! [`ClosureSync`] borrows a `Sync` Rust closure and, unlike [`Closure`],
+//! can be shared with other threads that call it.
+//!
+//! For arities from `0` to `12` there are also aliases such as
+//! <code>Closure<em>N</em></code>,
+//! <code>Closure<span></span>Sync<em>N</em></code>,
+//! <code>Closure<span></span>Mut<em>N</em></code>,
+//! and <code>Closure<span></span>Once<em>N</em></code>, so that
+//! [`Closure2<u32, u32, u64>`] is the same type as
+//! `Closure<(u32, u32), u64>`.
+//!
 //! The above usage case eliminates much of the boilerplate involved in
 //! creating a closure as compared to the `middle` and `low` layers, but
 //! at the price of flexibility. Some flexibility can be recovered by
 //! manually constructing and configuring a CIF (*e.g.,* a
 //! [`Cif2`]) and then creating the closure with
-//! [`Closure2::new_with_cif`].
+//! [`Closure::new_with_cif`].
 //!
 //! See the [`mod@call`] submodule for a simple interface
 //! to dynamic calls to C functions.
@@ -67,8 +76,37 @@
 //! ```
 //!
 //! Invoking the closure a second time will panic.
+//!
+//! <code>Closure<span></span>Variadic<em>N</em></code> turns a Rust lambda
+//! into a C function with *`N`* fixed arguments followed by variadic
+//! arguments, which the lambda reads from a [`middle::VarArgs`]:
+//!
+//! ```
+//! use core::ffi::c_int;
+//!
+//! use libffi::high::{ClosureVariadic1, CType};
+//! use libffi::middle::VarArgs;
+//!
+//! let sum = |count: c_int, mut var_args: VarArgs<'_>| -> f64 {
+//!     (0..count).map(|_| unsafe { var_args.next_arg::<f64>().unwrap() }).sum()
+//! };
+//!
+//! let variadic = [f64::reify().into_middle(), f64::reify().into_middle()];
+//! let closure = ClosureVariadic1::new(variadic, &sum);
+//! let sum: &unsafe extern "C" fn(c_int, ...) -> f64 =
+//!     unsafe { closure.instantiate_code_ptr() };
+//!
+//! assert_eq!(3.75, unsafe { sum(2, 1.5f64, 2.25f64) });
+//! ```
+
+use alloc::boxed::Box;
+use core::any::Any;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::{mem, ptr};
 
 pub use crate::middle::{ffi_abi_FFI_DEFAULT_ABI, FfiAbi};
+use crate::{low, middle};
 
 pub mod types;
 pub use types::{CType, Type};
@@ -125,373 +163,1200 @@ macro_rules! abort_on_panic {
     }};
 }
 
+/// Invokes `$m!` with every prefix of the type parameter list `T1 … T32`,
+/// from the empty one up to the full list.
+macro_rules! for_each_tuple {
+    ($m:ident) => {
+        for_each_tuple!(@ $m; [];
+            T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14 T15 T16
+            T17 T18 T19 T20 T21 T22 T23 T24 T25 T26 T27 T28 T29 T30 T31 T32);
+    };
+    (@ $m:ident; [$( $done:ident )*]; ) => {
+        $m!($( $done )*);
+    };
+    (@ $m:ident; [$( $done:ident )*]; $next:ident $( $rest:ident )*) => {
+        $m!($( $done )*);
+        for_each_tuple!(@ $m; [$( $done )* $next]; $( $rest )*);
+    };
+}
+
+#[cfg(all(feature = "dlopen", unix))]
+pub(crate) use for_each_tuple;
+
+mod tuple;
+pub use tuple::{CTypeTuple, TupleFn, TupleFnMut, TupleFnOnce, TupleFnVariadic};
+
+mod panic;
+#[cfg(feature = "std")]
+pub use panic::take_callback_panic;
+use panic::Guarded;
+pub use panic::PanicPolicy;
+
+mod builder;
+pub use builder::Builder;
+
+mod foreign;
+pub use foreign::ForeignFn;
+
+/// Implemented by every type, so that closures can own userdata of a type
+/// they do not name.
+trait Erased {}
+
+impl<T: ?Sized> Erased for T {}
+
+/// A typed CIF, which statically tracks argument and result types.
+///
+/// The argument types are given as a tuple, so `Cif<(u32, f64), u64>`
+/// describes the C function type `uint64_t (*)(uint32_t, double)`.
+pub struct Cif<Args, R> {
+    untyped: middle::Cif,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<Args: CTypeTuple, R: CType> Cif<Args, R> {
+    /// Creates a new statically-typed CIF by reifying the
+    /// argument types as `Type<T>`s.
+    pub fn reify() -> Self {
+        Self::from_middle(middle::Cif::new(
+            Args::reify_types(),
+            R::reify().into_middle(),
+        ))
+    }
+
+    /// Creates a new statically-typed variadic CIF by reifying the fixed
+    /// argument types. `variadic` lists the types of the variadic
+    /// arguments that follow them.
+    pub fn reify_variadic<Variadic>(variadic: Variadic) -> Self
+    where
+        Variadic: IntoIterator<Item = middle::Type>,
+    {
+        let mut args = Args::reify_types();
+        args.extend(variadic);
+        Self::from_middle(middle::Cif::new_variadic(
+            args,
+            Args::ARITY,
+            R::reify().into_middle(),
+        ))
+    }
+}
+
+impl<Args, R> Cif<Args, R> {
+    pub(crate) fn from_middle(untyped: middle::Cif) -> Self {
+        Cif {
+            untyped,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A lifetime carrying wrapper type for [`fn`] pointers.
+///
+/// `FnPtr<'a, (A, B), R>` has the same representation as
+/// `extern "C" fn(A, B) -> R`, and can be called with its `call` method.
+#[repr(transparent)]
+pub struct FnPtr<'a, Args, R> {
+    func: unsafe extern "C" fn(),
+    _lifetime: PhantomData<&'a fn(Args) -> R>,
+}
+
+impl<Args, R> Clone for FnPtr<'_, Args, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Args, R> Copy for FnPtr<'_, Args, R> {}
+
+macro_rules! impl_arity {
+    ($( $T:ident )*) => {
+        impl<$( $T, )* R> Cif<($( $T, )*), R> {
+            /// Creates a new statically-typed CIF with the given argument
+            /// and result types.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn new($( $T: Type<$T>, )* result: Type<R>) -> Self {
+                Self::new_with_abi($( $T, )* result, ffi_abi_FFI_DEFAULT_ABI)
+            }
+
+            /// Creates a new statically-typed CIF with the given argument
+            /// and result types for the specified ABI.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn new_with_abi($( $T: Type<$T>, )* result: Type<R>, abi: FfiAbi) -> Self {
+                Self::from_middle(middle::Cif::new_with_abi(
+                    alloc::vec![$( $T.into_middle() ),*],
+                    result.into_middle(),
+                    abi,
+                ))
+            }
+
+            /// Creates a new statically-typed variadic CIF. The given
+            /// argument types are the fixed arguments, and `variadic`
+            /// lists the types of the variadic arguments that follow
+            /// them.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn new_variadic<Variadic>(
+                $( $T: Type<$T>, )*
+                variadic: Variadic,
+                result: Type<R>,
+            ) -> Self
+            where
+                Variadic: IntoIterator<Item = middle::Type>,
+            {
+                Self::new_variadic_with_abi($( $T, )* variadic, result, ffi_abi_FFI_DEFAULT_ABI)
+            }
+
+            /// Creates a new statically-typed variadic CIF for the
+            /// specified ABI.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn new_variadic_with_abi<Variadic>(
+                $( $T: Type<$T>, )*
+                variadic: Variadic,
+                result: Type<R>,
+                abi: FfiAbi,
+            ) -> Self
+            where
+                Variadic: IntoIterator<Item = middle::Type>,
+            {
+                let mut args = alloc::vec![$( $T.into_middle() ),*];
+                let fixed_args = args.len();
+                args.extend(variadic);
+                Self::from_middle(middle::Cif::new_variadic_with_abi(
+                    args,
+                    fixed_args,
+                    result.into_middle(),
+                    abi,
+                ))
+            }
+        }
+
+        impl<$( $T, )* R> FnPtr<'_, ($( $T, )*), R> {
+            /// Call the wrapped [`fn`] pointer.
+            // We allow non snake case variable identifiers here because
+            // the parameter names are the type parameter names, which are
+            // entirely meaningless.
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            pub fn call(&self, $( $T: $T, )*) -> R {
+                // Safety: an `FnPtr` is only ever created from a code
+                // pointer of this type.
+                let func: extern "C" fn($( $T, )*) -> R = unsafe { mem::transmute(self.func) };
+                func($( $T, )*)
+            }
+        }
+    };
+}
+
+for_each_tuple!(impl_arity);
+
+/// The type of function called from an immutable, typed closure.
+///
+/// For `Args = (A, B)` this is
+/// `extern "C" fn(&low::ffi_cif, &mut R, &(&A, &B), &U)`.
+pub type Callback<U, Args, R> = <Args as CTypeTuple>::Callback<U, R>;
+
+/// The type of function called from a mutable, typed closure.
+pub type CallbackMut<U, Args, R> = <Args as CTypeTuple>::CallbackMut<U, R>;
+
+/// The type of function called from a one-shot, typed closure.
+pub type CallbackOnce<U, Args, R> = CallbackMut<Option<U>, Args, R>;
+
+/// An immutable, typed closure with the given argument and result
+/// types.
+pub struct Closure<'a, Args, R> {
+    untyped: middle::Closure<'a>,
+    _userdata: Box<dyn Erased + 'a>,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<'a, Args: CTypeTuple, R: CType + 'a> Closure<'a, Args, R> {
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure.
+    pub fn new<Callback>(callback: &'a Callback) -> Self
+    where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        Self::new_with_cif(Cif::reify(), callback)
+    }
+
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure, which handles panics in the Rust closure
+    /// according to `panic_policy`.
+    pub fn new_with_panic_policy<Callback>(
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        Self::new_with_cif_and_panic_policy(Cif::reify(), callback, panic_policy)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function and the Rust closure to call.
+    pub fn new_with_cif<Callback>(cif: Cif<Args, R>, callback: &'a Callback) -> Self
+    where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        Self::new_with_cif_and_panic_policy(cif, callback, PanicPolicy::Abort)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, the Rust closure to call, and the policy for
+    /// panics in the Rust closure.
+    pub fn new_with_cif_and_panic_policy<Callback>(
+        cif: Cif<Args, R>,
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        Self::build(callback, panic_policy, |callback, userdata| {
+            middle::Closure::new(cif.untyped, callback, userdata)
+        })
+    }
+
+    /// Constructs a typed closure as by
+    /// [`Closure::new_with_cif_and_panic_policy`], taking its allocation
+    /// from `pool`.
+    #[cfg(feature = "std")]
+    fn new_in_pool<Callback>(
+        cif: Cif<Args, R>,
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+        pool: &middle::ClosurePool,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        Self::build(callback, panic_policy, |callback, userdata| {
+            pool.closure(cif.untyped, callback, userdata)
+                .expect("low::prep_closure")
+        })
+    }
+
+    /// Boxes the Rust closure with its panic policy, and makes the untyped
+    /// closure that calls it with `make`.
+    fn build<Callback>(
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+        make: impl FnOnce(
+            middle::Callback<Guarded<&'a Callback, R>, R::RetType>,
+            &'a Guarded<&'a Callback, R>,
+        ) -> middle::Closure<'a>,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        let userdata = Box::new(Guarded {
+            callback,
+            policy: panic_policy,
+        });
+        // Safety: the userdata is boxed, so it does not move, and it is
+        // dropped only after the closure that refers to it.
+        let borrow = unsafe { &*(&*userdata as *const Guarded<&'a Callback, R>) };
+        Closure {
+            untyped: make(Self::static_callback, borrow),
+            _userdata: userdata,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, a callback for the function to call, and
+    /// userdata to pass to the callback.  Note that the return
+    /// type of the callback must follow the libffi implicit
+    /// extension rules.
+    pub fn from_parts<U>(
+        cif: Cif<Args, R>,
+        callback: Callback<U, Args, R::RetType>,
+        userdata: &'a U,
+    ) -> Self {
+        Closure {
+            untyped: middle::Closure::new(
+                cif.untyped,
+                Args::into_middle_callback(callback),
+                userdata,
+            ),
+            _userdata: Box::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replaces the Rust closure to call, keeping the code pointer.
+    ///
+    /// See [`ClosureMut::replace`].
+    ///
+    /// # Safety
+    ///
+    /// As for [`middle::Closure::rebind`]: no call through the code
+    /// pointer may be running or start while the Rust closure is replaced.
+    pub unsafe fn replace<Callback>(&mut self, callback: &'a Callback)
+    where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        self.replace_with_panic_policy(callback, PanicPolicy::Abort)
+    }
+
+    /// Replaces the Rust closure to call and the policy for panics in it,
+    /// keeping the code pointer.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Closure::replace`].
+    pub unsafe fn replace_with_panic_policy<Callback>(
+        &mut self,
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+    ) where
+        Callback: TupleFn<Args, R> + 'a,
+    {
+        let userdata = Box::new(Guarded {
+            callback,
+            policy: panic_policy,
+        });
+        // Safety: as in `new_with_cif_and_panic_policy`.
+        let borrow = &*(&*userdata as *const Guarded<&'a Callback, R>);
+        self.untyped.rebind(Self::static_callback, borrow);
+        // The closure no longer refers to the previous userdata.
+        self._userdata = userdata;
+    }
+
+    unsafe extern "C" fn static_callback<Callback>(
+        _cif: &low::ffi_cif,
+        result: &mut R::RetType,
+        args: *const *const c_void,
+        userdata: &Guarded<&Callback, R>,
+    ) where
+        Callback: TupleFn<Args, R>,
+    {
+        let args = Args::read_args(args);
+        let value = userdata.policy.call(|| userdata.callback.call_tuple(args));
+        ptr::write(result, value.into());
+    }
+}
+
+impl<'a, Args, R> Closure<'a, Args, R> {
+    /// Gets the C code pointer that is used to invoke the
+    /// closure.
+    pub fn code_ptr(&self) -> &FnPtr<'a, Args, R> {
+        // Safety: Here we produce an FnPtr wrapper for
+        // the correct `fn` pointer, which is repr(transparent)
+        // and therefore reference, layout, and otherwise ABI compatible
+        // with that type.
+        // Additionally, the FnPtr wrapper enforces usage of the returned
+        // function pointer be only within the lifetime of the closure
+        // from which it was made.
+        // Other safety invariants have not been checked by
+        // the author of this comment, see the `instantiate_code_ptr`
+        // method docs for more.
+        unsafe { self.untyped.instantiate_code_ptr() }
+    }
+}
+
+/// A mutable, typed closure with the given argument and
+/// result types.
+pub struct ClosureMut<'a, Args, R> {
+    untyped: middle::Closure<'a>,
+    _userdata: Box<dyn Erased + 'a>,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<'a, Args: CTypeTuple, R: CType + 'a> ClosureMut<'a, Args, R> {
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure.
+    pub fn new<Callback>(callback: &'a mut Callback) -> Self
+    where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        Self::new_with_cif(Cif::reify(), callback)
+    }
+
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure, which handles panics in the Rust closure
+    /// according to `panic_policy`.
+    pub fn new_with_panic_policy<Callback>(
+        callback: &'a mut Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        Self::new_with_cif_and_panic_policy(Cif::reify(), callback, panic_policy)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function and the Rust closure to call.
+    pub fn new_with_cif<Callback>(cif: Cif<Args, R>, callback: &'a mut Callback) -> Self
+    where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        Self::new_with_cif_and_panic_policy(cif, callback, PanicPolicy::Abort)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, the Rust closure to call, and the policy for
+    /// panics in the Rust closure.
+    pub fn new_with_cif_and_panic_policy<Callback>(
+        cif: Cif<Args, R>,
+        callback: &'a mut Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        Self::build(callback, panic_policy, |callback, userdata| {
+            middle::Closure::new_mut(cif.untyped, callback, userdata)
+        })
+    }
+
+    /// Constructs a typed closure as by
+    /// [`ClosureMut::new_with_cif_and_panic_policy`], taking its allocation
+    /// from `pool`.
+    #[cfg(feature = "std")]
+    fn new_in_pool<Callback>(
+        cif: Cif<Args, R>,
+        callback: &'a mut Callback,
+        panic_policy: PanicPolicy<R>,
+        pool: &middle::ClosurePool,
+    ) -> Self
+    where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        Self::build(callback, panic_policy, |callback, userdata| {
+            pool.closure_mut(cif.untyped, callback, userdata)
+                .expect("low::prep_closure_mut")
+        })
+    }
+
+    /// Boxes the Rust closure with its panic policy, and makes the untyped
+    /// closure that calls it with `make`.
+    fn build<Callback>(
+        callback: &'a mut Callback,
+        panic_policy: PanicPolicy<R>,
+        make: impl FnOnce(
+            middle::CallbackMut<Guarded<&'a mut Callback, R>, R::RetType>,
+            &'a mut Guarded<&'a mut Callback, R>,
+        ) -> middle::Closure<'a>,
+    ) -> Self
+    where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        let mut userdata = Box::new(Guarded {
+            callback,
+            policy: panic_policy,
+        });
+        // Safety: the userdata is boxed, so it does not move, and it is
+        // dropped only after the closure that refers to it.
+        let borrow = unsafe { &mut *(&mut *userdata as *mut Guarded<&'a mut Callback, R>) };
+        ClosureMut {
+            untyped: make(Self::static_callback, borrow),
+            _userdata: userdata,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, a callback for the function to call, and
+    /// userdata to pass to the callback.  Note that the return
+    /// type of the callback must follow the libffi implicit
+    /// extension rules.
+    pub fn from_parts<U>(
+        cif: Cif<Args, R>,
+        callback: CallbackMut<U, Args, R::RetType>,
+        userdata: &'a mut U,
+    ) -> Self {
+        ClosureMut {
+            untyped: middle::Closure::new_mut(
+                cif.untyped,
+                Args::into_middle_callback_mut(callback),
+                userdata,
+            ),
+            _userdata: Box::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replaces the Rust closure to call, keeping the code pointer.
+    ///
+    /// C code that holds the code pointer calls `callback` from then on,
+    /// which suits libraries that only let a callback be registered once.
+    ///
+    /// # Safety
+    ///
+    /// No call through the code pointer may be running, or start on
+    /// another thread, while the Rust closure is replaced. The code pointer
+    /// is `Copy` and `Send`, so the borrow checker cannot rule this out;
+    /// see [`middle::Closure::rebind`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::high::ClosureMut1;
+    ///
+    /// let mut calls = 0;
+    /// let mut count = |x: u32| { calls += 1; x };
+    /// let mut double = |x: u32| x * 2;
+    ///
+    /// let mut closure = ClosureMut1::new(&mut count);
+    /// let fun = *closure.code_ptr();
+    /// assert_eq!(5, fun.call(5));
+    ///
+    /// unsafe { closure.replace(&mut double) };
+    /// assert_eq!(10, fun.call(5));
+    ///
+    /// drop(closure);
+    /// assert_eq!(1, calls);
+    /// ```
+    pub unsafe fn replace<Callback>(&mut self, callback: &'a mut Callback)
+    where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        self.replace_with_panic_policy(callback, PanicPolicy::Abort)
+    }
+
+    /// Replaces the Rust closure to call and the policy for panics in it,
+    /// keeping the code pointer.
+    ///
+    /// # Safety
+    ///
+    /// As for [`ClosureMut::replace`].
+    pub unsafe fn replace_with_panic_policy<Callback>(
+        &mut self,
+        callback: &'a mut Callback,
+        panic_policy: PanicPolicy<R>,
+    ) where
+        Callback: TupleFnMut<Args, R> + 'a,
+    {
+        let mut userdata = Box::new(Guarded {
+            callback,
+            policy: panic_policy,
+        });
+        // Safety: as in `new_with_cif_and_panic_policy`.
+        let borrow = &mut *(&mut *userdata as *mut Guarded<&'a mut Callback, R>);
+        self.untyped.rebind_mut(Self::static_callback, borrow);
+        // The closure no longer refers to the previous userdata.
+        self._userdata = userdata;
+    }
+
+    unsafe extern "C" fn static_callback<Callback>(
+        _cif: &low::ffi_cif,
+        result: &mut R::RetType,
+        args: *const *const c_void,
+        userdata: &mut Guarded<&mut Callback, R>,
+    ) where
+        Callback: TupleFnMut<Args, R>,
+    {
+        let args = Args::read_args(args);
+        let Guarded { callback, policy } = userdata;
+        let value = policy.call(|| callback.call_tuple_mut(args));
+        ptr::write(result, value.into());
+    }
+}
+
+impl<'a, Args, R> ClosureMut<'a, Args, R> {
+    /// Gets the C code pointer that is used to invoke the
+    /// closure.
+    pub fn code_ptr(&self) -> &FnPtr<'a, Args, R> {
+        unsafe { self.untyped.instantiate_code_ptr() }
+    }
+}
+
+/// An immutable, typed closure with the given argument and result
+/// types, whose `Sync` Rust closure can be called from other threads.
+///
+/// A [`Closure`] is neither `Send` nor `Sync`, as it does not know
+/// whether its Rust closure can be called from other threads. A
+/// `ClosureSync` only borrows `Sync` Rust closures, and is both when its
+/// result type is, so that its code pointer can be shared with a thread
+/// pool.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::{AtomicU32, Ordering};
+///
+/// use libffi::high::ClosureSync1;
+///
+/// let calls = AtomicU32::new(0);
+/// let f = |x: u32| {
+///     calls.fetch_add(1, Ordering::Relaxed);
+///     x * 2
+/// };
+/// let closure = ClosureSync1::new(&f);
+///
+/// std::thread::scope(|scope| {
+///     for x in 0..4 {
+///         let closure = &closure;
+///         scope.spawn(move || assert_eq!(x * 2, closure.code_ptr().call(x)));
+///     }
+/// });
+/// assert_eq!(4, calls.load(Ordering::Relaxed));
+/// ```
+pub struct ClosureSync<'a, Args, R> {
+    untyped: Closure<'a, Args, R>,
+}
+
+// The Rust closure is `Sync`, and is only called through shared
+// references. The panic policy may hold a value of `R`, which is returned
+// by calls on any thread.
+unsafe impl<Args, R: Send> Send for ClosureSync<'_, Args, R> {}
+unsafe impl<Args, R: Sync> Sync for ClosureSync<'_, Args, R> {}
+
+impl<'a, Args: CTypeTuple, R: CType + 'a> ClosureSync<'a, Args, R> {
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure.
+    pub fn new<Callback>(callback: &'a Callback) -> Self
+    where
+        Callback: TupleFn<Args, R> + Sync + 'a,
+    {
+        Self::new_with_cif(Cif::reify(), callback)
+    }
+
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure, which handles panics in the Rust closure
+    /// according to `panic_policy`.
+    pub fn new_with_panic_policy<Callback>(
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + Sync + 'a,
+    {
+        Self::new_with_cif_and_panic_policy(Cif::reify(), callback, panic_policy)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function and the Rust closure to call.
+    pub fn new_with_cif<Callback>(cif: Cif<Args, R>, callback: &'a Callback) -> Self
+    where
+        Callback: TupleFn<Args, R> + Sync + 'a,
+    {
+        Self::new_with_cif_and_panic_policy(cif, callback, PanicPolicy::Abort)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, the Rust closure to call, and the policy for
+    /// panics in the Rust closure.
+    pub fn new_with_cif_and_panic_policy<Callback>(
+        cif: Cif<Args, R>,
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + Sync + 'a,
+    {
+        ClosureSync {
+            untyped: Closure::new_with_cif_and_panic_policy(cif, callback, panic_policy),
+        }
+    }
+
+    /// Constructs a typed closure as by
+    /// [`ClosureSync::new_with_cif_and_panic_policy`], taking its
+    /// allocation from `pool`.
+    #[cfg(feature = "std")]
+    fn new_in_pool<Callback>(
+        cif: Cif<Args, R>,
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+        pool: &middle::ClosurePool,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + Sync + 'a,
+    {
+        ClosureSync {
+            untyped: Closure::new_in_pool(cif, callback, panic_policy, pool),
+        }
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, a callback for the function to call, and
+    /// `Sync` userdata to pass to the callback.  Note that the
+    /// return type of the callback must follow the libffi
+    /// implicit extension rules.
+    pub fn from_parts<U: Sync>(
+        cif: Cif<Args, R>,
+        callback: Callback<U, Args, R::RetType>,
+        userdata: &'a U,
+    ) -> Self {
+        ClosureSync {
+            untyped: Closure::from_parts(cif, callback, userdata),
+        }
+    }
+}
+
+impl<'a, Args, R> ClosureSync<'a, Args, R> {
+    /// Gets the C code pointer that is used to invoke the
+    /// closure.
+    pub fn code_ptr(&self) -> &FnPtr<'a, Args, R> {
+        self.untyped.code_ptr()
+    }
+}
+
+/// A one-shot, typed closure with the given argument and
+/// result types.
+pub struct ClosureOnce<Args, R> {
+    untyped: middle::ClosureOnce,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<Args: CTypeTuple, R: CType + Any> ClosureOnce<Args, R> {
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure.
+    pub fn new<Callback>(callback: Callback) -> Self
+    where
+        Callback: TupleFnOnce<Args, R> + Any,
+    {
+        Self::new_with_cif(Cif::reify(), callback)
+    }
+
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure, which handles panics in the Rust closure
+    /// and calls after the first one according to `panic_policy`.
+    pub fn new_with_panic_policy<Callback>(callback: Callback, panic_policy: PanicPolicy<R>) -> Self
+    where
+        Callback: TupleFnOnce<Args, R> + Any,
+    {
+        Self::new_with_cif_and_panic_policy(Cif::reify(), callback, panic_policy)
+    }
+
+    /// Constructs a one-shot closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function and the Rust closure to call.
+    pub fn new_with_cif<Callback>(cif: Cif<Args, R>, callback: Callback) -> Self
+    where
+        Callback: TupleFnOnce<Args, R> + Any,
+    {
+        Self::new_with_cif_and_panic_policy(cif, callback, PanicPolicy::Abort)
+    }
+
+    /// Constructs a one-shot closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, the Rust closure to call, and the policy for
+    /// panics in the Rust closure and for calls after the first
+    /// one.
+    pub fn new_with_cif_and_panic_policy<Callback>(
+        cif: Cif<Args, R>,
+        callback: Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFnOnce<Args, R> + Any,
+    {
+        let userdata = Guarded {
+            callback: Some(callback),
+            policy: panic_policy,
+        };
+        ClosureOnce {
+            untyped: middle::ClosureOnce::new(cif.untyped, Self::static_callback, userdata),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Constructs a one-shot closure as by
+    /// [`ClosureOnce::new_with_cif_and_panic_policy`], taking its
+    /// allocation from `pool`.
+    #[cfg(feature = "std")]
+    fn new_in_pool<Callback>(
+        cif: Cif<Args, R>,
+        callback: Callback,
+        panic_policy: PanicPolicy<R>,
+        pool: &middle::ClosurePool,
+    ) -> Self
+    where
+        Callback: TupleFnOnce<Args, R> + Any,
+    {
+        let userdata = Guarded {
+            callback: Some(callback),
+            policy: panic_policy,
+        };
+        ClosureOnce {
+            untyped: pool
+                .closure_once(cif.untyped, Self::static_callback, userdata)
+                .expect("low::prep_closure_mut"),
+            _marker: PhantomData,
+        }
+    }
+
+    unsafe extern "C" fn static_callback<Callback>(
+        _cif: &low::ffi_cif,
+        result: &mut R::RetType,
+        args: *const *const c_void,
+        userdata: &mut Option<Guarded<Option<Callback>, R>>,
+    ) where
+        Callback: TupleFnOnce<Args, R>,
+    {
+        // The middle layer's `Option` is always `Some`, since we never
+        // take the userdata out of it.
+        let Some(Guarded { callback, policy }) = userdata else {
+            unreachable!()
+        };
+        let value = match callback.take() {
+            Some(callback) => {
+                let args = Args::read_args(args);
+                policy.call(|| callback.call_tuple_once(args))
+            }
+            None => policy.already_called(),
+        };
+        ptr::write(result, value.into());
+    }
+}
+
+impl<Args: CTypeTuple, R: CType> ClosureOnce<Args, R> {
+    /// Constructs a one-shot closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, a callback for the function to call, and
+    /// userdata to pass to the callback.  Note that the return
+    /// type of the callback must follow the libffi implicit
+    /// extension rules.
+    pub fn from_parts<U: Any>(
+        cif: Cif<Args, R>,
+        callback: CallbackOnce<U, Args, R::RetType>,
+        userdata: U,
+    ) -> Self {
+        ClosureOnce {
+            untyped: middle::ClosureOnce::new(
+                cif.untyped,
+                Args::into_middle_callback_mut(callback),
+                userdata,
+            ),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Args, R> ClosureOnce<Args, R> {
+    /// Gets the C code pointer that is used to invoke the
+    /// closure.
+    pub fn code_ptr(&self) -> &FnPtr<'_, Args, R> {
+        unsafe { self.untyped.instantiate_code_ptr() }
+    }
+}
+
+/// A typed closure with the given argument and result types, which owns
+/// its `Send + Sync` Rust closure.
+///
+/// A `ClosureOwned` can be called any number of times and, because it
+/// borrows nothing, can be given to C for as long as needed: convert it
+/// into a function pointer and an opaque handle with
+/// [`ClosureOwned::into_raw`], and free it later by passing the handle to
+/// [`ClosureOwned::from_raw`].
+///
+/// # Examples
+///
+/// ```
+/// use libffi::high::ClosureOwned1;
+///
+/// let offset = 10u32;
+/// let closure = ClosureOwned1::new(move |x: u32| x + offset);
+/// let (add, handle) = closure.into_raw();
+///
+/// assert_eq!(15, add.call(5));
+///
+/// // Frees the closure and the Rust closure it owns.
+/// drop(unsafe { ClosureOwned1::<u32, u32>::from_raw(handle) });
+/// ```
+pub struct ClosureOwned<Args, R> {
+    untyped: middle::ClosureOwned,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<Args: CTypeTuple, R: CType + Any + Send + Sync> ClosureOwned<Args, R> {
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure.
+    pub fn new<Callback>(callback: Callback) -> Self
+    where
+        Callback: TupleFn<Args, R> + Send + Sync + 'static,
+    {
+        Self::new_with_cif(Cif::reify(), callback)
+    }
+
+    /// Constructs a typed closure callable from C from a
+    /// Rust closure, which handles panics in the Rust closure
+    /// according to `panic_policy`.
+    pub fn new_with_panic_policy<Callback>(callback: Callback, panic_policy: PanicPolicy<R>) -> Self
+    where
+        Callback: TupleFn<Args, R> + Send + Sync + 'static,
+    {
+        Self::new_with_cif_and_panic_policy(Cif::reify(), callback, panic_policy)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function and the Rust closure to call.
+    pub fn new_with_cif<Callback>(cif: Cif<Args, R>, callback: Callback) -> Self
+    where
+        Callback: TupleFn<Args, R> + Send + Sync + 'static,
+    {
+        Self::new_with_cif_and_panic_policy(cif, callback, PanicPolicy::Abort)
+    }
+
+    /// Constructs a typed closure callable from C from a CIF
+    /// describing the calling convention for the resulting
+    /// function, the Rust closure to call, and the policy for
+    /// panics in the Rust closure.
+    pub fn new_with_cif_and_panic_policy<Callback>(
+        cif: Cif<Args, R>,
+        callback: Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + Send + Sync + 'static,
+    {
+        let userdata = Guarded {
+            callback,
+            policy: panic_policy,
+        };
+        ClosureOwned {
+            untyped: middle::ClosureOwned::new(cif.untyped, Self::static_callback, userdata),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Constructs a typed closure as by
+    /// [`ClosureOwned::new_with_cif_and_panic_policy`], taking its
+    /// allocation from `pool`.
+    #[cfg(feature = "std")]
+    fn new_in_pool<Callback>(
+        cif: Cif<Args, R>,
+        callback: Callback,
+        panic_policy: PanicPolicy<R>,
+        pool: &middle::ClosurePool,
+    ) -> Self
+    where
+        Callback: TupleFn<Args, R> + Send + Sync + 'static,
+    {
+        let userdata = Guarded {
+            callback,
+            policy: panic_policy,
+        };
+        ClosureOwned {
+            untyped: pool
+                .closure_owned(cif.untyped, Self::static_callback, userdata)
+                .expect("low::prep_closure"),
+            _marker: PhantomData,
+        }
+    }
+
+    unsafe extern "C" fn static_callback<Callback>(
+        _cif: &low::ffi_cif,
+        result: &mut R::RetType,
+        args: *const *const c_void,
+        userdata: &Guarded<Callback, R>,
+    ) where
+        Callback: TupleFn<Args, R>,
+    {
+        let args = Args::read_args(args);
+        let value = userdata.policy.call(|| userdata.callback.call_tuple(args));
+        ptr::write(result, value.into());
+    }
+}
+
+impl<Args, R> ClosureOwned<Args, R> {
+    /// Gets the C code pointer that is used to invoke the
+    /// closure.
+    pub fn code_ptr(&self) -> &FnPtr<'_, Args, R> {
+        unsafe { self.untyped.instantiate_code_ptr() }
+    }
+
+    /// Consumes the closure, returning its function pointer and an
+    /// opaque handle to it.
+    ///
+    /// The function pointer stays valid until the handle is passed to
+    /// [`ClosureOwned::from_raw`] and the resulting closure is dropped,
+    /// which is why it is returned with a `'static` lifetime. If that
+    /// never happens, the closure is leaked.
+    pub fn into_raw(self) -> (FnPtr<'static, Args, R>, *mut c_void) {
+        let (code, handle) = self.untyped.into_raw();
+        let fn_ptr = FnPtr {
+            // Safety: `code` is the code pointer of a closure, which
+            // has the type of a function pointer.
+            func: unsafe { mem::transmute::<*mut c_void, unsafe extern "C" fn()>(code.0) },
+            _lifetime: PhantomData,
+        };
+        (fn_ptr, handle)
+    }
+
+    /// Reclaims a closure from a handle returned by
+    /// [`ClosureOwned::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been returned by [`ClosureOwned::into_raw`] on
+    /// a closure with the same argument and result types, and must not be
+    /// passed to this function more than once. Once the returned closure
+    /// is dropped, the function pointer returned by `into_raw` must not be
+    /// called anymore.
+    pub unsafe fn from_raw(handle: *mut c_void) -> Self {
+        ClosureOwned {
+            untyped: middle::ClosureOwned::from_raw(handle),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// An immutable, typed closure with the given fixed argument
+/// and result types, which is called with variadic arguments.
+///
+/// The callback receives the fixed arguments by value and the
+/// variadic arguments as [`middle::VarArgs`]. The types of the
+/// variadic arguments are fixed when the closure is created, and
+/// the closure must only be called with arguments of those types.
+pub struct ClosureVariadic<'a, Args, R> {
+    untyped: middle::Closure<'a>,
+    _userdata: Box<dyn Erased + 'a>,
+    _marker: PhantomData<fn(Args) -> R>,
+}
+
+impl<'a, Args: CTypeTuple, R: CType + 'a> ClosureVariadic<'a, Args, R> {
+    /// Constructs a typed variadic closure callable from C from a
+    /// Rust closure and the types of the variadic arguments.
+    pub fn new<Variadic, Callback>(variadic: Variadic, callback: &'a Callback) -> Self
+    where
+        Variadic: IntoIterator<Item = middle::Type>,
+        Callback: TupleFnVariadic<Args, R> + 'a,
+    {
+        Self::new_with_cif(Cif::reify_variadic(variadic), callback)
+    }
+
+    /// Constructs a typed variadic closure callable from C from
+    /// a CIF built with [`Cif::new_variadic`] and the Rust closure
+    /// to call.
+    pub fn new_with_cif<Callback>(cif: Cif<Args, R>, callback: &'a Callback) -> Self
+    where
+        Callback: TupleFnVariadic<Args, R> + 'a,
+    {
+        Self::new_with_cif_and_panic_policy(cif, callback, PanicPolicy::Abort)
+    }
+
+    /// Constructs a typed variadic closure callable from C from
+    /// a CIF built with [`Cif::new_variadic`], the Rust closure
+    /// to call, and the policy for panics in the Rust closure.
+    pub fn new_with_cif_and_panic_policy<Callback>(
+        cif: Cif<Args, R>,
+        callback: &'a Callback,
+        panic_policy: PanicPolicy<R>,
+    ) -> Self
+    where
+        Callback: TupleFnVariadic<Args, R> + 'a,
+    {
+        let userdata = Box::new(Guarded {
+            callback,
+            policy: panic_policy,
+        });
+        // Safety: the userdata is boxed, so it does not move, and it is
+        // dropped only after the closure that refers to it.
+        let borrow = unsafe { &*(&*userdata as *const Guarded<&'a Callback, R>) };
+        ClosureVariadic {
+            untyped: middle::Closure::new(cif.untyped, Self::static_callback, borrow),
+            _userdata: userdata,
+            _marker: PhantomData,
+        }
+    }
+
+    unsafe extern "C" fn static_callback<Callback>(
+        cif: &low::ffi_cif,
+        result: &mut R::RetType,
+        args: *const *const c_void,
+        userdata: &Guarded<&Callback, R>,
+    ) where
+        Callback: TupleFnVariadic<Args, R>,
+    {
+        let fixed = Args::read_args(args);
+        let var_args = middle::VarArgs::new(cif, args, Args::ARITY);
+        let value = userdata
+            .policy
+            .call(|| userdata.callback.call_tuple_variadic(fixed, var_args));
+        ptr::write(result, value.into());
+    }
+}
+
+impl<Args, R> ClosureVariadic<'_, Args, R> {
+    /// Gets the C code pointer that is used to invoke the
+    /// closure.
+    ///
+    /// Rust cannot name the type of a variadic function with no
+    /// fixed arguments, so unlike the fixed-arity closures this
+    /// returns an untyped pointer. Transmute it to the variadic
+    /// function type with [`ClosureVariadic::instantiate_code_ptr`].
+    pub fn code_ptr(&self) -> &unsafe extern "C" fn() {
+        self.untyped.code_ptr()
+    }
+
+    /// Transmutes the callable code pointer for the closure to a
+    /// reference to any type, such as
+    /// `unsafe extern "C" fn(*const c_char, ...) -> c_int`.
+    ///
+    /// # Safety
+    ///
+    /// The type is not checked. If it does not match the fixed
+    /// arguments, the variadic argument types and the result
+    /// type of the closure, undefined behavior will result.
+    pub unsafe fn instantiate_code_ptr<Fun>(&self) -> &Fun {
+        self.untyped.instantiate_code_ptr()
+    }
+}
+
 macro_rules! define_closure_mod {
     (
         $module:ident $cif:ident $fnptr:ident
           $callback:ident $callback_mut:ident $callback_once:ident
-          $closure:ident $closure_mut:ident $closure_once:ident;
+          $closure:ident $closure_sync:ident $closure_mut:ident $closure_once:ident
+          $closure_owned:ident $closure_variadic:ident;
         $( $T:ident )*
     )
         =>
     {
-        /// CIF and closure types organized by function arity.
-        #[allow(clippy::too_many_arguments)]
+        /// Aliases for the CIF and closure types of one function arity.
         pub mod $module {
-            use core::any::Any;
-            use core::marker::PhantomData;
-            use core::{mem, ptr};
-
             use super::*;
-            use crate::{low, middle};
 
             /// A typed CIF, which statically tracks argument and result types.
-            pub struct $cif<$( $T, )* R> {
-                untyped: middle::Cif,
-                _marker: PhantomData<fn($( $T, )*) -> R>,
-            }
-
-            impl<$( $T, )* R> $cif<$( $T, )* R> {
-                /// Creates a new statically-typed CIF with the given argument
-                /// and result types.
-                #[allow(non_snake_case)]
-                pub fn new($( $T: Type<$T>, )* result: Type<R>) -> Self {
-                    Self::new_with_abi($($T, )* result, ffi_abi_FFI_DEFAULT_ABI)
-                }
-
-                /// Creates a new statically-typed CIF with the given argument
-                /// and result types for the specified ABI.
-                #[allow(non_snake_case)]
-                pub fn new_with_abi($( $T: Type<$T>, )* result: Type<R>, abi: FfiAbi) -> Self {
-                    let cif = middle::Cif::new_with_abi(
-                        alloc::vec![$( $T.into_middle() ),*].into_iter(),
-                        result.into_middle(),
-                        abi,
-                    );
-                    $cif { untyped: cif, _marker: PhantomData }
-                }
-            }
-
-            impl<$( $T: CType, )* R: CType> $cif<$( $T, )* R> {
-                /// Creates a new statically-typed CIF by reifying the
-                /// argument types as `Type<T>`s.
-                pub fn reify() -> Self {
-                    Self::new($( $T::reify(), )* R::reify())
-                }
-            }
+            pub type $cif<$( $T, )* R> = Cif<($( $T, )*), R>;
 
             /// A lifetime carrying wrapper type for [`fn`] pointers.
-            #[derive(Clone, Copy)]
-            #[repr(transparent)]
-            pub struct $fnptr<'a, $( $T, )* R> {
-                func: extern "C" fn($( $T, )*) -> R,
-                _lifetime: PhantomData<&'a extern "C" fn($( $T, )*) -> R>,
-            }
-            impl<'a, $( $T, )* R> $fnptr<'a, $( $T, )* R> {
-                /// Call the wrapped [`fn`] pointer.
-                // We allow non snake case variable identifiers here because
-                // we would otherwise need to take in a whole new list of
-                // argument identifiers at every invocation of this macro,
-                // and there would be no gain from doing so, since the parameter
-                // names here are entirely meaningless.
-                #[allow(non_snake_case)]
-                pub fn call(&self, $( $T : $T, )*) -> R {
-                    (self.func)($( $T, )*)
-                }
-            }
-
-            // We use tuples of pointers to describe the arguments, and we
-            // extract them by pattern matching. This assumes that a tuple
-            // of pointers will be laid out packed and in order. This seems
-            // to hold true right now, and I can’t think of a reason why it
-            // wouldn’t be that way, but technically it may be undefined
-            // behavior.
+            pub type $fnptr<'a, $( $T, )* R> = FnPtr<'a, ($( $T, )*), R>;
 
             /// The type of function called from an immutable, typed closure.
-            pub type $callback<U, $( $T, )* R>
-                = extern "C" fn(cif:      &low::ffi_cif,
-                                result:   &mut R,
-                                args:     &($( &$T, )*),
-                                userdata: &U);
+            pub type $callback<U, $( $T, )* R> = Callback<U, ($( $T, )*), R>;
 
             /// An immutable, typed closure with the given argument and result
             /// types.
-            pub struct $closure<'a, $( $T, )* R> {
-                untyped: middle::Closure<'a>,
-                _marker: PhantomData<fn($( $T, )*) -> R>,
-            }
-
-            impl<'a, $($T: CType,)* R: CType> $closure<'a, $($T,)* R> {
-                /// Constructs a typed closure callable from C from a
-                /// Rust closure.
-                pub fn new<Callback>(callback: &'a Callback) -> Self
-                    where Callback: Fn($( $T, )*) -> R + 'a
-                {
-                    Self::new_with_cif($cif::reify(), callback)
-                }
-            }
+            pub type $closure<'a, $( $T, )* R> = Closure<'a, ($( $T, )*), R>;
 
-            impl<'a, $( $T, )* R: CType> $closure<'a, $( $T, )* R> {
-                /// Gets the C code pointer that is used to invoke the
-                /// closure.
-                pub fn code_ptr(&self) -> & $fnptr <'a, $( $T, )* R> {
-                    // Safety: Here we produce an FnPtrN wrapper for
-                    // the correct `fn` pointer, which is repr(transparent)
-                    // and therefore reference, layout, and otherwise ABI compatible
-                    // with that type.
-                    // Additionally, the FnPtrN wrapper enforces usage of the returned
-                    // function pointer be only within the lifetime of the closure
-                    // from which it was made.
-                    // Other safety invariants have not been checked by
-                    // the author of this comment, see the `instantiate_code_ptr`
-                    // method docs for more.
-                    unsafe {
-                        self.untyped.instantiate_code_ptr()
-                    }
-                }
-
-                /// Constructs a typed closure callable from C from a CIF
-                /// describing the calling convention for the resulting
-                /// function, a callback for the function to call, and
-                /// userdata to pass to the callback.  Note that the return
-                /// type of the callback must follow the libffi implicit
-                /// extension rules.
-                pub fn from_parts<U>(cif: $cif<$( $T, )* R>,
-                                     callback: $callback<U, $( $T, )* R::RetType>,
-                                     userdata: &'a U) -> Self
-                {
-                    let callback: middle::Callback<U, R::RetType>
-                        = unsafe { mem::transmute(callback) };
-                    let closure
-                        = middle::Closure::new(cif.untyped,
-                                               callback,
-                                               userdata);
-                    $closure {
-                        untyped: closure,
-                        _marker: PhantomData,
-                    }
-                }
-            }
-
-            impl<'a, $( $T: Copy, )* R: CType> $closure<'a, $( $T, )* R> {
-                /// Constructs a typed closure callable from C from a CIF
-                /// describing the calling convention for the resulting
-                /// function and the Rust closure to call.
-                pub fn new_with_cif<Callback>(cif: $cif<$( $T, )* R>,
-                                              callback: &'a Callback) -> Self
-                    where Callback: Fn($( $T, )*) -> R + 'a
-                {
-                    Self::from_parts(cif,
-                                     Self::static_callback,
-                                     callback)
-                }
-
-                #[allow(non_snake_case)]
-                extern "C" fn static_callback<Callback>
-                    (_cif:     &low::ffi_cif,
-                     result:   &mut R::RetType,
-                     &($( &$T, )*):
-                               &($( &$T, )*),
-                     userdata: &Callback)
-                  where Callback: Fn($( $T, )*) -> R + 'a
-                {
-                    abort_on_panic!("Cannot panic inside FFI callback", {
-                        unsafe {
-                            ptr::write(result, userdata($( $T, )*).into());
-                        }
-                    });
-                }
-            }
+            /// An immutable, typed closure with the given argument and result
+            /// types, whose `Sync` Rust closure can be called from other
+            /// threads.
+            pub type $closure_sync<'a, $( $T, )* R> = ClosureSync<'a, ($( $T, )*), R>;
 
             /// The type of function called from a mutable, typed closure.
-            pub type $callback_mut<U, $( $T, )* R>
-                = extern "C" fn(cif:      &low::ffi_cif,
-                                result:   &mut R,
-                                args:     &($( &$T, )*),
-                                userdata: &mut U);
+            pub type $callback_mut<U, $( $T, )* R> = CallbackMut<U, ($( $T, )*), R>;
 
             /// A mutable, typed closure with the given argument and
             /// result types.
-            pub struct $closure_mut<'a, $( $T, )* R> {
-                untyped: middle::Closure<'a>,
-                _marker: PhantomData<fn($( $T, )*) -> R>,
-            }
-
-            impl<'a, $($T: CType,)* R: CType>
-                $closure_mut<'a, $($T,)* R>
-            {
-                /// Constructs a typed closure callable from C from a
-                /// Rust closure.
-                pub fn new<Callback>(callback: &'a mut Callback) -> Self
-                    where Callback: FnMut($( $T, )*) -> R + 'a
-                {
-                    Self::new_with_cif($cif::reify(), callback)
-                }
-            }
-
-            impl<'a, $( $T, )* R: CType> $closure_mut<'a, $( $T, )* R> {
-                /// Gets the C code pointer that is used to invoke the
-                /// closure.
-                pub fn code_ptr(&self) -> & $fnptr <'a, $( $T, )* R> {
-                    unsafe {
-                        self.untyped.instantiate_code_ptr()
-                    }
-                }
-
-                /// Constructs a typed closure callable from C from a CIF
-                /// describing the calling convention for the resulting
-                /// function, a callback for the function to call, and
-                /// userdata to pass to the callback.  Note that the return
-                /// type of the callback must follow the libffi implicit
-                /// extension rules.
-                pub fn from_parts<U>(cif:      $cif<$( $T, )* R>,
-                                     callback: $callback_mut<U, $( $T, )* R::RetType>,
-                                     userdata: &'a mut U) -> Self
-                {
-                    let callback: middle::CallbackMut<U, R::RetType>
-                        = unsafe { mem::transmute(callback) };
-                    let closure
-                        = middle::Closure::new_mut(cif.untyped,
-                                                   callback,
-                                                   userdata);
-                    $closure_mut {
-                        untyped: closure,
-                        _marker: PhantomData,
-                    }
-                }
-            }
-
-            impl<'a, $( $T: Copy, )* R: CType> $closure_mut<'a, $( $T, )* R> {
-                /// Constructs a typed closure callable from C from a CIF
-                /// describing the calling convention for the resulting
-                /// function and the Rust closure to call.
-                pub fn new_with_cif<Callback>(cif: $cif<$( $T, )* R>,
-                                              callback: &'a mut Callback)
-                                              -> Self
-                    where Callback: FnMut($( $T, )*) -> R + 'a
-                {
-                    Self::from_parts(cif,
-                                     Self::static_callback,
-                                     callback)
-                }
-
-                #[allow(non_snake_case)]
-                extern "C" fn static_callback<Callback>
-                    (_cif:     &low::ffi_cif,
-                     result:   &mut R::RetType,
-                     &($( &$T, )*):
-                               &($( &$T, )*),
-                     userdata: &mut Callback)
-                  where Callback: FnMut($( $T, )*) -> R + 'a
-                {
-                    abort_on_panic!("Cannot panic inside FFI callback", {
-                        unsafe {
-                            ptr::write(result, userdata($( $T, )*).into());
-                        }
-                    });
-                }
-            }
+            pub type $closure_mut<'a, $( $T, )* R> = ClosureMut<'a, ($( $T, )*), R>;
 
             /// The type of function called from a one-shot, typed closure.
-            pub type $callback_once<U, $( $T, )* R>
-                = $callback_mut<Option<U>, $( $T, )* R>;
+            pub type $callback_once<U, $( $T, )* R> = CallbackOnce<U, ($( $T, )*), R>;
 
             /// A one-shot, typed closure with the given argument and
             /// result types.
-            pub struct $closure_once<$( $T, )* R> {
-                untyped: middle::ClosureOnce,
-                _marker: PhantomData<fn($( $T, )*) -> R>,
-            }
+            pub type $closure_once<$( $T, )* R> = ClosureOnce<($( $T, )*), R>;
 
-            impl<$($T: CType,)* R: CType> $closure_once<$($T,)* R> {
-                /// Constructs a typed closure callable from C from a
-                /// Rust closure.
-                pub fn new<Callback>(callback: Callback) -> Self
-                    where Callback: FnOnce($( $T, )*) -> R + Any
-                {
-                    Self::new_with_cif($cif::reify(), callback)
-                }
-            }
-
-            impl<$( $T: Copy, )* R: CType> $closure_once<$( $T, )* R> {
-                /// Constructs a one-shot closure callable from C from a CIF
-                /// describing the calling convention for the resulting
-                /// function and the Rust closure to call.
-                pub fn new_with_cif<Callback>(cif: $cif<$( $T, )* R>,
-                                              callback: Callback) -> Self
-                    where Callback: FnOnce($( $T, )*) -> R + Any
-                {
-                    Self::from_parts(cif,
-                                     Self::static_callback,
-                                     callback)
-                }
-
-                #[allow(non_snake_case)]
-                extern "C" fn static_callback<Callback>
-                    (_cif:     &low::ffi_cif,
-                     result:   &mut R::RetType,
-                     &($( &$T, )*):
-                               &($( &$T, )*),
-                     userdata: &mut Option<Callback>)
-                  where Callback: FnOnce($( $T, )*) -> R
-                {
-                    if let Some(userdata) = userdata.take() {
-                        abort_on_panic!("Cannot panic inside FFI callback", {
-                            unsafe {
-                                ptr::write(result, userdata($( $T, )*).into());
-                            }
-                        });
-                    } else {
-                        // There is probably a better way to abort here.
-                        #[cfg(feature = "std")]
-                        {
-                            use std::io::Write;
-                            let _ =
-                                std::io::stderr().write(b"FnOnce closure already used");
-                            std::process::exit(2);
-                        }
-                        #[cfg(not(feature = "std"))]
-                        {
-                            unsafe {
-                                segfault();
-                            }
-                        }
-                    }
-                }
-            }
+            /// A typed closure with the given argument and result types,
+            /// which owns its `Send + Sync` Rust closure.
+            pub type $closure_owned<$( $T, )* R> = ClosureOwned<($( $T, )*), R>;
 
-            impl<$( $T, )* R: CType> $closure_once<$( $T, )* R> {
-                /// Gets the C code pointer that is used to invoke the
-                /// closure.
-                pub fn code_ptr(&self) -> & $fnptr <'_, $( $T, )* R> {
-                    unsafe {
-                        self.untyped.instantiate_code_ptr()
-                    }
-                }
-
-                /// Constructs a one-shot closure callable from C from a CIF
-                /// describing the calling convention for the resulting
-                /// function, a callback for the function to call, and
-                /// userdata to pass to the callback.  Note that the return
-                /// type of the callback must follow the libffi implicit
-                /// extension rules.
-                pub fn from_parts<U: Any>(
-                    cif:      $cif<$( $T, )* R>,
-                    callback: $callback_once<U, $( $T, )* R::RetType>,
-                    userdata: U)
-                    -> Self
-                {
-                    let callback: middle::CallbackOnce<U, R::RetType>
-                        = unsafe { mem::transmute(callback) };
-                    let closure
-                        = middle::ClosureOnce::new(cif.untyped,
-                                                   callback,
-                                                   userdata);
-                    $closure_once {
-                        untyped: closure,
-                        _marker: PhantomData,
-                    }
-                }
-            }
+            /// An immutable, typed closure with the given fixed argument
+            /// and result types, which is called with variadic arguments.
+            pub type $closure_variadic<'a, $( $T, )* R> = ClosureVariadic<'a, ($( $T, )*), R>;
         }
 
         pub use $module::*;
@@ -500,61 +1365,74 @@ macro_rules! define_closure_mod {
 
 define_closure_mod!(arity0 Cif0 FnPtr0
 Callback0 CallbackMut0 CallbackOnce0
-Closure0 ClosureMut0 ClosureOnce0;
+Closure0 ClosureSync0 ClosureMut0 ClosureOnce0 ClosureOwned0 ClosureVariadic0;
 );
 define_closure_mod!(arity1 Cif1 FnPtr1
                     Callback1 CallbackMut1 CallbackOnce1
-                    Closure1 ClosureMut1 ClosureOnce1;
+                    Closure1 ClosureSync1 ClosureMut1 ClosureOnce1 ClosureOwned1 ClosureVariadic1;
                     A);
 define_closure_mod!(arity2 Cif2 FnPtr2
                     Callback2 CallbackMut2 CallbackOnce2
-                    Closure2 ClosureMut2 ClosureOnce2;
+                    Closure2 ClosureSync2 ClosureMut2 ClosureOnce2 ClosureOwned2 ClosureVariadic2;
                     A B);
 define_closure_mod!(arity3 Cif3 FnPtr3
                     Callback3 CallbackMut3 CallbackOnce3
-                    Closure3 ClosureMut3 ClosureOnce3;
+                    Closure3 ClosureSync3 ClosureMut3 ClosureOnce3 ClosureOwned3 ClosureVariadic3;
                     A B C);
 define_closure_mod!(arity4 Cif4 FnPtr4
                     Callback4 CallbackMut4 CallbackOnce4
-                    Closure4 ClosureMut4 ClosureOnce4;
+                    Closure4 ClosureSync4 ClosureMut4 ClosureOnce4 ClosureOwned4 ClosureVariadic4;
                     A B C D);
 define_closure_mod!(arity5 Cif5 FnPtr5
                     Callback5 CallbackMut5 CallbackOnce5
-                    Closure5 ClosureMut5 ClosureOnce5;
+                    Closure5 ClosureSync5 ClosureMut5 ClosureOnce5 ClosureOwned5 ClosureVariadic5;
                     A B C D E);
 define_closure_mod!(arity6 Cif6 FnPtr6
                     Callback6 CallbackMut6 CallbackOnce6
-                    Closure6 ClosureMut6 ClosureOnce6;
+                    Closure6 ClosureSync6 ClosureMut6 ClosureOnce6 ClosureOwned6 ClosureVariadic6;
                     A B C D E F);
 define_closure_mod!(arity7 Cif7 FnPtr7
                     Callback7 CallbackMut7 CallbackOnce7
-                    Closure7 ClosureMut7 ClosureOnce7;
+                    Closure7 ClosureSync7 ClosureMut7 ClosureOnce7 ClosureOwned7 ClosureVariadic7;
                     A B C D E F G);
 define_closure_mod!(arity8 Cif8 FnPtr8
                     Callback8 CallbackMut8 CallbackOnce8
-                    Closure8 ClosureMut8 ClosureOnce8;
+                    Closure8 ClosureSync8 ClosureMut8 ClosureOnce8 ClosureOwned8 ClosureVariadic8;
                     A B C D E F G H);
 define_closure_mod!(arity9 Cif9 FnPtr9
                     Callback9 CallbackMut9 CallbackOnce9
-                    Closure9 ClosureMut9 ClosureOnce9;
+                    Closure9 ClosureSync9 ClosureMut9 ClosureOnce9 ClosureOwned9 ClosureVariadic9;
                     A B C D E F G H I);
 define_closure_mod!(arity10 Cif10 FnPtr10
                     Callback10 CallbackMut10 CallbackOnce10
-                    Closure10 ClosureMut10 ClosureOnce10;
+                    Closure10 ClosureSync10 ClosureMut10 ClosureOnce10 ClosureOwned10 ClosureVariadic10;
                     A B C D E F G H I J);
 define_closure_mod!(arity11 Cif11 FnPtr11
                     Callback11 CallbackMut11 CallbackOnce11
-                    Closure11 ClosureMut11 ClosureOnce11;
+                    Closure11 ClosureSync11 ClosureMut11 ClosureOnce11 ClosureOwned11 ClosureVariadic11;
                     A B C D E F G H I J K);
 define_closure_mod!(arity12 Cif12 FnPtr12
                     Callback12 CallbackMut12 CallbackOnce12
-                    Closure12 ClosureMut12 ClosureOnce12;
+                    Closure12 ClosureSync12 ClosureMut12 ClosureOnce12 ClosureOwned12 ClosureVariadic12;
                     A B C D E F G H I J K L);
 
 #[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
 
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot access libffi's extern type statics")]
+    fn reify_arrays_with_any_features() {
+        let array = <[f32; 2]>::reify().into_middle();
+        assert_eq!(middle::TypeKind::Array { len: 2 }, array.kind());
+
+        #[cfg(all(feature = "complex", not(windows)))]
+        assert_eq!(
+            middle::TypeKind::Complex,
+            types::c_c32::reify().into_middle().kind()
+        );
+    }
+
     #[test]
     #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
     fn new_with_cif() {
@@ -615,4 +1493,497 @@ mod test {
         assert_eq!(6, counter.call(1));
         assert_eq!(8, counter.call(2));
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn variadic_new_with_cif() {
+        use core::ffi::{c_char, c_int, CStr};
+
+        let f = |prefix: *const c_char, scale: u8, mut var_args: crate::middle::VarArgs<'_>| {
+            let prefix = unsafe { CStr::from_ptr(prefix) }.to_bytes().len() as i64;
+            let first = unsafe { var_args.next_arg::<c_int>() }.unwrap();
+            let second = unsafe { var_args.next_arg::<i64>() }.unwrap();
+            assert!(var_args.next().is_none());
+            prefix + i64::from(scale) * (i64::from(first) + second)
+        };
+
+        let cif = Cif2::new_variadic(
+            <*const c_char>::reify(),
+            u8::reify(),
+            [c_int::reify().into_middle(), i64::reify().into_middle()],
+            i64::reify(),
+        );
+        let closure = ClosureVariadic2::new_with_cif(cif, &f);
+        let fun: &unsafe extern "C" fn(*const c_char, u8, ...) -> i64 =
+            unsafe { closure.instantiate_code_ptr() };
+
+        assert_eq!(3 + 2 * 47, unsafe {
+            fun(c"abc".as_ptr(), 2, 5 as c_int, 42i64)
+        });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn from_parts() {
+        extern "C" fn callback(
+            _cif: &low::ffi_cif,
+            result: &mut u64,
+            &(&x, &y): &(&u32, &u32),
+            offset: &u64,
+        ) {
+            *result = u64::from(x) * u64::from(y) + offset;
+        }
+
+        let offset = 2u64;
+        let closure = Closure2::<u32, u32, u64>::from_parts(Cif2::reify(), callback, &offset);
+
+        assert_eq!(44, closure.code_ptr().call(6, 7));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn many_arguments() {
+        type Args = (
+            u8,
+            i8,
+            u16,
+            i16,
+            u32,
+            i32,
+            u64,
+            i64,
+            f32,
+            f64,
+            usize,
+            isize,
+            u8,
+            i8,
+            u16,
+            i16,
+            u32,
+            i32,
+            u64,
+            i64,
+            f32,
+            f64,
+            usize,
+            isize,
+            u8,
+            i8,
+            u16,
+            i16,
+            u32,
+            i32,
+            u64,
+            f64,
+        );
+
+        let f = |a: u8,
+                 b: i8,
+                 c: u16,
+                 d: i16,
+                 e: u32,
+                 f: i32,
+                 g: u64,
+                 h: i64,
+                 i: f32,
+                 j: f64,
+                 k: usize,
+                 l: isize,
+                 m: u8,
+                 n: i8,
+                 o: u16,
+                 p: i16,
+                 q: u32,
+                 r: i32,
+                 s: u64,
+                 t: i64,
+                 u: f32,
+                 v: f64,
+                 w: usize,
+                 x: isize,
+                 y: u8,
+                 z: i8,
+                 aa: u16,
+                 ab: i16,
+                 ac: u32,
+                 ad: i32,
+                 ae: u64,
+                 af: f64| {
+            (a as f64 + b as f64 + c as f64 + d as f64 + e as f64 + f as f64 + g as f64 + h as f64)
+                + (i as f64 + j + k as f64 + l as f64 + m as f64 + n as f64 + o as f64 + p as f64)
+                + (q as f64 + r as f64 + s as f64 + t as f64 + u as f64 + v + w as f64 + x as f64)
+                + (y as f64
+                    + z as f64
+                    + aa as f64
+                    + ab as f64
+                    + ac as f64
+                    + ad as f64
+                    + ae as f64
+                    + af)
+        };
+
+        let closure = Closure::<Args, f64>::new(&f);
+        let sum = closure.code_ptr().call(
+            1, -2, 3, -4, 5, -6, 7, -8, 9.5, 10.5, 11, -12, 13, -14, 15, -16, 17, -18, 19, -20,
+            21.5, 22.5, 23, -24, 25, -26, 27, -28, 29, -30, 31, 32.5,
+        );
+
+        assert_eq!(Args::ARITY, 32);
+        assert_eq!(114.5, sum);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn once_with_thirteen_arguments() {
+        let v = alloc::vec![1u64; 13];
+        let f = move |a: u64,
+                      b: u64,
+                      c: u64,
+                      d: u64,
+                      e: u64,
+                      f: u64,
+                      g: u64,
+                      h: u64,
+                      i: u64,
+                      j: u64,
+                      k: u64,
+                      l: u64,
+                      m: u64| {
+            let args = [a, b, c, d, e, f, g, h, i, j, k, l, m];
+            v.iter().zip(args).map(|(x, y)| x * y).sum::<u64>()
+        };
+
+        let closure = ClosureOnce::<
+            (
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+                u64,
+            ),
+            u64,
+        >::new(f);
+
+        assert_eq!(
+            91,
+            closure
+                .code_ptr()
+                .call(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn owned_into_raw() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&calls);
+        let closure = ClosureOwned2::new(move |x: u32, y: u32| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            u64::from(x) << y
+        });
+
+        let (shift, handle) = std::thread::spawn(move || {
+            let (shift, handle) = closure.into_raw();
+            (shift, handle as usize)
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(12, shift.call(3, 2));
+        assert_eq!(1024, shift.call(1, 10));
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+
+        drop(unsafe { ClosureOwned2::<u32, u32, u64>::from_raw(handle as *mut _) });
+        assert_eq!(1, Arc::strong_count(&calls));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn panic_policy_store() {
+        let mut calls = 0;
+        let mut f = |x: i32| {
+            calls += 1;
+            assert!(x >= 0, "negative argument");
+            x * 2
+        };
+
+        {
+            let closure = ClosureMut1::new_with_panic_policy(&mut f, PanicPolicy::Store(-1));
+            let double = closure.code_ptr();
+
+            assert_eq!(8, double.call(4));
+            assert!(take_callback_panic().is_none());
+            assert_eq!(-1, double.call(-4));
+            assert_eq!(6, double.call(3));
+        }
+
+        let payload = take_callback_panic().unwrap();
+        assert_eq!(Some(&"negative argument"), payload.downcast_ref::<&str>());
+        assert_eq!(3, calls);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn panic_policy_once_called_twice() {
+        let v = alloc::vec![1u8, 2, 3];
+        let closure = Builder::<(), usize>::new()
+            .panic_policy(PanicPolicy::Store(0))
+            .into_closure_once(move || v.len());
+        let len = closure.code_ptr();
+
+        assert_eq!(3, len.call());
+        assert_eq!(0, len.call());
+        assert!(take_callback_panic().is_some());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn panic_policy_owned() {
+        let closure = Builder::<(u8,), u8>::new()
+            .panic_policy(PanicPolicy::Return(u8::MAX))
+            .into_closure_owned(|x: u8| x + 1);
+        let increment = closure.code_ptr();
+
+        assert_eq!(1, increment.call(0));
+        assert_eq!(u8::MAX, increment.call(u8::MAX));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn pooled_closures() {
+        let pool = middle::ClosurePool::new(4);
+        let builder = Builder::<(u32,), u32>::new().pool(pool.clone());
+
+        let add = |x: u32| x + 1;
+        let mut calls = 0;
+        let mut count = |x: u32| {
+            calls += 1;
+            x
+        };
+        let closure = builder.clone().into_closure(&add);
+        let closure_mut = builder.clone().into_closure_mut(&mut count);
+        let closure_sync = builder.clone().into_closure_sync(&add);
+        let closure_once = builder.clone().into_closure_once(|x: u32| x * 2);
+        let closure_owned = builder.into_closure_owned(|x: u32| x * 3);
+
+        assert_eq!(6, closure.code_ptr().call(5));
+        assert_eq!(5, closure_mut.code_ptr().call(5));
+        assert_eq!(6, closure_sync.code_ptr().call(5));
+        assert_eq!(10, closure_once.code_ptr().call(5));
+        assert_eq!(15, closure_owned.code_ptr().call(5));
+        assert_eq!(5, pool.stats().live);
+
+        drop((
+            closure,
+            closure_mut,
+            closure_sync,
+            closure_once,
+            closure_owned,
+        ));
+        assert_eq!(1, calls);
+
+        let stats = pool.stats();
+        assert_eq!(0, stats.live);
+        assert_eq!(4, stats.free);
+        assert_eq!(5, stats.allocated);
+    }
+
+    mod derive {
+        use super::super::*;
+        use crate::ffi_call;
+        use libffi_derive::CType;
+
+        #[derive(Clone, Copy, Debug, PartialEq, CType)]
+        #[repr(C)]
+        struct Small {
+            tag: u8,
+            value: u16,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, CType)]
+        #[repr(C)]
+        struct Large {
+            small: Small,
+            weight: f64,
+            next: *const Large,
+            id: u64,
+        }
+
+        #[derive(Clone, Copy, CType)]
+        #[repr(C)]
+        struct WithUnit {
+            unit: (),
+            value: u32,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, CType)]
+        #[repr(C)]
+        struct Pair<T>(T, T);
+
+        #[derive(Clone, Copy, Debug, PartialEq, CType)]
+        #[repr(C)]
+        struct Matrix {
+            name: [u8; 3],
+            m: [[f32; 2]; 2],
+        }
+
+        extern "C" fn transpose(matrix: Matrix) -> Matrix {
+            let [[a, b], [c, d]] = matrix.m;
+            Matrix {
+                m: [[a, c], [b, d]],
+                ..matrix
+            }
+        }
+
+        extern "C" fn swap(pair: Pair<f32>) -> Pair<f32> {
+            Pair(pair.1, pair.0)
+        }
+
+        extern "C" fn bump(large: Large, by: u64) -> Large {
+            Large {
+                id: large.id + by,
+                ..large
+            }
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore = "Miri cannot access libffi's extern type statics")]
+        fn reify_checks_layout() {
+            let _ = Small::reify();
+            let _ = Large::reify();
+            let _ = Pair::<u8>::reify();
+            let _ = Matrix::reify();
+
+            // Cached types are reified again as they were first.
+            assert_eq!(Small::reify().into_middle(), Small::reify().into_middle());
+            assert_eq!(16, Pair::<u64>::reify().into_middle().size());
+            assert_eq!(2, Pair::<u8>::reify().into_middle().size());
+        }
+
+        #[test]
+        #[cfg_attr(
+            miri,
+            ignore = "Miri cannot call Rust function pointers through libffi"
+        )]
+        fn call_with_array_fields() {
+            let matrix = Matrix {
+                name: *b"abc",
+                m: [[1.0, 2.0], [3.0, 4.0]],
+            };
+            let result: Matrix = unsafe { call(CodePtr(transpose as *mut _), &[arg(&matrix)]) };
+
+            assert_eq!(
+                Matrix {
+                    m: [[1.0, 3.0], [2.0, 4.0]],
+                    ..matrix
+                },
+                result
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "WithUnit")]
+        fn rejects_void_fields() {
+            let _ = WithUnit::reify();
+        }
+
+        #[test]
+        #[cfg_attr(
+            miri,
+            ignore = "Miri cannot call Rust function pointers through libffi"
+        )]
+        fn call_with_large_struct() {
+            let large = Large {
+                small: Small { tag: 7, value: 9 },
+                weight: 0.25,
+                next: core::ptr::null(),
+                id: 40,
+            };
+            let result: Large =
+                unsafe { call(CodePtr(bump as *mut _), &[arg(&large), arg(&2u64)]) };
+
+            assert_eq!(Large { id: 42, ..large }, result);
+        }
+
+        #[test]
+        #[cfg_attr(
+            miri,
+            ignore = "Miri cannot call Rust function pointers through libffi"
+        )]
+        fn prepared_call_with_large_struct() {
+            let large = Large {
+                small: Small { tag: 7, value: 9 },
+                weight: 0.25,
+                next: core::ptr::null(),
+                id: 40,
+            };
+            let mut bump = call::PreparedCall::<(Large, u64), Large>::new(CodePtr(bump as *mut _));
+
+            // `Large` is larger than 16 bytes, which libffi may pass through
+            // a copy whose address it writes into the argument array.
+            for by in [2, 3] {
+                let result = unsafe { bump.call((large, by)) };
+                assert_eq!(
+                    Large {
+                        id: 40 + by,
+                        ..large
+                    },
+                    result
+                );
+            }
+        }
+
+        #[test]
+        #[cfg_attr(
+            miri,
+            ignore = "Miri cannot call Rust function pointers through libffi"
+        )]
+        fn call_with_struct() {
+            let pair = Pair(1.5f32, -2.0f32);
+            let result: Pair<f32> = unsafe { call(CodePtr(swap as *mut _), &[arg(&pair)]) };
+
+            assert_eq!(Pair(-2.0, 1.5), result);
+        }
+
+        #[test]
+        #[cfg_attr(
+            miri,
+            ignore = "Miri cannot call Rust function pointers through libffi"
+        )]
+        fn ffi_call_with_struct() {
+            let pair = Pair(3.0f32, 4.0f32);
+            let result = unsafe {
+                ffi_call! { swap(pair) -> Pair<f32> }
+            };
+
+            assert_eq!(Pair(4.0, 3.0), result);
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+        fn closure_with_struct() {
+            let f = |small: Small, x: u16| Small {
+                tag: small.tag + 1,
+                value: small.value * x,
+            };
+            let closure = Closure2::new(&f);
+
+            assert_eq!(
+                Small { tag: 2, value: 42 },
+                closure.code_ptr().call(Small { tag: 1, value: 21 }, 2)
+            );
+        }
+    }
 }
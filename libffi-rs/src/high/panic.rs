@@ -0,0 +1,162 @@
+//! What closures do when their Rust callback panics.
+
+#[cfg(not(feature = "std"))]
+use core::marker::PhantomData;
+
+#[cfg(not(feature = "std"))]
+use super::segfault;
+#[cfg(feature = "std")]
+use std::{any::Any, boxed::Box, cell::RefCell, panic};
+
+/// Determines what a closure does when its Rust callback panics.
+///
+/// A panic must not unwind out of a function called from C, so by default
+/// a closure aborts the process when its callback panics. With the `std`
+/// feature, a closure can instead catch the panic and return a fixed value
+/// to its C caller, optionally keeping the panic payload so that it can be
+/// inspected or resumed with [`take_callback_panic`].
+///
+/// The policy also applies when a one-shot closure is called a second time,
+/// which otherwise exits the process.
+///
+/// The policy is set with the `new_with_panic_policy` and
+/// `new_with_cif_and_panic_policy` closure constructors, or with
+/// [`Builder::panic_policy`](super::Builder::panic_policy).
+///
+/// # Examples
+///
+/// ```
+/// use libffi::high::{Closure1, PanicPolicy};
+///
+/// let f = |x: u32| 100 / x;
+/// let closure = Closure1::new_with_panic_policy(&f, PanicPolicy::Return(0));
+/// let divide = closure.code_ptr();
+///
+/// assert_eq!(20, divide.call(5));
+/// assert_eq!(0, divide.call(0));
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub enum PanicPolicy<R> {
+    /// Abort the process. This is the default.
+    #[default]
+    Abort,
+    /// Catch the panic, discard its payload and return the given value.
+    #[cfg(feature = "std")]
+    Return(R),
+    /// Catch the panic, store its payload for [`take_callback_panic`] and
+    /// return the given value.
+    #[cfg(feature = "std")]
+    Store(R),
+    #[cfg(not(feature = "std"))]
+    #[doc(hidden)]
+    __Unused(core::convert::Infallible, PhantomData<R>),
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CALLBACK_PANIC: RefCell<Option<Box<dyn Any + Send>>> = const { RefCell::new(None) };
+}
+
+/// Takes the payload of the last panic caught by a closure with the
+/// [`PanicPolicy::Store`] policy on the current thread.
+///
+/// Returns `None` if no panic has been stored since the last call. The
+/// payload can be passed to [`std::panic::resume_unwind`] to continue
+/// unwinding once control is back in Rust.
+///
+/// This item is enabled by `#[cfg(feature = "std")]`.
+///
+/// # Examples
+///
+/// ```
+/// use libffi::high::{take_callback_panic, Closure0, PanicPolicy};
+///
+/// let f = || -> i32 { panic!("oops") };
+/// let closure = Closure0::new_with_panic_policy(&f, PanicPolicy::Store(-1));
+///
+/// assert_eq!(-1, closure.code_ptr().call());
+///
+/// let payload = take_callback_panic().unwrap();
+/// assert_eq!(Some(&"oops"), payload.downcast_ref::<&str>());
+/// assert!(take_callback_panic().is_none());
+/// ```
+#[cfg(feature = "std")]
+pub fn take_callback_panic() -> Option<Box<dyn Any + Send>> {
+    CALLBACK_PANIC.with(|stored| stored.borrow_mut().take())
+}
+
+#[cfg(feature = "std")]
+fn store_panic(payload: Box<dyn Any + Send>) {
+    CALLBACK_PANIC.with(|stored| *stored.borrow_mut() = Some(payload));
+}
+
+impl<R: Copy> PanicPolicy<R> {
+    /// Calls `f`, handling a panic according to the policy.
+    pub(crate) fn call(&self, f: impl FnOnce() -> R) -> R {
+        match *self {
+            PanicPolicy::Abort => {
+                let result;
+                abort_on_panic!("Cannot panic inside FFI callback", {
+                    result = f();
+                });
+                result
+            }
+            #[cfg(feature = "std")]
+            PanicPolicy::Return(value) => {
+                panic::catch_unwind(panic::AssertUnwindSafe(f)).unwrap_or(value)
+            }
+            #[cfg(feature = "std")]
+            PanicPolicy::Store(value) => panic::catch_unwind(panic::AssertUnwindSafe(f))
+                .unwrap_or_else(|payload| {
+                    store_panic(payload);
+                    value
+                }),
+            #[cfg(not(feature = "std"))]
+            PanicPolicy::__Unused(never, _) => match never {},
+        }
+    }
+
+    /// Handles a one-shot closure being called a second time.
+    pub(crate) fn already_called(&self) -> R {
+        match *self {
+            PanicPolicy::Abort => {
+                // There is probably a better way to abort here.
+                #[cfg(feature = "std")]
+                {
+                    use std::io::Write;
+                    let _ = std::io::stderr().write(b"FnOnce closure already used");
+                    std::process::exit(2);
+                }
+                #[cfg(not(feature = "std"))]
+                {
+                    unsafe {
+                        segfault();
+                    }
+                    unreachable!()
+                }
+            }
+            #[cfg(feature = "std")]
+            PanicPolicy::Return(value) => value,
+            #[cfg(feature = "std")]
+            PanicPolicy::Store(value) => {
+                store_panic(Box::new("FnOnce closure already used"));
+                value
+            }
+            #[cfg(not(feature = "std"))]
+            PanicPolicy::__Unused(never, _) => match never {},
+        }
+    }
+}
+
+/// The userdata of the high layer’s closures: the Rust callback along
+/// with the closure’s panic policy.
+pub(crate) struct Guarded<F, R> {
+    pub(crate) callback: F,
+    pub(crate) policy: PanicPolicy<R>,
+}
+
+// The policy holds a value of `R`, which is copied out of it by calls on
+// any thread, so the userdata is only `Send` or `Sync` when both the
+// callback and `R` are.
+unsafe impl<F: Send, R: Send> Send for Guarded<F, R> {}
+unsafe impl<F: Sync, R: Sync> Sync for Guarded<F, R> {}
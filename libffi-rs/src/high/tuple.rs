@@ -0,0 +1,205 @@
+//! Argument lists of the high layer’s CIFs and closures, represented as
+//! tuples.
+
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::{mem, ptr};
+
+use super::CType;
+use crate::{low, middle};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Tuples of [`CType`]s, which describe the argument lists of
+/// [`Cif`](super::Cif)s and [`Closure`](super::Closure)s.
+///
+/// This trait is implemented for tuples of up to 32 elements, where every
+/// element implements [`CType`]. For example, the arguments of a C function
+/// of type `int (*)(double, const char *)` are described by
+/// `(f64, *const c_char)`, and those of `void (*)(void)` by `()`.
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+pub trait CTypeTuple: Copy + private::Sealed {
+    /// The number of elements of the tuple.
+    const ARITY: usize;
+
+    /// The type of function called from an immutable, typed closure with
+    /// these argument types and result type `R`, with userdata of type `U`.
+    ///
+    /// For `(A, B)` this is
+    /// `extern "C" fn(&low::ffi_cif, &mut R, &(&A, &B), &U)`.
+    type Callback<U, R>: Copy;
+
+    /// The type of function called from a mutable, typed closure with
+    /// these argument types and result type `R`, with userdata of type `U`.
+    ///
+    /// For `(A, B)` this is
+    /// `extern "C" fn(&low::ffi_cif, &mut R, &(&A, &B), &mut U)`.
+    type CallbackMut<U, R>: Copy;
+
+    /// Describes the element types to the [`mod@middle`] layer.
+    fn reify_types() -> Vec<middle::Type>;
+
+    /// Reads the arguments from the argument array a closure callback is
+    /// invoked with.
+    ///
+    /// # Safety
+    ///
+    /// `args` must point to at least [`ARITY`](Self::ARITY) pointers to
+    /// valid values of the element types, in order.
+    #[doc(hidden)]
+    unsafe fn read_args(args: *const *const c_void) -> Self;
+
+    /// Stores the elements as the arguments of a call frame.
+    #[doc(hidden)]
+    fn store_args(self, frame: &mut middle::CallFrame);
+
+    #[doc(hidden)]
+    fn into_middle_callback<U, R>(callback: Self::Callback<U, R>) -> middle::Callback<U, R>;
+
+    #[doc(hidden)]
+    fn into_middle_callback_mut<U, R>(
+        callback: Self::CallbackMut<U, R>,
+    ) -> middle::CallbackMut<U, R>;
+}
+
+/// Rust closures that can be called with the arguments in the tuple
+/// `Args`.
+///
+/// This is implemented for every `Fn(A, B, …) -> R` with
+/// `Args = (A, B, …)`, and lets [`Closure`](super::Closure) call a Rust
+/// closure of any arity.
+pub trait TupleFn<Args, R> {
+    /// Calls the closure with the elements of `args` as its arguments.
+    fn call_tuple(&self, args: Args) -> R;
+}
+
+/// Rust closures that can be called mutably with the arguments in the
+/// tuple `Args`.
+///
+/// This is the [`FnMut`] counterpart of [`TupleFn`].
+pub trait TupleFnMut<Args, R> {
+    /// Calls the closure with the elements of `args` as its arguments.
+    fn call_tuple_mut(&mut self, args: Args) -> R;
+}
+
+/// Rust closures that can be called once with the arguments in the
+/// tuple `Args`.
+///
+/// This is the [`FnOnce`] counterpart of [`TupleFn`].
+pub trait TupleFnOnce<Args, R> {
+    /// Calls the closure with the elements of `args` as its arguments.
+    fn call_tuple_once(self, args: Args) -> R;
+}
+
+/// Rust closures that can be called with the arguments in the tuple
+/// `Args`, followed by the variadic arguments.
+///
+/// This is implemented for every
+/// `Fn(A, B, …, middle::VarArgs<'_>) -> R` with `Args = (A, B, …)`.
+pub trait TupleFnVariadic<Args, R> {
+    /// Calls the closure with the elements of `args` and `var_args` as its
+    /// arguments.
+    fn call_tuple_variadic(&self, args: Args, var_args: middle::VarArgs<'_>) -> R;
+}
+
+macro_rules! impl_tuple {
+    ($( $T:ident )*) => {
+        impl<$( $T: CType, )*> private::Sealed for ($( $T, )*) {}
+
+        impl<$( $T: CType, )*> CTypeTuple for ($( $T, )*) {
+            const ARITY: usize = 0 $( + { let _ = stringify!($T); 1 } )*;
+
+            type Callback<U, R> = extern "C" fn(&low::ffi_cif, &mut R, &($( &$T, )*), &U);
+
+            type CallbackMut<U, R> = extern "C" fn(&low::ffi_cif, &mut R, &($( &$T, )*), &mut U);
+
+            fn reify_types() -> Vec<middle::Type> {
+                alloc::vec![$( $T::reify().into_middle() ),*]
+            }
+
+            #[allow(unused_mut, unused_variables, unused_assignments, clippy::unused_unit)]
+            unsafe fn read_args(args: *const *const c_void) -> Self {
+                let mut next = args;
+                ($(
+                    {
+                        let arg = ptr::read(*next as *const $T);
+                        next = next.add(1);
+                        arg
+                    },
+                )*)
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables, unused_assignments)]
+            fn store_args(self, frame: &mut middle::CallFrame) {
+                let ($( $T, )*) = self;
+                let mut index = 0;
+                $(
+                    frame.set(index, $T);
+                    index += 1;
+                )*
+            }
+
+            fn into_middle_callback<U, R>(callback: Self::Callback<U, R>) -> middle::Callback<U, R> {
+                // The callback reads its arguments through a tuple of
+                // references, which we assume to be laid out like the
+                // argument array.
+                unsafe { mem::transmute(callback) }
+            }
+
+            fn into_middle_callback_mut<U, R>(
+                callback: Self::CallbackMut<U, R>,
+            ) -> middle::CallbackMut<U, R> {
+                unsafe { mem::transmute(callback) }
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<Fun, $( $T, )* R> TupleFn<($( $T, )*), R> for Fun
+        where
+            Fun: Fn($( $T ),*) -> R,
+        {
+            fn call_tuple(&self, ($( $T, )*): ($( $T, )*)) -> R {
+                self($( $T ),*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<Fun, $( $T, )* R> TupleFnMut<($( $T, )*), R> for Fun
+        where
+            Fun: FnMut($( $T ),*) -> R,
+        {
+            fn call_tuple_mut(&mut self, ($( $T, )*): ($( $T, )*)) -> R {
+                self($( $T ),*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<Fun, $( $T, )* R> TupleFnOnce<($( $T, )*), R> for Fun
+        where
+            Fun: FnOnce($( $T ),*) -> R,
+        {
+            fn call_tuple_once(self, ($( $T, )*): ($( $T, )*)) -> R {
+                self($( $T ),*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<Fun, $( $T, )* R> TupleFnVariadic<($( $T, )*), R> for Fun
+        where
+            Fun: Fn($( $T, )* middle::VarArgs<'_>) -> R,
+        {
+            fn call_tuple_variadic(
+                &self,
+                ($( $T, )*): ($( $T, )*),
+                var_args: middle::VarArgs<'_>,
+            ) -> R {
+                self($( $T, )* var_args)
+            }
+        }
+    };
+}
+
+for_each_tuple!(impl_tuple);
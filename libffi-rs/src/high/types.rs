@@ -1,6 +1,8 @@
 //! Representations of C types for the high layer.
 
+use core::any::type_name;
 use core::marker::PhantomData;
+use core::mem;
 
 use super::super::low;
 use super::super::middle;
@@ -36,6 +38,29 @@ impl<T> Type<T> {
 ///
 /// In particular, for any type `T` that implements `CType`, we can
 /// get a `Type<T>` for describing that type.
+///
+/// With the `derive` feature enabled, `CType` can be derived for
+/// `#[repr(C)]` structs whose fields all implement `CType`, which allows
+/// such structs to be passed and returned by value:
+///
+/// ```
+/// # #[cfg(feature = "derive")] {
+/// use libffi::high::{CType, Closure1};
+///
+/// #[derive(Clone, Copy, CType)]
+/// #[repr(C)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// let f = |p: Point| p.x * p.y;
+/// let closure = Closure1::new(&f);
+///
+/// assert_eq!(12, closure.code_ptr().call(Point { x: 3, y: 4 }));
+/// # }
+/// ```
+///
 /// # Safety
 /// This trait is unsafe to implement because if the libffi type
 /// associated with a Rust type doesn’t match then we get
@@ -52,6 +77,81 @@ pub unsafe trait CType: Copy {
     type RetType: core::convert::From<Self> + core::convert::TryInto<Self>;
 }
 
+/// Derives [`CType`] for a `#[repr(C)]` struct.
+///
+/// This item is enabled by `#[cfg(feature = "derive")]`.
+#[cfg(feature = "derive")]
+pub use libffi_derive::CType;
+
+/// Builds the type of a `#[repr(C)]` struct from the types of its fields
+/// and checks that libffi lays it out exactly as the Rust compiler does.
+///
+/// This is used by `#[derive(CType)]` and is not part of the public API.
+#[doc(hidden)]
+pub fn __reify_struct<T, const N: usize>(
+    fields: [middle::Type; N],
+    offsets: [usize; N],
+) -> Type<T> {
+    let mut untyped = middle::Type::structure(fields);
+    let ffi_offsets = untyped
+        .struct_offsets(low::ffi_abi_FFI_DEFAULT_ABI)
+        .unwrap_or_else(|error| panic!("libffi cannot lay out `{}`: {error:?}", type_name::<T>()));
+
+    // SAFETY: `struct_offsets` succeeded, so `untyped` points to a valid,
+    // laid out structure type.
+    let (size, alignment) = unsafe {
+        let raw = &*untyped.as_raw_ptr();
+        (raw.size, usize::from(raw.alignment))
+    };
+
+    assert!(
+        ffi_offsets[..] == offsets[..]
+            && size == mem::size_of::<T>()
+            && alignment == mem::align_of::<T>(),
+        "libffi lays out `{}` with offsets {:?}, size {}, and alignment {}, \
+         but Rust uses offsets {:?}, size {}, and alignment {}",
+        type_name::<T>(),
+        ffi_offsets,
+        size,
+        alignment,
+        offsets,
+        mem::size_of::<T>(),
+        mem::align_of::<T>(),
+    );
+
+    Type::make(untyped)
+}
+
+/// Holds the type built by [`__reify_struct`] for a struct without type or
+/// const parameters, so that its layout is built and checked only once.
+/// Without the `std` feature, nothing is cached.
+///
+/// This is used by `#[derive(CType)]` and is not part of the public API.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct __StructCache {
+    #[cfg(feature = "std")]
+    type_: std::sync::OnceLock<middle::Type>,
+}
+
+impl __StructCache {
+    /// Creates an empty cache.
+    pub const fn new() -> Self {
+        __StructCache {
+            #[cfg(feature = "std")]
+            type_: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Returns the cached type, calling `reify` to build it the first time.
+    pub fn get_or_init<T>(&self, reify: impl FnOnce() -> Type<T>) -> Type<T> {
+        #[cfg(feature = "std")]
+        return Type::make(self.type_.get_or_init(|| reify().into_middle()).clone());
+        #[cfg(not(feature = "std"))]
+        return reify();
+    }
+}
+
 macro_rules! impl_ffi_type {
     ($type_:ty, $ret_:ty, $cons:ident) => {
         unsafe impl CType for $type_ {
@@ -91,8 +191,8 @@ impl_ffi_type!((), (), void);
 // c_c64 should come from elsewhere (the num package?), but that
 // elsewhere doesn’t seem to exist yet.
 
-/// Laid out the same as C11 `float complex` and C++11
-/// `std::complex<float>`.
+/// A complex number as its real and imaginary parts, laid out the same as
+/// C11 `float complex` and C++11 `std::complex<float>`.
 ///
 /// This item is enabled by `#[cfg(all(feature = "complex", not(windows)))]`.
 ///
@@ -104,10 +204,12 @@ impl_ffi_type!((), (), void);
 /// doesn’t have complex support on most platforms yet.
 #[allow(non_camel_case_types)]
 #[cfg(all(feature = "complex", not(windows)))]
-pub type c_c32 = [f32; 2];
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(transparent)]
+pub struct c_c32(pub [f32; 2]);
 
-/// Laid out the same as C11 `double complex` and C++11
-/// `std::complex<double>`.
+/// A complex number as its real and imaginary parts, laid out the same as
+/// C11 `double complex` and C++11 `std::complex<double>`.
 ///
 /// This item is enabled by `#[cfg(all(feature = "complex", not(windows)))]`.
 ///
@@ -119,7 +221,9 @@ pub type c_c32 = [f32; 2];
 /// doesn’t have complex support on most platforms yet.
 #[allow(non_camel_case_types)]
 #[cfg(all(feature = "complex", not(windows)))]
-pub type c_c64 = [f64; 2];
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(transparent)]
+pub struct c_c64(pub [f64; 2]);
 
 #[cfg(all(feature = "complex", not(windows)))]
 impl_ffi_type!(c_c32, c_c32, c32);
@@ -127,6 +231,21 @@ impl_ffi_type!(c_c32, c_c32, c32);
 #[cfg(all(feature = "complex", not(windows)))]
 impl_ffi_type!(c_c64, c_c64, c64);
 
+/// Describes an array as a [`middle::Type::array`].
+///
+/// C functions cannot take or return arrays by value, so this is only
+/// meant for array fields of structs, such as with `#[derive(CType)]`.
+///
+/// # Panics
+///
+/// Reifying an empty array panics, as libffi cannot lay out empty arrays.
+unsafe impl<T: CType, const N: usize> CType for [T; N] {
+    fn reify() -> Type<Self> {
+        Type::make(middle::Type::array(T::reify().into_middle(), N))
+    }
+    type RetType = Self;
+}
+
 unsafe impl<T> CType for *const T {
     fn reify() -> Type<Self> {
         Type::make(middle::Type::pointer())
@@ -93,6 +93,10 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+// Lets `#[derive(CType)]` refer to `::libffi` in this crate's own tests.
+#[cfg(test)]
+extern crate self as libffi;
+
 /// Raw definitions imported from the C library (via bindgen).
 ///
 /// This module is generated by bindgen and undocumented. It’s intended
@@ -101,6 +105,8 @@ pub mod raw {
     pub use libffi_sys::*;
 }
 
+#[cfg(all(feature = "dlopen", unix))]
+pub mod dylib;
 pub mod high;
 pub mod low;
 pub mod middle;
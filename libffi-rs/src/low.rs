@@ -9,7 +9,7 @@
 
 use core::ffi::{c_uint, c_void};
 use core::ptr::{addr_of, addr_of_mut, null_mut};
-use core::{mem, ptr};
+use core::{fmt, mem, ptr};
 
 use crate::raw;
 
@@ -25,8 +25,25 @@ pub enum Error {
     ArgType,
     /// A libffi-managed allocation failed.
     Allocation,
+    /// libffi reported a status this crate does not know.
+    Unknown(raw::ffi_status),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Typedef => f.write_str("bad or unsupported type representation"),
+            Error::Abi => f.write_str("bad or unsupported ABI"),
+            Error::ArgType => f.write_str("bad or unsupported argument type"),
+            Error::Allocation => f.write_str("libffi allocation failed"),
+            Error::Unknown(status) => write!(f, "unknown libffi status {status}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
 /// The [`std::result::Result`] type specialized for libffi [`Error`]s.
 pub type Result<T> = ::core::result::Result<T, Error>;
 
@@ -38,10 +55,10 @@ fn status_to_result<R>(status: raw::ffi_status, good: R) -> Result<R> {
         Err(Error::Typedef)
     } else if status == raw::ffi_status_FFI_BAD_ARGTYPE {
         Err(Error::ArgType)
-    }
-    // If we don't recognize the status, that is an ABI error:
-    else {
+    } else if status == raw::ffi_status_FFI_BAD_ABI {
         Err(Error::Abi)
+    } else {
+        Err(Error::Unknown(status))
     }
 }
 
@@ -129,9 +146,25 @@ impl CodePtr {
 }
 
 pub use raw::{
-    ffi_abi, ffi_abi_FFI_DEFAULT_ABI, ffi_arg, ffi_cif, ffi_closure, ffi_sarg, ffi_status, ffi_type,
+    ffi_abi, ffi_abi_FFI_DEFAULT_ABI, ffi_arg, ffi_cif, ffi_closure, ffi_go_closure, ffi_raw,
+    ffi_raw_closure, ffi_sarg, ffi_status, ffi_type,
 };
 
+/// Whether libffi implements the raw API natively on this target.
+///
+/// Where it does not, [`raw_call`] and raw closures convert between the
+/// raw format and arrays of argument pointers, so they are no faster than
+/// [`call`] and ordinary closures. Where it does, which is only 32-bit x86,
+/// the raw format is the layout of the arguments on the stack, and passes
+/// structures by value rather than by pointer.
+pub const NATIVE_RAW_API: bool = raw::FFI_NATIVE_RAW_API != 0;
+
+/// Whether libffi supports Go closures on this target.
+///
+/// Where it does not, [`prep_go_closure`] fails with [`Error::Abi`] and
+/// [`call_go`] panics, so callers should fall back to [`prep_closure`].
+pub const GO_CLOSURES: bool = raw::FFI_GO_CLOSURES != 0;
+
 /// Re-exports the [`ffi_type`] objects used to describe the types of
 /// arguments and results.
 ///
@@ -412,6 +445,23 @@ pub unsafe fn get_struct_offsets(
 /// It copies large structures to a new location and rewrites the pointer.
 /// this leads to an issue if args is being reused across multiple calls.
 pub unsafe fn call<R>(cif: *mut ffi_cif, fun: CodePtr, args: *mut *mut c_void) -> R {
+    // SAFETY: It is up to the caller to ensure that the ffi_call is safe to
+    // perform.
+    unsafe {
+        call_with(cif, |result| {
+            raw::ffi_call(cif, Some(*fun.as_safe_fun()), result, args);
+        })
+    }
+}
+
+/// Reads the result of a call made by `invoke`, which is passed the
+/// location that libffi should write the result to.
+///
+/// # Safety
+///
+/// `cif` must point to a valid CIF whose result type is `R`, and `invoke`
+/// must write a result of that type as `ffi_call` does.
+unsafe fn call_with<R>(cif: *mut ffi_cif, invoke: impl FnOnce(*mut c_void)) -> R {
     // libffi always writes *at least* a full register to the result pointer.
     // Therefore, if the return value is smaller, we need to handle the return
     // value with extra care to prevent out of bounds write and returning the
@@ -427,15 +477,10 @@ pub unsafe fn call<R>(cif: *mut ffi_cif, fun: CodePtr, args: *mut *mut c_void) -
         // divisible by any of the smaller alignments.
         let mut result = mem::MaybeUninit::<usize>::uninit();
 
-        // SAFETY: It is up to the caller to ensure that the ffi_call is safe
-        // to perform.
+        // SAFETY: It is up to the caller to ensure that the call is safe to
+        // perform.
         unsafe {
-            raw::ffi_call(
-                cif,
-                Some(*fun.as_safe_fun()),
-                result.as_mut_ptr().cast::<c_void>(),
-                args,
-            );
+            invoke(result.as_mut_ptr().cast::<c_void>());
 
             let result = result.assume_init();
 
@@ -448,15 +493,10 @@ pub unsafe fn call<R>(cif: *mut ffi_cif, fun: CodePtr, args: *mut *mut c_void) -
     } else {
         let mut result = mem::MaybeUninit::<R>::uninit();
 
-        // SAFETY: It is up to the caller to ensure that the ffi_call is safe
-        // to perform.
+        // SAFETY: It is up to the caller to ensure that the call is safe to
+        // perform.
         unsafe {
-            raw::ffi_call(
-                cif,
-                Some(*fun.as_safe_fun()),
-                result.as_mut_ptr().cast::<c_void>(),
-                args,
-            );
+            invoke(result.as_mut_ptr().cast::<c_void>());
 
             result.assume_init()
         }
@@ -886,9 +926,362 @@ pub unsafe fn prep_closure_mut<U, R>(
     status_to_result(status, ())
 }
 
+/// Returns the number of bytes of the raw argument array for `cif`.
+///
+/// The arguments occupy consecutive [`ffi_raw`] slots in the order of
+/// the CIF’s argument types. A scalar argument is stored at the start of
+/// its first slot and takes as many slots as it needs, while a structure
+/// argument takes a single slot holding a pointer to it.
+///
+/// # Safety
+///
+/// `cif` must point to a CIF prepared by [`prep_cif`] or [`prep_cif_var`].
+pub unsafe fn raw_size(cif: *mut ffi_cif) -> usize {
+    raw::ffi_raw_size(cif)
+}
+
+/// Calls C function `fun` using the calling convention and types
+/// specified by the given CIF, passing the arguments in the raw format.
+///
+/// See [`raw_size`] for the layout of `args`, and [`call`] for how the
+/// result is read.
+///
+/// # Examples
+///
+/// ```
+/// use std::ptr::addr_of_mut;
+/// use libffi::low::*;
+///
+/// extern "C" fn c_function(a: u64, b: u16) -> u64 { a * b as u64 }
+///
+/// let result = unsafe {
+///     let mut args = [addr_of_mut!(types::uint64), addr_of_mut!(types::uint16)];
+///     let mut cif: ffi_cif = Default::default();
+///
+///     prep_cif(&mut cif, ffi_abi_FFI_DEFAULT_ABI, 2,
+///              addr_of_mut!(types::uint64), args.as_mut_ptr()).unwrap();
+///
+///     let mut raw_args = [ffi_raw::default(); 2];
+///     raw_args[0].uint = 6;
+///     raw_args[1].uint = 7;
+///
+///     raw_call::<u64>(&mut cif, CodePtr(c_function as *mut _), raw_args.as_mut_ptr())
+/// };
+///
+/// assert_eq!(42, result);
+/// ```
+///
+/// # Safety
+///
+/// As for [`call`], and `args` must hold valid values of the argument
+/// types in the layout described by [`raw_size`].
+pub unsafe fn raw_call<R>(cif: *mut ffi_cif, fun: CodePtr, args: *mut ffi_raw) -> R {
+    // SAFETY: It is up to the caller to ensure that the ffi_raw_call is safe
+    // to perform.
+    unsafe {
+        call_with(cif, |result| {
+            raw::ffi_raw_call(cif, Some(*fun.as_safe_fun()), result, args);
+        })
+    }
+}
+
+/// The type of function called by a raw closure.
+///
+/// `U` is the type of the user data captured by the closure and passed
+/// to the callback, and `R` is the type of the result. The parameters
+/// are passed in the raw format described by [`raw_size`].
+pub type RawClosureCallback<U, R> =
+    unsafe extern "C" fn(cif: &ffi_cif, result: &mut R, args: *mut ffi_raw, userdata: &U);
+
+/// The callback type expected by [`raw::ffi_prep_raw_closure_loc`].
+type RawClosureRawCallback = unsafe extern "C" fn(
+    cif: *mut ffi_cif,
+    result: *mut c_void,
+    args: *mut ffi_raw,
+    userdata: *mut c_void,
+);
+
+/// Allocates a raw closure.
+///
+/// This is the counterpart of [`closure_alloc`] for closures initialized
+/// with [`prep_raw_closure`], which are larger than other closures. The
+/// closure must be deallocated using [`raw_closure_free`].
+pub fn raw_closure_alloc() -> (*mut ffi_raw_closure, CodePtr) {
+    try_raw_closure_alloc().expect("ffi_closure_alloc")
+}
+
+/// Attempts to allocate a raw closure.
+///
+/// This is the fallible counterpart to [`raw_closure_alloc`].
+pub fn try_raw_closure_alloc() -> Option<(*mut ffi_raw_closure, CodePtr)> {
+    unsafe {
+        let mut code_pointer = null_mut();
+        let closure = raw::ffi_closure_alloc(
+            mem::size_of::<ffi_raw_closure>(),
+            addr_of_mut!(code_pointer),
+        )
+        .cast::<ffi_raw_closure>();
+
+        if closure.is_null() || code_pointer.is_null() {
+            if !closure.is_null() {
+                raw::ffi_closure_free(closure.cast());
+            }
+            None
+        } else {
+            Some((closure, CodePtr::from_ptr(code_pointer)))
+        }
+    }
+}
+
+/// Frees a raw closure allocated with [`raw_closure_alloc`].
+///
+/// # Safety
+///
+/// The closure cannot be null and must be a valid pointer to a closure
+/// allocated with [`raw_closure_alloc`].
+pub unsafe fn raw_closure_free(closure: *mut ffi_raw_closure) {
+    raw::ffi_closure_free(closure as *mut c_void);
+}
+
+/// Initializes a raw closure with a callback function and userdata.
+///
+/// This is the counterpart of [`prep_closure`] for closures whose
+/// callback receives its arguments in the raw format described by
+/// [`raw_size`].
+///
+/// # Safety
+///
+/// The closure retains a reference to CIF `cif`, so that must
+/// still be live when the closure is used lest undefined behavior
+/// result. `closure` and `code` must have been returned together by
+/// [`raw_closure_alloc`].
+///
+/// # Result
+///
+/// `Ok(())` for success or `Err(e)` for failure. Where the raw API is
+/// [native](NATIVE_RAW_API), libffi rejects structure and `long double`
+/// arguments with [`Error::Typedef`].
+///
+/// # Examples
+///
+/// ```
+/// use std::mem;
+/// use std::ptr::addr_of_mut;
+/// use libffi::low::*;
+///
+/// unsafe extern "C" fn callback(_cif: &ffi_cif,
+///                               result: &mut u64,
+///                               args: *mut ffi_raw,
+///                               userdata: &u64)
+/// {
+///     *result = (*args).uint as u64 + *userdata;
+/// }
+///
+/// unsafe {
+///     let mut cif: ffi_cif = Default::default();
+///     let mut args = [addr_of_mut!(types::uint64)];
+///     let userdata: u64 = 5;
+///
+///     prep_cif(&mut cif, ffi_abi_FFI_DEFAULT_ABI, 1,
+///              addr_of_mut!(types::uint64), args.as_mut_ptr()).unwrap();
+///
+///     let (closure, code) = raw_closure_alloc();
+///     prep_raw_closure(closure, &mut cif, callback, &userdata, code).unwrap();
+///
+///     let add5: extern "C" fn(u64) -> u64 = mem::transmute(code);
+///     assert_eq!(11, add5(6));
+///
+///     raw_closure_free(closure);
+/// }
+/// ```
+pub unsafe fn prep_raw_closure<U, R>(
+    closure: *mut ffi_raw_closure,
+    cif: *mut ffi_cif,
+    callback: RawClosureCallback<U, R>,
+    userdata: *const U,
+    code: CodePtr,
+) -> Result<()> {
+    let status = raw::ffi_prep_raw_closure_loc(
+        closure,
+        cif,
+        Some(mem::transmute::<
+            RawClosureCallback<U, R>,
+            RawClosureRawCallback,
+        >(callback)),
+        userdata as *mut c_void,
+        code.as_mut_ptr(),
+    );
+
+    // Where the raw API is not native, libffi prepares an ordinary closure
+    // whose userdata is the raw closure, to convert the arguments before
+    // calling `callback`. It passes `code` as that userdata, which is not
+    // the closure when libffi uses static trampolines, so point it at the
+    // closure itself.
+    if !NATIVE_RAW_API && status == raw::ffi_status_FFI_OK {
+        set_this_closure(closure);
+    }
+
+    status_to_result(status, ())
+}
+
+// libffi-sys declares `this_closure` only where the raw API is not native,
+// by architecture rather than from `FFI_NATIVE_RAW_API`, so check that the
+// two agree.
+const _: () = assert!(NATIVE_RAW_API == cfg!(target_arch = "x86"));
+
+#[cfg(not(target_arch = "x86"))]
+unsafe fn set_this_closure(closure: *mut ffi_raw_closure) {
+    (*closure).this_closure = closure.cast();
+}
+
+#[cfg(target_arch = "x86")]
+unsafe fn set_this_closure(_closure: *mut ffi_raw_closure) {}
+
+/// The type of function called by a Go closure.
+///
+/// `C` is the type of the closure, which is either [`ffi_go_closure`] or a
+/// `#[repr(C)]` type whose first field is one, and `R` is the type of the
+/// result. The parameters are not typed, since they are passed as a C
+/// array of `void*`.
+pub type GoCallback<C, R> =
+    unsafe extern "C" fn(cif: &ffi_cif, result: &mut R, args: *const *const c_void, closure: &C);
+
+// libffi only defines the Go closure functions where it supports Go
+// closures, so elsewhere they are replaced by stubs that never call it.
+#[cfg(not(all(target_arch = "aarch64", any(windows, target_vendor = "apple"))))]
+use raw::{ffi_call_go, ffi_prep_go_closure};
+
+#[cfg(all(target_arch = "aarch64", any(windows, target_vendor = "apple")))]
+unsafe fn ffi_prep_go_closure(
+    _closure: *mut ffi_go_closure,
+    _cif: *mut ffi_cif,
+    _fun: Option<RawCallback>,
+) -> ffi_status {
+    raw::ffi_status_FFI_BAD_ABI
+}
+
+#[cfg(all(target_arch = "aarch64", any(windows, target_vendor = "apple")))]
+unsafe fn ffi_call_go(
+    _cif: *mut ffi_cif,
+    _fn: Option<unsafe extern "C" fn()>,
+    _rvalue: *mut c_void,
+    _avalue: *mut *mut c_void,
+    _closure: *mut c_void,
+) {
+    panic!("libffi does not support Go closures on this target");
+}
+
+/// Initializes a Go closure with a callback function.
+///
+/// Unlike the closures of [`prep_closure`], a Go closure needs no
+/// executable memory. Its code pointer, the `tramp` field, is shared by
+/// all Go closures, and expects the address of the closure in the static
+/// chain register, which [`call_go`] sets. The callback receives that
+/// address, so userdata is stored alongside the closure in a `#[repr(C)]`
+/// type `C` that starts with the [`ffi_go_closure`].
+///
+/// # Safety
+///
+/// The closure retains a reference to CIF `cif`, so that must
+/// still be live when the closure is used lest undefined behavior
+/// result. When the closure is called, the static chain must point to a
+/// valid `C` whose first field is `closure`.
+///
+/// # Result
+///
+/// `Ok(())` for success or `Err(e)` for failure. Where Go closures are not
+/// [supported](GO_CLOSURES), this fails with [`Error::Abi`].
+///
+/// # Examples
+///
+/// ```
+/// use std::os::raw::c_void;
+/// use std::ptr::addr_of_mut;
+/// use libffi::low::*;
+///
+/// #[repr(C)]
+/// struct Adder {
+///     closure: ffi_go_closure,
+///     addend: u64,
+/// }
+///
+/// unsafe extern "C" fn callback(_cif: &ffi_cif,
+///                               result: &mut u64,
+///                               args: *const *const c_void,
+///                               adder: &Adder)
+/// {
+///     *result = *(*args as *const u64) + adder.addend;
+/// }
+///
+/// if GO_CLOSURES {
+///     unsafe {
+///         let mut cif: ffi_cif = Default::default();
+///         let mut args = [addr_of_mut!(types::uint64)];
+///
+///         prep_cif(&mut cif, ffi_abi_FFI_DEFAULT_ABI, 1,
+///                  addr_of_mut!(types::uint64), args.as_mut_ptr()).unwrap();
+///
+///         let mut adder = Adder { closure: Default::default(), addend: 5 };
+///         prep_go_closure(addr_of_mut!(adder.closure), &mut cif, callback).unwrap();
+///
+///         let mut x = 6u64;
+///         let result: u64 = call_go(&mut cif,
+///                                   CodePtr(adder.closure.tramp),
+///                                   [addr_of_mut!(x).cast()].as_mut_ptr(),
+///                                   addr_of_mut!(adder).cast());
+///         assert_eq!(11, result);
+///     }
+/// }
+/// ```
+pub unsafe fn prep_go_closure<C, R>(
+    closure: *mut ffi_go_closure,
+    cif: *mut ffi_cif,
+    callback: GoCallback<C, R>,
+) -> Result<()> {
+    let status = ffi_prep_go_closure(
+        closure,
+        cif,
+        Some(mem::transmute::<GoCallback<C, R>, RawCallback>(callback)),
+    );
+    status_to_result(status, ())
+}
+
+/// Calls C function `fun` using the calling convention and types
+/// specified by the given CIF, with `closure` in the static chain
+/// register.
+///
+/// This calls a Go closure prepared by [`prep_go_closure`] when `fun` is
+/// its `tramp` field and `closure` is its address. Functions that do not
+/// use a static chain ignore `closure`. See [`prep_go_closure`] for an
+/// example, and [`call`] for how the result is read.
+///
+/// # Panics
+///
+/// Panics where Go closures are not [supported](GO_CLOSURES).
+///
+/// # Safety
+///
+/// As for [`call`], and `closure` must be the value `fun` expects in the
+/// static chain.
+pub unsafe fn call_go<R>(
+    cif: *mut ffi_cif,
+    fun: CodePtr,
+    args: *mut *mut c_void,
+    closure: *mut c_void,
+) -> R {
+    // SAFETY: It is up to the caller to ensure that the ffi_call_go is safe
+    // to perform.
+    unsafe {
+        call_with(cif, |result| {
+            ffi_call_go(cif, Some(*fun.as_safe_fun()), result, args, closure);
+        })
+    }
+}
+
 #[cfg(all(test, feature = "std"))]
 mod test {
     use std::ptr::{addr_of_mut, null_mut};
+    use std::string::ToString;
 
     use super::*;
 
@@ -939,6 +1332,25 @@ mod test {
         }
     }
 
+    #[test]
+    fn maps_statuses_to_errors() {
+        assert_eq!(Ok(1), status_to_result(raw::ffi_status_FFI_OK, 1));
+        assert_eq!(
+            Err(Error::Typedef),
+            status_to_result(raw::ffi_status_FFI_BAD_TYPEDEF, ())
+        );
+        assert_eq!(
+            Err(Error::Abi),
+            status_to_result(raw::ffi_status_FFI_BAD_ABI, ())
+        );
+        assert_eq!(
+            Err(Error::ArgType),
+            status_to_result(raw::ffi_status_FFI_BAD_ARGTYPE, ())
+        );
+        assert_eq!(Err(Error::Unknown(42)), status_to_result(42, ()));
+        assert_eq!("unknown libffi status 42", Error::Unknown(42).to_string());
+    }
+
     #[test]
     #[cfg_attr(miri, ignore = "Miri cannot access libffi's extern type statics")]
     fn test_get_struct_offsets() {
@@ -11,7 +11,8 @@ use super::types::Type;
 /// `void(*)()`). Add argument types to the function type with the
 /// [`Builder::arg`] and [`args`](Builder::args) methods. Set the result type
 /// with [`Builder::res`]. Change the calling convention, if necessary,
-/// with [`Builder::abi`].
+/// with [`Builder::abi`]. To build a variadic CIF, mark how many of the
+/// argument types are fixed with [`Builder::variadic`].
 ///
 /// Once the builder is configured, construct a `Cif` with
 /// [`Builder::into_cif`] or a closure with [`Builder::into_closure`],
@@ -61,6 +62,7 @@ pub struct Builder {
     args: alloc::vec::Vec<Type>,
     res: Type,
     abi: super::FfiAbi,
+    fixed_args: Option<usize>,
 }
 
 impl Default for Builder {
@@ -76,6 +78,7 @@ impl Builder {
             args: alloc::vec![],
             res: Type::void(),
             abi: super::ffi_abi_FFI_DEFAULT_ABI,
+            fixed_args: None,
         }
     }
 
@@ -106,16 +109,44 @@ impl Builder {
         self
     }
 
+    /// Makes the function type variadic.
+    ///
+    /// The first `fixed_args` argument types are the fixed arguments,
+    /// and the remaining ones are the types of the variadic arguments.
+    /// The CIF is then prepared with [`Cif::try_new_variadic_with_abi`],
+    /// and closures built from it can read their variadic arguments with
+    /// [`VarArgs`](super::VarArgs).
+    ///
+    /// [`Cif::try_new_variadic_with_abi`]: super::Cif::try_new_variadic_with_abi
+    pub fn variadic(mut self, fixed_args: usize) -> Self {
+        self.fixed_args = Some(fixed_args);
+        self
+    }
+
     /// Builds a CIF.
     pub fn into_cif(self) -> super::Cif {
-        self.try_into_cif().expect("low::prep_cif")
+        self.try_into_cif_detailed().expect("low::prep_cif")
     }
 
     /// Attempts to build a CIF.
     ///
-    /// This is the fallible counterpart to [`Builder::into_cif`].
+    /// This is the fallible counterpart to [`Builder::into_cif`]. See
+    /// [`Builder::try_into_cif_detailed`] for an error that names the type
+    /// libffi rejected.
     pub fn try_into_cif(self) -> crate::low::Result<super::Cif> {
-        super::Cif::try_new_with_abi(self.args, self.res, self.abi)
+        self.try_into_cif_detailed()
+            .map_err(crate::low::Error::from)
+    }
+
+    /// Attempts to build a CIF, as [`Builder::try_into_cif`] does, reporting
+    /// errors with the type that caused them.
+    pub fn try_into_cif_detailed(self) -> Result<super::Cif, super::Error> {
+        match self.fixed_args {
+            Some(fixed_args) => super::Cif::try_new_variadic_with_abi_detailed(
+                self.args, fixed_args, self.res, self.abi,
+            ),
+            None => super::Cif::try_new_with_abi_detailed(self.args, self.res, self.abi),
+        }
     }
 
     /// Builds an immutable closure.
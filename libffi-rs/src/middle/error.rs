@@ -0,0 +1,90 @@
+use core::fmt;
+
+use super::Type;
+use crate::low;
+
+/// An error from preparing a [CIF](super::Cif), with the type that caused it
+/// when it is known.
+///
+/// # Examples
+///
+/// ```
+/// use libffi::low;
+/// use libffi::middle::{Cif, Culprit, Type};
+///
+/// let empty = Type::structure([]);
+/// let error = Cif::try_new_detailed([Type::u32(), empty.clone()], Type::void()).unwrap_err();
+///
+/// assert_eq!(low::Error::Typedef, error.kind());
+/// assert_eq!(
+///     Some(&Culprit::Arg { index: 1, type_: empty }),
+///     error.culprit(),
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    kind: low::Error,
+    culprit: Option<Culprit>,
+}
+
+/// The type that caused an [`Error`].
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Culprit {
+    /// An argument type.
+    Arg {
+        /// The position of the argument.
+        index: usize,
+        /// The argument’s type.
+        type_: Type,
+    },
+    /// The result type.
+    Result(Type),
+}
+
+impl Error {
+    pub(crate) fn new(kind: low::Error, culprit: Option<Culprit>) -> Self {
+        Error { kind, culprit }
+    }
+
+    /// Returns the error reported by libffi, or the one libffi would have
+    /// reported.
+    pub fn kind(&self) -> low::Error {
+        self.kind
+    }
+
+    /// Returns the type that caused the error, if it is known.
+    pub fn culprit(&self) -> Option<&Culprit> {
+        self.culprit.as_ref()
+    }
+}
+
+impl From<low::Error> for Error {
+    fn from(kind: low::Error) -> Self {
+        Error::new(kind, None)
+    }
+}
+
+impl From<Error> for low::Error {
+    fn from(error: Error) -> Self {
+        error.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match &self.culprit {
+            Some(Culprit::Arg { index, type_ }) => write!(f, " in argument {index} ({type_})"),
+            Some(Culprit::Result(type_)) => write!(f, " in the result type ({type_})"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
@@ -0,0 +1,262 @@
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use alloc::boxed::Box;
+use core::ffi::c_void;
+use core::ptr::{self, NonNull};
+
+use super::{Cif, CodePtr, Ret};
+use crate::low;
+
+/// A [CIF](Cif) with preallocated storage for the values of its arguments,
+/// for calling a function repeatedly without allocating.
+///
+/// [`Cif::call`] takes an array of pointers to the arguments, which callers
+/// usually build for every call. A `CallFrame` instead owns a buffer with
+/// a slot for each argument, laid out for the argument types, and the array
+/// of pointers to those slots. Arguments are stored into their slots with
+/// [`CallFrame::set`], and remain there between calls, so only the
+/// arguments that change need to be set again.
+///
+/// # Examples
+///
+/// ```
+/// use libffi::middle::{CallFrame, Cif, CodePtr, Type};
+///
+/// extern "C" fn scale(x: f64, factor: u32) -> f64 {
+///     x * factor as f64
+/// }
+///
+/// let cif = Cif::new([Type::f64(), Type::u32()], Type::f64());
+/// let mut frame = CallFrame::new(cif);
+/// frame.set(1, 3u32);
+///
+/// let mut total = 0.0;
+/// for x in [0.5, 1.5, 2.0] {
+///     frame.set(0, x);
+///     total += unsafe { frame.call::<f64>(CodePtr(scale as *mut _)) };
+/// }
+///
+/// assert_eq!(12.0, total);
+/// ```
+#[derive(Debug)]
+pub struct CallFrame {
+    cif: Cif,
+    storage: NonNull<u8>,
+    layout: Layout,
+    offsets: Box<[usize]>,
+    args: Box<[*mut c_void]>,
+}
+
+impl CallFrame {
+    /// Creates a frame for calling functions through `cif`, with every
+    /// argument initially zeroed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the argument types are too large to be stored together.
+    pub fn new(cif: Cif) -> Self {
+        let mut offsets = alloc::vec::Vec::with_capacity(cif.nargs());
+        let mut layout = Layout::new::<()>();
+        for type_ in cif.arg_types() {
+            let slot = Layout::from_size_align(type_.size(), type_.alignment().max(1))
+                .expect("CallFrame::new: argument types are too large");
+            let (extended, offset) = layout
+                .extend(slot)
+                .expect("CallFrame::new: argument types are too large");
+            layout = extended;
+            offsets.push(offset);
+        }
+        // Allocations must not be empty, so every frame stores at least
+        // one byte.
+        let layout = Layout::from_size_align(layout.size().max(1), layout.align())
+            .expect("CallFrame::new: argument types are too large");
+
+        let storage = NonNull::new(unsafe { alloc_zeroed(layout) })
+            .unwrap_or_else(|| handle_alloc_error(layout));
+        let args = offsets
+            .iter()
+            .map(|&offset| unsafe { storage.as_ptr().add(offset) }.cast::<c_void>())
+            .collect();
+
+        Self {
+            cif,
+            storage,
+            layout,
+            offsets: offsets.into_boxed_slice(),
+            args,
+        }
+    }
+
+    /// Returns the CIF this frame calls through.
+    pub fn cif(&self) -> &Cif {
+        &self.cif
+    }
+
+    /// Stores `value` as the argument at `index`, for the following calls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no argument at `index`, or if `T` does not have
+    /// the size of its type.
+    pub fn set<T: Copy>(&mut self, index: usize, value: T) {
+        let type_ = &self.cif.arg_types()[index];
+        assert_eq!(
+            type_.size(),
+            core::mem::size_of::<T>(),
+            "CallFrame::set: argument {index} has a different size"
+        );
+
+        unsafe {
+            let slot = self.storage.as_ptr().add(self.offsets[index]);
+            ptr::write_unaligned(slot.cast::<T>(), value);
+        }
+    }
+
+    /// Returns a pointer to the storage of the argument at `index`, which
+    /// is aligned for its type and valid until the frame is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no argument at `index`.
+    pub fn arg_ptr(&mut self, index: usize) -> *mut c_void {
+        unsafe { self.storage.as_ptr().add(self.offsets[index]) }.cast()
+    }
+
+    /// Points the argument array at the slots again, and returns it.
+    ///
+    /// libffi may replace the pointer to an argument with a pointer to a
+    /// copy on the stack of the call, as it does on x86-64 for structures
+    /// larger than 16 bytes, so the array is rebuilt before every call.
+    fn args(&mut self) -> *mut *mut c_void {
+        for (arg, &offset) in self.args.iter_mut().zip(&*self.offsets) {
+            *arg = unsafe { self.storage.as_ptr().add(offset) }.cast();
+        }
+        self.args.as_mut_ptr()
+    }
+
+    /// Calls the C function `fun` with the stored arguments.
+    ///
+    /// As for [`Cif::call`], `R` must be the result type of the CIF.
+    ///
+    /// # Safety
+    ///
+    /// The function must have the signature of the CIF, and the stored
+    /// arguments must be valid values of the argument types.
+    pub unsafe fn call<R>(&mut self, fun: CodePtr) -> R {
+        let args = self.args();
+        low::call::<R>(self.cif.as_raw_ptr(), fun, args)
+    }
+
+    /// Calls the C function `fun` with the stored arguments, writing the
+    /// result to `ret`.
+    ///
+    /// See [`Cif::call_return_into`] for how the result is written.
+    ///
+    /// # Safety
+    ///
+    /// The function must have the signature of the CIF, the stored
+    /// arguments must be valid values of the argument types, and `ret`
+    /// must be valid for the result as for [`Cif::call_return_into`].
+    pub unsafe fn call_return_into(&mut self, fun: CodePtr, ret: Ret) {
+        let args = self.args();
+        low::call_return_into(self.cif.as_raw_ptr(), fun, args, ret.0);
+    }
+}
+
+impl Drop for CallFrame {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.storage.as_ptr(), self.layout) }
+    }
+}
+
+// The frame owns its storage, which is only written through `&mut self`.
+unsafe impl Send for CallFrame {}
+unsafe impl Sync for CallFrame {}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::middle::Type;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Pair {
+        tag: u8,
+        value: f64,
+    }
+
+    extern "C" fn weigh(pair: Pair, scale: u16, offset: i64) -> f64 {
+        pair.tag as f64 * pair.value * scale as f64 + offset as f64
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn call_frame() {
+        let pair = Type::structure([Type::u8(), Type::f64()]);
+        let cif = Cif::new([pair, Type::u16(), Type::i64()], Type::f64());
+        let mut frame = CallFrame::new(cif);
+
+        for index in 0..3 {
+            let slot = frame.arg_ptr(index) as usize;
+            assert_eq!(0, slot % frame.cif().arg_types()[index].alignment());
+        }
+
+        frame.set(0, Pair { tag: 2, value: 1.5 });
+        frame.set(1, 10u16);
+        frame.set(2, -1i64);
+        assert_eq!(29.0, unsafe { frame.call::<f64>(CodePtr(weigh as *mut _)) });
+
+        frame.set(2, 1i64);
+        assert_eq!(31.0, unsafe { frame.call::<f64>(CodePtr(weigh as *mut _)) });
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Triple {
+        a: u64,
+        b: u64,
+        c: u64,
+    }
+
+    extern "C" fn sum(triple: Triple, offset: u64) -> u64 {
+        triple.a + triple.b + triple.c + offset
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn call_frame_with_large_structure() {
+        // libffi passes structures larger than 16 bytes through a copy,
+        // whose address it may write into the argument array.
+        let triple = Type::structure([Type::u64(), Type::u64(), Type::u64()]);
+        let cif = Cif::new([triple, Type::u64()], Type::u64());
+        let mut frame = CallFrame::new(cif);
+
+        frame.set(0, Triple { a: 1, b: 2, c: 3 });
+        frame.set(1, 4u64);
+        assert_eq!(10, unsafe { frame.call::<u64>(CodePtr(sum as *mut _)) });
+
+        frame.set(
+            0,
+            Triple {
+                a: 10,
+                b: 20,
+                c: 30,
+            },
+        );
+        assert_eq!(64, unsafe { frame.call::<u64>(CodePtr(sum as *mut _)) });
+        frame.set(1, 0u64);
+        assert_eq!(60, unsafe { frame.call::<u64>(CodePtr(sum as *mut _)) });
+    }
+
+    #[test]
+    #[should_panic(expected = "argument 1 has a different size")]
+    fn call_frame_checks_sizes() {
+        let cif = Cif::new([Type::u8(), Type::u32()], Type::void());
+        CallFrame::new(cif).set(1, 0u64);
+    }
+}
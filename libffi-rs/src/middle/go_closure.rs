@@ -0,0 +1,261 @@
+use alloc::boxed::Box;
+use core::any::Any;
+use core::ffi::c_void;
+use core::mem;
+
+use super::{Arg, Callback, Cif, CodePtr};
+use crate::low;
+
+/// A Go closure with its userdata, laid out as libffi passes it to
+/// [`dispatch`].
+#[derive(Debug)]
+#[repr(C)]
+struct GoClosureData {
+    closure: low::ffi_go_closure,
+    callback: unsafe extern "C" fn(),
+    userdata: *const c_void,
+}
+
+/// Calls the callback of a Go closure with its userdata.
+unsafe extern "C" fn dispatch<U, R>(
+    cif: &low::ffi_cif,
+    result: &mut R,
+    args: *const *const c_void,
+    data: &GoClosureData,
+) {
+    let callback = mem::transmute::<unsafe extern "C" fn(), Callback<U, R>>(data.callback);
+    callback(cif, result, args, &*data.userdata.cast::<U>());
+}
+
+/// A Go closure, which owns its `Send + Sync` userdata and needs no
+/// executable memory.
+///
+/// Where a [`ClosureOwned`](super::ClosureOwned) writes a trampoline that
+/// loads its userdata, a Go closure is called through a code pointer that
+/// is shared by all Go closures, with the address of the closure in the
+/// platform’s static chain register. This works where writable memory
+/// cannot be made executable, but only callers that set the static chain
+/// can call it: [`GoClosure::call`], [`Cif::call_go`], or code that calls
+/// Go function values. Where libffi does not support Go closures, as
+/// reported by [`low::GO_CLOSURES`], creating one fails with
+/// [`low::Error::Abi`].
+///
+/// # Examples
+///
+/// ```
+/// use std::os::raw::c_void;
+///
+/// use libffi::low;
+/// use libffi::middle::*;
+///
+/// unsafe extern "C" fn callback(
+///     _cif: &low::ffi_cif,
+///     result: &mut u64,
+///     args: *const *const c_void,
+///     userdata: &u64,
+/// ) {
+///     *result = *(*args as *const u64) + userdata;
+/// }
+///
+/// let cif = Cif::new([Type::u64()], Type::u64());
+/// match GoClosure::try_new(cif, callback, 10u64) {
+///     Ok(closure) => {
+///         let result: u64 = unsafe { closure.call(&[arg(&5u64)]) };
+///         assert_eq!(15, result);
+///     }
+///     Err(error) => {
+///         assert!(!low::GO_CLOSURES);
+///         assert_eq!(low::Error::Abi, error);
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct GoClosure {
+    data: Box<GoClosureData>,
+    code: CodePtr,
+    cif: Box<Cif>,
+    _userdata: Box<dyn Any + Send + Sync>,
+}
+
+// The closure only hands out shared references to its `Send + Sync`
+// userdata, and neither the closure nor its CIF are modified after they
+// have been prepared.
+unsafe impl Send for GoClosure {}
+unsafe impl Sync for GoClosure {}
+
+impl GoClosure {
+    /// Creates a new Go closure with owned, shared userdata.
+    ///
+    /// # Arguments
+    ///
+    /// - `cif` — describes the calling convention and argument and
+    ///   result types
+    /// - `callback` — the function to call when the closure is invoked
+    /// - `userdata` — the value to pass to `callback` along with the
+    ///   arguments when the closure is called
+    ///
+    /// # Result
+    ///
+    /// The new closure.
+    pub fn new<U: Any + Send + Sync, R>(cif: Cif, callback: Callback<U, R>, userdata: U) -> Self {
+        Self::try_new(cif, callback, userdata).expect("low::prep_go_closure")
+    }
+
+    /// Attempts to create a new Go closure with owned, shared userdata.
+    ///
+    /// This is the fallible counterpart to [`GoClosure::new`].
+    pub fn try_new<U: Any + Send + Sync, R>(
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: U,
+    ) -> low::Result<Self> {
+        let cif = Box::new(cif);
+        let userdata = Box::new(userdata);
+        let mut data = Box::new(GoClosureData {
+            closure: Default::default(),
+            callback: unsafe { mem::transmute::<Callback<U, R>, unsafe extern "C" fn()>(callback) },
+            userdata: &*userdata as *const U as *const c_void,
+        });
+
+        unsafe {
+            low::prep_go_closure::<GoClosureData, R>(
+                &mut data.closure,
+                cif.as_raw_ptr(),
+                dispatch::<U, R>,
+            )?;
+        }
+
+        Ok(Self {
+            code: CodePtr(data.closure.tramp),
+            data,
+            cif,
+            _userdata: userdata,
+        })
+    }
+
+    /// Returns the CIF the closure is called through.
+    pub fn cif(&self) -> &Cif {
+        &self.cif
+    }
+
+    /// Obtains the code pointer shared by Go closures, which must be called
+    /// with [`GoClosure::as_raw_ptr`] in the static chain register.
+    pub fn code_ptr(&self) -> &unsafe extern "C" fn() {
+        self.code.as_fun()
+    }
+
+    /// Returns the address of the underlying [`low::ffi_go_closure`],
+    /// which is the value of the static chain when calling it.
+    pub fn as_raw_ptr(&self) -> *mut low::ffi_go_closure {
+        &self.data.closure as *const _ as *mut _
+    }
+
+    /// Calls the closure with the given arguments.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of arguments does not match the CIF.
+    ///
+    /// # Safety
+    ///
+    /// The arguments must match the argument types of the CIF, and `R`
+    /// must be its result type.
+    pub unsafe fn call<R>(&self, args: &[Arg]) -> R {
+        self.cif.call_go(self.code, args, self.as_raw_ptr().cast())
+    }
+}
+
+impl Cif {
+    /// Calls a function with the given arguments, with `closure` in the
+    /// static chain register.
+    ///
+    /// This calls Go closures, such as a [`GoClosure`] when `fun` is its
+    /// code pointer and `closure` its [raw pointer](GoClosure::as_raw_ptr).
+    /// Functions that do not use a static chain ignore `closure`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of arguments does not match the CIF, or where
+    /// libffi does not support [Go closures](low::GO_CLOSURES).
+    ///
+    /// # Safety
+    ///
+    /// As for [`Cif::call`], and `closure` must be the value `fun` expects
+    /// in the static chain.
+    pub unsafe fn call_go<R>(&self, fun: CodePtr, args: &[Arg], closure: *mut c_void) -> R {
+        assert_eq!(
+            self.nargs(),
+            args.len(),
+            "Cif::call_go: passed wrong number of arguments"
+        );
+
+        low::call_go::<R>(
+            self.as_raw_ptr(),
+            fun,
+            args.as_ptr() as *mut *mut c_void,
+            closure,
+        )
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::middle::{arg, Type};
+    use core::ptr::null_mut;
+    use std::string::String;
+
+    unsafe extern "C" fn greet(
+        _cif: &low::ffi_cif,
+        result: &mut usize,
+        args: *const *const c_void,
+        userdata: &String,
+    ) {
+        *result = userdata.len() * *(*args as *const u16) as usize;
+    }
+
+    extern "C" fn subtract(a: i64, b: i32) -> i64 {
+        a - b as i64
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri does not support libffi's Go closures")]
+    fn call_go_closure() {
+        let cif = Cif::new([Type::u16()], Type::usize());
+        let closure = GoClosure::try_new(cif, greet, String::from("hello"));
+        assert_eq!(low::GO_CLOSURES, closure.is_ok());
+        let Ok(closure) = closure else {
+            return;
+        };
+
+        let result: usize = unsafe { closure.call(&[arg(&3u16)]) };
+        assert_eq!(15, result);
+
+        // The closure can be moved, and called from another thread.
+        let result = std::thread::spawn(move || unsafe { closure.call::<usize>(&[arg(&2u16)]) })
+            .join()
+            .unwrap();
+        assert_eq!(10, result);
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn call_go_without_static_chain() {
+        if !low::GO_CLOSURES {
+            return;
+        }
+
+        let cif = Cif::new([Type::i64(), Type::i32()], Type::i64());
+        let result: i64 = unsafe {
+            cif.call_go(
+                CodePtr(subtract as *mut _),
+                &[arg(&10i64), arg(&-4i32)],
+                null_mut(),
+            )
+        };
+        assert_eq!(14, result);
+    }
+}
@@ -12,24 +12,74 @@ use crate::low;
 pub use crate::low::{ffi_abi as FfiAbi, ffi_abi_FFI_DEFAULT_ABI, Callback, CallbackMut, CodePtr};
 use alloc::boxed::Box;
 use core::any::Any;
-use core::ffi::{c_uint, c_void};
+use core::ffi::{c_int, c_uint, c_void};
+use core::hash::{Hash, Hasher};
 use core::marker::PhantomData;
 use core::ptr::{null_mut, NonNull};
 
 mod types;
 mod util;
-pub use types::Type;
+pub use types::{Fields, OpaqueClass, Type, TypeKind};
 
 mod builder;
 pub use builder::Builder;
 
+mod value;
+pub use value::{CallError, Value};
+
+mod varargs;
+pub use varargs::VarArgs;
+
+mod frame;
+pub use frame::CallFrame;
+
+mod raw_args;
+pub use raw_args::RawArgs;
+
+mod go_closure;
+pub use go_closure::GoClosure;
+
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(feature = "std")]
+pub use pool::{ClosurePool, PoolStats};
+
+mod signature;
+pub use signature::{ParseError, ParseErrorKind};
+
+mod error;
+pub use error::{Culprit, Error};
+
 #[derive(Debug)]
-pub(crate) struct ClosureAlloc(NonNull<low::ffi_closure>);
+pub(crate) struct ClosureAlloc {
+    closure: NonNull<low::ffi_closure>,
+    code: CodePtr,
+    /// The pool to return the allocation to instead of freeing it.
+    #[cfg(feature = "std")]
+    pool: Option<ClosurePool>,
+}
+
+impl ClosureAlloc {
+    fn new() -> low::Result<Self> {
+        let (closure, code) = low::try_closure_alloc().ok_or(low::Error::Allocation)?;
+        Ok(ClosureAlloc {
+            closure: NonNull::new(closure).ok_or(low::Error::Allocation)?,
+            code,
+            #[cfg(feature = "std")]
+            pool: None,
+        })
+    }
+}
 
 impl Drop for ClosureAlloc {
     fn drop(&mut self) {
+        #[cfg(feature = "std")]
+        if let Some(pool) = self.pool.take() {
+            return pool.release(self.closure, self.code);
+        }
+
         unsafe {
-            low::closure_free(self.0.as_ptr());
+            low::closure_free(self.closure.as_ptr());
         }
     }
 }
@@ -131,8 +181,15 @@ pub struct Cif {
     cif: low::ffi_cif,
     args: types::TypeArray,
     result: Type,
+    fixed_args: Option<usize>,
 }
 
+// The `ffi_cif` only points into the types the `Cif` owns, and neither
+// it nor they are written after `prep_cif`, since calling through a CIF
+// only reads it.
+unsafe impl Send for Cif {}
+unsafe impl Sync for Cif {}
+
 // To clone a Cif we need to clone the types and then make sure the new
 // ffi_cif refers to the clones of the types.
 impl Clone for Cif {
@@ -141,6 +198,7 @@ impl Clone for Cif {
             cif: self.cif,
             args: self.args.clone(),
             result: self.result.clone(),
+            fixed_args: self.fixed_args,
         };
 
         copy.cif.arg_types = copy.args.as_raw_ptr();
@@ -150,6 +208,29 @@ impl Clone for Cif {
     }
 }
 
+// Two CIFs are equal if they have the same ABI, the same number of fixed
+// arguments if they are variadic, and structurally equal argument and result
+// types.
+impl PartialEq for Cif {
+    fn eq(&self, other: &Self) -> bool {
+        self.cif.abi == other.cif.abi
+            && self.fixed_args == other.fixed_args
+            && self.args == other.args
+            && self.result == other.result
+    }
+}
+
+impl Eq for Cif {}
+
+impl Hash for Cif {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cif.abi.hash(state);
+        self.fixed_args.hash(state);
+        self.args.hash(state);
+        self.result.hash(state);
+    }
+}
+
 impl Cif {
     /// Creates a new [CIF](Cif) for the given argument and result
     /// types with the default ABI.
@@ -163,19 +244,32 @@ impl Cif {
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
     {
-        Self::try_new(args, result).expect("low::prep_cif")
+        Self::try_new_detailed(args, result).expect("low::prep_cif")
     }
 
     /// Attempts to create a new [CIF](Cif) for the given argument and result
     /// types with the default ABI.
     ///
-    /// This is the fallible counterpart to [`Cif::new`].
+    /// This is the fallible counterpart to [`Cif::new`]. See
+    /// [`Cif::try_new_detailed`] for an error that names the type libffi
+    /// rejected.
     pub fn try_new<I>(args: I, result: Type) -> low::Result<Self>
     where
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
     {
-        Self::try_new_with_abi(args, result, ffi_abi_FFI_DEFAULT_ABI)
+        Self::try_new_detailed(args, result).map_err(low::Error::from)
+    }
+
+    /// Attempts to create a new [CIF](Cif) for the given argument and result
+    /// types with the default ABI, as [`Cif::try_new`] does, reporting
+    /// errors with the type that caused them.
+    pub fn try_new_detailed<I>(args: I, result: Type) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = Type>,
+        I::IntoIter: ExactSizeIterator<Item = Type>,
+    {
+        Self::try_new_with_abi_detailed(args, result, ffi_abi_FFI_DEFAULT_ABI)
     }
 
     /// Creates a new [CIF](Cif) for the given argument and result
@@ -183,19 +277,39 @@ impl Cif {
     ///
     /// Takes ownership of the argument and result [`Type`]s, because
     /// the resulting [`Cif`] retains references to them.
+    ///
+    /// Structures and unions keep the layout they were given for the
+    /// default ABI when they were constructed; libffi does not lay them out
+    /// again for `abi`. The layout of a structure depends on the ABI only
+    /// on targets such as 32-bit PowerPC, whose ABIs differ in the size of
+    /// `long double`, so a structure with a `long double` field cannot be
+    /// passed with such an ABI.
     pub fn new_with_abi<I>(args: I, result: Type, abi: FfiAbi) -> Self
     where
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
     {
-        Self::try_new_with_abi(args, result, abi).expect("low::prep_cif")
+        Self::try_new_with_abi_detailed(args, result, abi).expect("low::prep_cif")
     }
 
     /// Attempts to create a new [CIF](Cif) for the given argument and result
     /// types with the specified ABI.
     ///
-    /// This is the fallible counterpart to [`Cif::new_with_abi`].
+    /// This is the fallible counterpart to [`Cif::new_with_abi`]. See
+    /// [`Cif::try_new_with_abi_detailed`] for an error that names the type
+    /// libffi rejected.
     pub fn try_new_with_abi<I>(args: I, result: Type, abi: FfiAbi) -> low::Result<Self>
+    where
+        I: IntoIterator<Item = Type>,
+        I::IntoIter: ExactSizeIterator<Item = Type>,
+    {
+        Self::try_new_with_abi_detailed(args, result, abi).map_err(low::Error::from)
+    }
+
+    /// Attempts to create a new [CIF](Cif) for the given argument and result
+    /// types with the specified ABI, as [`Cif::try_new_with_abi`] does,
+    /// reporting errors with the type that caused them.
+    pub fn try_new_with_abi_detailed<I>(args: I, result: Type, abi: FfiAbi) -> Result<Self, Error>
     where
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
@@ -204,6 +318,9 @@ impl Cif {
         let nargs = args.len();
         let nargs = c_uint::try_from(nargs).map_err(|_| low::Error::ArgType)?;
         let args = types::TypeArray::new(args);
+        if let Some(culprit) = Self::find_unpassable(&args, &result) {
+            return Err(Error::new(low::Error::Typedef, Some(culprit)));
+        }
         let mut cif = low::ffi_cif::default();
 
         unsafe {
@@ -213,12 +330,18 @@ impl Cif {
                 nargs as usize,
                 result.as_raw_ptr(),
                 args.as_raw_ptr(),
-            )?;
+            )
+            .map_err(|kind| Self::locate_error(kind, &args, &result, None))?;
         }
 
         // Note that cif retains references to args and result,
         // which is why we hold onto them here.
-        Ok(Self { cif, args, result })
+        Ok(Self {
+            cif,
+            args,
+            result,
+            fixed_args: None,
+        })
     }
 
     /// Creates a new variadic [CIF](Cif) for the given argument and result
@@ -233,19 +356,36 @@ impl Cif {
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
     {
-        Self::try_new_variadic(args, fixed_args, result).expect("low::prep_cif_var")
+        Self::try_new_variadic_detailed(args, fixed_args, result).expect("low::prep_cif_var")
     }
 
     /// Attempts to create a new variadic [CIF](Cif) for the given argument and
     /// result types with the default ABI.
     ///
-    /// This is the fallible counterpart to [`Cif::new_variadic`].
+    /// This is the fallible counterpart to [`Cif::new_variadic`]. See
+    /// [`Cif::try_new_variadic_detailed`] for an error that names the type
+    /// libffi rejected.
     pub fn try_new_variadic<I>(args: I, fixed_args: usize, result: Type) -> low::Result<Self>
     where
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
     {
-        Self::try_new_variadic_with_abi(args, fixed_args, result, ffi_abi_FFI_DEFAULT_ABI)
+        Self::try_new_variadic_detailed(args, fixed_args, result).map_err(low::Error::from)
+    }
+
+    /// Attempts to create a new variadic [CIF](Cif) for the given argument and
+    /// result types with the default ABI, as [`Cif::try_new_variadic`]
+    /// does, reporting errors with the type that caused them.
+    pub fn try_new_variadic_detailed<I>(
+        args: I,
+        fixed_args: usize,
+        result: Type,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = Type>,
+        I::IntoIter: ExactSizeIterator<Item = Type>,
+    {
+        Self::try_new_variadic_with_abi_detailed(args, fixed_args, result, ffi_abi_FFI_DEFAULT_ABI)
     }
 
     /// Creates a new variadic [CIF](Cif) for the given argument and result
@@ -258,19 +398,40 @@ impl Cif {
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
     {
-        Self::try_new_variadic_with_abi(args, fixed_args, result, abi).expect("low::prep_cif_var")
+        Self::try_new_variadic_with_abi_detailed(args, fixed_args, result, abi)
+            .expect("low::prep_cif_var")
     }
 
     /// Attempts to create a new variadic [CIF](Cif) for the given argument and
     /// result types with the specified ABI.
     ///
     /// This is the fallible counterpart to [`Cif::new_variadic_with_abi`].
+    /// See [`Cif::try_new_variadic_with_abi_detailed`] for an error that
+    /// names the type libffi rejected.
     pub fn try_new_variadic_with_abi<I>(
         args: I,
         fixed_args: usize,
         result: Type,
         abi: FfiAbi,
     ) -> low::Result<Self>
+    where
+        I: IntoIterator<Item = Type>,
+        I::IntoIter: ExactSizeIterator<Item = Type>,
+    {
+        Self::try_new_variadic_with_abi_detailed(args, fixed_args, result, abi)
+            .map_err(low::Error::from)
+    }
+
+    /// Attempts to create a new variadic [CIF](Cif) for the given argument and
+    /// result types with the specified ABI, as
+    /// [`Cif::try_new_variadic_with_abi`] does, reporting errors with the
+    /// type that caused them.
+    pub fn try_new_variadic_with_abi_detailed<I>(
+        args: I,
+        fixed_args: usize,
+        result: Type,
+        abi: FfiAbi,
+    ) -> Result<Self, Error>
     where
         I: IntoIterator<Item = Type>,
         I::IntoIter: ExactSizeIterator<Item = Type>,
@@ -281,10 +442,13 @@ impl Cif {
             || c_uint::try_from(fixed_args).is_err()
             || c_uint::try_from(nargs).is_err()
         {
-            return Err(low::Error::ArgType);
+            return Err(low::Error::ArgType.into());
         }
 
         let args = types::TypeArray::new(args);
+        if let Some(culprit) = Self::find_unpassable(&args, &result) {
+            return Err(Error::new(low::Error::Typedef, Some(culprit)));
+        }
         let mut cif: low::ffi_cif = Default::default();
 
         unsafe {
@@ -295,12 +459,78 @@ impl Cif {
                 nargs,
                 result.as_raw_ptr(),
                 args.as_raw_ptr(),
-            )?;
+            )
+            .map_err(|kind| Self::locate_error(kind, &args, &result, Some(fixed_args)))?;
         }
 
         // Note that cif retains references to args and result,
         // which is why we hold onto them here.
-        Ok(Self { cif, args, result })
+        Ok(Self {
+            cif,
+            args,
+            result,
+            fixed_args: Some(fixed_args),
+        })
+    }
+
+    /// Finds the first of the result and argument types that `bad` holds
+    /// for.
+    fn find_culprit(
+        args: &types::TypeArray,
+        result: &Type,
+        mut bad: impl FnMut(&Type) -> bool,
+    ) -> Option<Culprit> {
+        if bad(result) {
+            return Some(Culprit::Result(result.clone()));
+        }
+        let index = args.iter().position(&mut bad)?;
+        Some(Culprit::Arg {
+            index,
+            type_: args.as_slice()[index].clone(),
+        })
+    }
+
+    /// Finds the first of the result and argument types that libffi cannot
+    /// pass as C does.
+    fn find_unpassable(args: &types::TypeArray, result: &Type) -> Option<Culprit> {
+        if !result.passable() {
+            return Some(Culprit::Result(result.clone()));
+        }
+        let index = args.iter().position(|type_| !type_.passable_as_arg())?;
+        Some(Culprit::Arg {
+            index,
+            type_: args.as_slice()[index].clone(),
+        })
+    }
+
+    /// Adds to an error from libffi the type that libffi rejected, found
+    /// by repeating its checks.
+    fn locate_error(
+        kind: low::Error,
+        args: &types::TypeArray,
+        result: &Type,
+        fixed_args: Option<usize>,
+    ) -> Error {
+        let culprit = match kind {
+            // libffi rejects structures it cannot lay out, which are those
+            // left with a size of 0.
+            low::Error::Typedef => Self::find_culprit(args, result, |type_| type_.size() == 0),
+            // libffi rejects variadic arguments that C would promote.
+            low::Error::ArgType => fixed_args.and_then(|fixed_args| {
+                let promoted = |type_: &Type| match type_.kind() {
+                    TypeKind::Float => true,
+                    TypeKind::Int { bits, .. } => bits < c_int::BITS,
+                    _ => false,
+                };
+                let index = fixed_args + args.as_slice()[fixed_args..].iter().position(promoted)?;
+                Some(Culprit::Arg {
+                    index,
+                    type_: args.as_slice()[index].clone(),
+                })
+            }),
+            _ => None,
+        };
+        Error::new(kind, culprit)
     }
 
     /// Calls a function with the given arguments.
@@ -357,6 +587,71 @@ impl Cif {
         );
     }
 
+    /// Returns the ABI this CIF was prepared for.
+    pub fn abi(&self) -> FfiAbi {
+        self.cif.abi
+    }
+
+    /// Returns the argument types, including the variadic ones of a
+    /// variadic CIF.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{Cif, Type};
+    ///
+    /// let cif = Cif::new([Type::pointer(), Type::u32()], Type::void());
+    ///
+    /// assert_eq!([Type::pointer(), Type::u32()], cif.arg_types());
+    /// ```
+    pub fn arg_types(&self) -> &[Type] {
+        self.args.as_slice()
+    }
+
+    /// Returns the result type.
+    pub fn result_type(&self) -> &Type {
+        &self.result
+    }
+
+    /// Returns the number of arguments, including the variadic ones of a
+    /// variadic CIF.
+    pub fn nargs(&self) -> usize {
+        self.cif.nargs as usize
+    }
+
+    /// Returns the number of fixed arguments of a variadic CIF, or `None`
+    /// if the CIF is not variadic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{Cif, Type};
+    ///
+    /// let printf = Cif::new_variadic([Type::pointer(), Type::f64()], 1, Type::c_int());
+    ///
+    /// assert_eq!(Some(1), printf.fixed_args());
+    /// assert_eq!(2, printf.nargs());
+    /// assert_eq!(None, Cif::new([], Type::void()).fixed_args());
+    /// ```
+    pub fn fixed_args(&self) -> Option<usize> {
+        self.fixed_args
+    }
+
+    /// Returns whether this CIF was created by one of the variadic
+    /// constructors, such as [`Cif::new_variadic`].
+    pub fn is_variadic(&self) -> bool {
+        self.fixed_args.is_some()
+    }
+
+    /// Returns the number of bytes of stack space that libffi computed the
+    /// arguments need when the CIF was prepared.
+    ///
+    /// What this counts is up to libffi’s implementation for the ABI; it
+    /// is 0 on some platforms even when arguments are passed on the stack.
+    pub fn stack_bytes(&self) -> usize {
+        self.cif.bytes as usize
+    }
+
     /// Gets a raw pointer to the underlying [`low::ffi_cif`].
     ///
     /// This can be used for passing a `middle::Cif` to functions from the
@@ -420,8 +715,8 @@ impl Cif {
 /// ```
 #[derive(Debug)]
 pub struct Closure<'a> {
-    _cif: Box<Cif>,
-    _alloc: ClosureAlloc,
+    cif: Box<Cif>,
+    alloc: ClosureAlloc,
     code: CodePtr,
     _marker: PhantomData<&'a ()>,
 }
@@ -448,13 +743,21 @@ impl<'a> Closure<'a> {
     ///
     /// This is the fallible counterpart to [`Closure::new`].
     pub fn try_new<U, R>(cif: Cif, callback: Callback<U, R>, userdata: &'a U) -> low::Result<Self> {
+        Self::with_alloc(ClosureAlloc::new()?, cif, callback, userdata)
+    }
+
+    fn with_alloc<U, R>(
+        alloc: ClosureAlloc,
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: &'a U,
+    ) -> low::Result<Self> {
         let cif = Box::new(cif);
-        let (alloc, code) = low::try_closure_alloc().ok_or(low::Error::Allocation)?;
-        let alloc = ClosureAlloc(NonNull::new(alloc).ok_or(low::Error::Allocation)?);
+        let code = alloc.code;
 
         unsafe {
             low::prep_closure(
-                alloc.0.as_ptr(),
+                alloc.closure.as_ptr(),
                 cif.as_raw_ptr(),
                 callback,
                 userdata as *const U,
@@ -463,8 +766,8 @@ impl<'a> Closure<'a> {
         }
 
         Ok(Closure {
-            _cif: cif,
-            _alloc: alloc,
+            cif,
+            alloc,
             code,
             _marker: PhantomData,
         })
@@ -494,14 +797,22 @@ impl<'a> Closure<'a> {
         cif: Cif,
         callback: CallbackMut<U, R>,
         userdata: &'a mut U,
+    ) -> low::Result<Self> {
+        Self::with_alloc_mut(ClosureAlloc::new()?, cif, callback, userdata)
+    }
+
+    fn with_alloc_mut<U, R>(
+        alloc: ClosureAlloc,
+        cif: Cif,
+        callback: CallbackMut<U, R>,
+        userdata: &'a mut U,
     ) -> low::Result<Self> {
         let cif = Box::new(cif);
-        let (alloc, code) = low::try_closure_alloc().ok_or(low::Error::Allocation)?;
-        let alloc = ClosureAlloc(NonNull::new(alloc).ok_or(low::Error::Allocation)?);
+        let code = alloc.code;
 
         unsafe {
             low::prep_closure_mut(
-                alloc.0.as_ptr(),
+                alloc.closure.as_ptr(),
                 cif.as_raw_ptr(),
                 callback,
                 userdata as *mut U,
@@ -510,13 +821,94 @@ impl<'a> Closure<'a> {
         }
 
         Ok(Closure {
-            _cif: cif,
-            _alloc: alloc,
+            cif,
+            alloc,
             code,
             _marker: PhantomData,
         })
     }
 
+    /// Replaces the callback and immutable userdata of the closure,
+    /// keeping its code pointer.
+    ///
+    /// This prepares the closure’s allocation again for its CIF, so C code
+    /// that holds the code pointer calls `callback` with `userdata` from
+    /// then on.
+    ///
+    /// # Safety
+    ///
+    /// No call through the closure’s code pointer may be running, or start
+    /// on another thread, while the closure is rebound. libffi writes the
+    /// callback and userdata one after the other, so such a call could
+    /// receive the new callback with the old userdata, which may already
+    /// have been dropped. Copies of the code pointer, such as those
+    /// obtained from [`Closure::instantiate_code_ptr`], are not tied to the
+    /// `&mut` borrow, so the borrow checker cannot rule this out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::os::raw::c_void;
+    ///
+    /// use libffi::low;
+    /// use libffi::middle::*;
+    ///
+    /// unsafe extern "C" fn add(
+    ///     _cif: &low::ffi_cif,
+    ///     result: &mut u64,
+    ///     args: *const *const c_void,
+    ///     userdata: &u64,
+    /// ) {
+    ///     *result = *(*args as *const u64) + userdata;
+    /// }
+    ///
+    /// unsafe extern "C" fn multiply(
+    ///     _cif: &low::ffi_cif,
+    ///     result: &mut u64,
+    ///     args: *const *const c_void,
+    ///     userdata: &u64,
+    /// ) {
+    ///     *result = *(*args as *const u64) * userdata;
+    /// }
+    ///
+    /// let (two, three) = (2u64, 3u64);
+    /// let mut closure = Closure::new(Cif::new([Type::u64()], Type::u64()), add, &two);
+    /// let fun: extern "C" fn(u64) -> u64 = unsafe { *closure.instantiate_code_ptr() };
+    /// assert_eq!(7, fun(5));
+    ///
+    /// unsafe { closure.rebind(multiply, &three) };
+    /// assert_eq!(15, fun(5));
+    /// ```
+    pub unsafe fn rebind<U, R>(&mut self, callback: Callback<U, R>, userdata: &'a U) {
+        low::prep_closure(
+            self.alloc.closure.as_ptr(),
+            self.cif.as_raw_ptr(),
+            callback,
+            userdata as *const U,
+            self.code,
+        )
+        .expect("low::prep_closure");
+    }
+
+    /// Replaces the callback and mutable userdata of the closure, keeping
+    /// its code pointer.
+    ///
+    /// See [`Closure::rebind`] for when the new callback is called.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Closure::rebind`].
+    pub unsafe fn rebind_mut<U, R>(&mut self, callback: CallbackMut<U, R>, userdata: &'a mut U) {
+        low::prep_closure_mut(
+            self.alloc.closure.as_ptr(),
+            self.cif.as_raw_ptr(),
+            callback,
+            userdata as *mut U,
+            self.code,
+        )
+        .expect("low::prep_closure_mut");
+    }
+
     /// Obtains the callable code pointer for a closure.
     ///
     /// # Safety
@@ -542,6 +934,98 @@ impl<'a> Closure<'a> {
     }
 }
 
+/// A closure with immutable, `Sync` userdata, which can be shared with
+/// and called from other threads.
+///
+/// A [`Closure`] is neither `Send` nor `Sync`, as it cannot tell whether
+/// its userdata can be used from other threads. A `ClosureSync` is
+/// created only with `Sync` userdata, and is both.
+///
+/// # Examples
+///
+/// ```
+/// use std::os::raw::c_void;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// use libffi::low;
+/// use libffi::middle::*;
+///
+/// unsafe extern "C" fn callback(
+///     _cif: &low::ffi_cif,
+///     result: &mut u64,
+///     args: *const *const c_void,
+///     userdata: &AtomicU64,
+/// ) {
+///     *result = userdata.fetch_add(*(*args as *const u64), Ordering::Relaxed);
+/// }
+///
+/// let total = AtomicU64::new(0);
+/// let closure = ClosureSync::new(Cif::new([Type::u64()], Type::u64()), callback, &total);
+/// let add: &extern "C" fn(u64) -> u64 = unsafe { closure.instantiate_code_ptr() };
+///
+/// std::thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| add(5));
+///     }
+/// });
+/// assert_eq!(20, total.load(Ordering::Relaxed));
+/// ```
+#[derive(Debug)]
+pub struct ClosureSync<'a> {
+    untyped: Closure<'a>,
+}
+
+// The closure only passes its callback shared references to `Sync`
+// userdata, and is not modified after it has been prepared.
+unsafe impl Send for ClosureSync<'_> {}
+unsafe impl Sync for ClosureSync<'_> {}
+
+impl<'a> ClosureSync<'a> {
+    /// Creates a new closure with immutable, `Sync` userdata.
+    ///
+    /// See [`Closure::new`] for the arguments.
+    pub fn new<U: Sync, R>(cif: Cif, callback: Callback<U, R>, userdata: &'a U) -> Self {
+        Self::try_new(cif, callback, userdata).expect("low::prep_closure")
+    }
+
+    /// Attempts to create a new closure with immutable, `Sync` userdata.
+    ///
+    /// This is the fallible counterpart to [`ClosureSync::new`].
+    pub fn try_new<U: Sync, R>(
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: &'a U,
+    ) -> low::Result<Self> {
+        Ok(ClosureSync {
+            untyped: Closure::try_new(cif, callback, userdata)?,
+        })
+    }
+
+    /// Obtains the callable code pointer for a closure.
+    ///
+    /// # Safety
+    ///
+    /// The result needs to be transmuted to the correct type before
+    /// it can be called. If the type is wrong then undefined behavior
+    /// will result.
+    pub fn code_ptr(&self) -> &unsafe extern "C" fn() {
+        self.untyped.code_ptr()
+    }
+
+    /// Transmutes the callable code pointer for a closure to a reference
+    /// to any type. This is intended to be used to transmute it to its
+    /// correct function type in order to call it.
+    ///
+    /// # Safety
+    ///
+    /// This method allows transmuting to a reference to *any* sized type,
+    /// and cannot check whether the code pointer actually has that type.
+    /// If the type is wrong then undefined behavior will result.
+    pub unsafe fn instantiate_code_ptr<T>(&self) -> &T {
+        self.untyped.instantiate_code_ptr()
+    }
+}
+
 /// The type of callback invoked by a [`ClosureOnce`].
 pub type CallbackOnce<U, R> = CallbackMut<Option<U>, R>;
 
@@ -582,17 +1066,25 @@ impl ClosureOnce {
         cif: Cif,
         callback: CallbackOnce<U, R>,
         userdata: U,
+    ) -> low::Result<Self> {
+        Self::with_alloc(ClosureAlloc::new()?, cif, callback, userdata)
+    }
+
+    fn with_alloc<U: Any, R>(
+        alloc: ClosureAlloc,
+        cif: Cif,
+        callback: CallbackOnce<U, R>,
+        userdata: U,
     ) -> low::Result<Self> {
         let cif = Box::new(cif);
         let userdata = Box::new(Some(userdata)) as Box<dyn Any>;
-        let (alloc, code) = low::try_closure_alloc().ok_or(low::Error::Allocation)?;
-        let alloc = ClosureAlloc(NonNull::new(alloc).ok_or(low::Error::Allocation)?);
+        let code = alloc.code;
 
         {
             let borrow = userdata.downcast_ref::<Option<U>>().unwrap();
             unsafe {
                 low::prep_closure_mut(
-                    alloc.0.as_ptr(),
+                    alloc.closure.as_ptr(),
                     cif.as_raw_ptr(),
                     callback,
                     borrow as *const _ as *mut _,
@@ -634,12 +1126,170 @@ impl ClosureOnce {
     }
 }
 
+/// A closure that owns its `Send + Sync` userdata and can be handed to C
+/// indefinitely.
+///
+/// Unlike [`Closure`], a `ClosureOwned` does not borrow its userdata, and
+/// unlike [`ClosureOnce`], its callback receives a shared reference to the
+/// userdata and can be called any number of times. This makes it suitable
+/// for callbacks that are registered with a C library for a long time.
+/// Such a closure can be turned into a code pointer and an opaque handle
+/// with [`ClosureOwned::into_raw`], and later reclaimed with
+/// [`ClosureOwned::from_raw`] to free it.
+///
+/// # Examples
+///
+/// ```
+/// use std::os::raw::c_void;
+///
+/// use libffi::low;
+/// use libffi::middle::*;
+///
+/// unsafe extern "C" fn callback(
+///     _cif: &low::ffi_cif,
+///     result: &mut u64,
+///     args: *const *const c_void,
+///     userdata: &u64,
+/// ) {
+///     *result = *(*args as *const u64) + userdata;
+/// }
+///
+/// let cif = Cif::new([Type::u64()], Type::u64());
+/// let (code, handle) = ClosureOwned::new(cif, callback, 10u64).into_raw();
+///
+/// let add_ten: extern "C" fn(u64) -> u64 = unsafe { std::mem::transmute(code.0) };
+/// assert_eq!(15, add_ten(5));
+///
+/// // Frees the closure and its userdata.
+/// drop(unsafe { ClosureOwned::from_raw(handle) });
+/// ```
+#[derive(Debug)]
+pub struct ClosureOwned {
+    _alloc: ClosureAlloc,
+    code: CodePtr,
+    _cif: Box<Cif>,
+    _userdata: Box<dyn Any + Send + Sync>,
+}
+
+// The closure only hands out shared references to its `Send + Sync`
+// userdata, and neither the closure nor its CIF are modified after they
+// have been prepared.
+unsafe impl Send for ClosureOwned {}
+unsafe impl Sync for ClosureOwned {}
+
+impl ClosureOwned {
+    /// Creates a new closure with owned, shared userdata.
+    ///
+    /// # Arguments
+    ///
+    /// - `cif` — describes the calling convention and argument and
+    ///   result types
+    /// - `callback` — the function to call when the closure is invoked
+    /// - `userdata` — the value to pass to `callback` along with the
+    ///   arguments when the closure is called
+    ///
+    /// # Result
+    ///
+    /// The new closure.
+    pub fn new<U: Any + Send + Sync, R>(cif: Cif, callback: Callback<U, R>, userdata: U) -> Self {
+        Self::try_new(cif, callback, userdata).expect("low::prep_closure")
+    }
+
+    /// Attempts to create a new closure with owned, shared userdata.
+    ///
+    /// This is the fallible counterpart to [`ClosureOwned::new`].
+    pub fn try_new<U: Any + Send + Sync, R>(
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: U,
+    ) -> low::Result<Self> {
+        Self::with_alloc(ClosureAlloc::new()?, cif, callback, userdata)
+    }
+
+    fn with_alloc<U: Any + Send + Sync, R>(
+        alloc: ClosureAlloc,
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: U,
+    ) -> low::Result<Self> {
+        let cif = Box::new(cif);
+        let userdata = Box::new(userdata);
+        let code = alloc.code;
+
+        unsafe {
+            low::prep_closure(
+                alloc.closure.as_ptr(),
+                cif.as_raw_ptr(),
+                callback,
+                &*userdata as *const U,
+                code,
+            )?;
+        }
+
+        Ok(Self {
+            _alloc: alloc,
+            code,
+            _cif: cif,
+            _userdata: userdata,
+        })
+    }
+
+    /// Obtains the callable code pointer for a closure.
+    ///
+    /// # Safety
+    ///
+    /// The result needs to be transmuted to the correct type before
+    /// it can be called. If the type is wrong then undefined behavior
+    /// will result.
+    pub fn code_ptr(&self) -> &unsafe extern "C" fn() {
+        self.code.as_fun()
+    }
+
+    /// Transmutes the callable code pointer for a closure to a reference
+    /// to any type. This is intended to be used to transmute it to its
+    /// correct function type in order to call it.
+    ///
+    /// # Safety
+    ///
+    /// This method allows transmuting to a reference to *any* sized type,
+    /// and cannot check whether the code pointer actually has that type.
+    /// If the type is wrong then undefined behavior will result.
+    pub unsafe fn instantiate_code_ptr<T>(&self) -> &T {
+        self.code.as_any_ref_()
+    }
+
+    /// Consumes the closure, returning its code pointer and an opaque
+    /// handle to it.
+    ///
+    /// The code pointer stays valid until the handle is passed to
+    /// [`ClosureOwned::from_raw`] and the resulting closure is dropped.
+    /// If that never happens, the closure and its userdata are leaked.
+    pub fn into_raw(self) -> (CodePtr, *mut c_void) {
+        let code = self.code;
+        (code, Box::into_raw(Box::new(self)) as *mut c_void)
+    }
+
+    /// Reclaims a closure from a handle returned by
+    /// [`ClosureOwned::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been returned by [`ClosureOwned::into_raw`], and
+    /// must not be passed to this function more than once. Once the
+    /// returned closure is dropped, its code pointer must not be called
+    /// anymore.
+    pub unsafe fn from_raw(handle: *mut c_void) -> Self {
+        *Box::from_raw(handle as *mut Self)
+    }
+}
+
 #[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
     use crate::low;
     use core::ffi::{c_char, c_void};
     use std::ffi::CStr;
+    use std::string::ToString;
 
     #[test]
     #[cfg_attr(
@@ -682,6 +1332,35 @@ mod test {
         n + m
     }
 
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn share_cif_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Type>();
+        assert_send_sync::<types::TypeArray>();
+        assert_send_sync::<Cif>();
+
+        static CIF: std::sync::OnceLock<Cif> = std::sync::OnceLock::new();
+        let cif = CIF.get_or_init(|| Cif::new([Type::i64(), Type::i64()], Type::i64()));
+        let pair = Type::structure([Type::u8(), Type::u64()]);
+
+        std::thread::scope(|scope| {
+            for n in 0..4i64 {
+                let pair = &pair;
+                scope.spawn(move || {
+                    assert_eq!(16, pair.size());
+                    let sum: i64 = unsafe {
+                        cif.call(CodePtr(add_it as *mut c_void), &[arg(&n), arg(&10i64)])
+                    };
+                    assert_eq!(n + 10, sum);
+                });
+            }
+        });
+    }
+
     #[test]
     fn try_cif_constructors() {
         let cif = Cif::try_new([Type::i64(), Type::i64()], Type::i64()).unwrap();
@@ -700,6 +1379,54 @@ mod test {
         ));
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot call libffi")]
+    fn cif_error_culprits() {
+        let tagged = Type::structure_packed([Type::u8(), Type::u32()], 1);
+        let error = Cif::try_new_detailed([Type::u8(), tagged.clone()], Type::void()).unwrap_err();
+        assert_eq!(low::Error::Typedef, error.kind());
+        assert_eq!(
+            Some(&Culprit::Arg {
+                index: 1,
+                type_: tagged.clone(),
+            }),
+            error.culprit()
+        );
+
+        let error = Cif::try_new_detailed([], tagged.clone()).unwrap_err();
+        assert_eq!(Some(&Culprit::Result(tagged)), error.culprit());
+
+        let empty = Type::structure([]);
+        let error = Builder::new()
+            .args([Type::i64(), Type::i64(), empty.clone()])
+            .try_into_cif_detailed()
+            .unwrap_err();
+        assert_eq!(low::Error::Typedef, error.kind());
+        assert_eq!(
+            "bad or unsupported type representation in argument 2 ({})",
+            error.to_string()
+        );
+
+        let error = Cif::try_new_variadic_detailed(
+            [Type::pointer(), Type::c_int(), Type::f32()],
+            1,
+            Type::void(),
+        )
+        .unwrap_err();
+        assert_eq!(low::Error::ArgType, error.kind());
+        assert_eq!(
+            Some(&Culprit::Arg {
+                index: 2,
+                type_: Type::f32(),
+            }),
+            error.culprit()
+        );
+        assert_eq!(low::Error::ArgType, low::Error::from(error));
+
+        let error = Cif::try_new_detailed([], Type::void()).err();
+        assert_eq!(None, error);
+    }
+
     #[test]
     #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
     fn try_closure() {
@@ -746,6 +1473,80 @@ mod test {
         assert_eq!(11, fun(5, 6));
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn rebind_closure() {
+        unsafe extern "C" fn count(
+            _cif: &low::ffi_cif,
+            result: &mut u64,
+            args: *const *const c_void,
+            userdata: &mut u64,
+        ) {
+            *userdata += **(args as *const &u64);
+            *result = *userdata;
+        }
+
+        let cif = Cif::new([Type::u64()], Type::u64());
+        let env = 5;
+        let mut closure = Closure::new(cif, callback, &env);
+        let fun: extern "C" fn(u64) -> u64 = unsafe { *closure.instantiate_code_ptr() };
+        assert_eq!(11, fun(6));
+
+        let mut total = 100;
+        unsafe { closure.rebind_mut(count, &mut total) };
+        assert_eq!(fun as usize, *closure.code_ptr() as usize);
+        assert_eq!(101, fun(1));
+        assert_eq!(103, fun(2));
+
+        let env = 7;
+        unsafe { closure.rebind(callback, &env) };
+        assert_eq!(8, fun(1));
+        drop(closure);
+        assert_eq!(103, total);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn share_closure_across_threads() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        unsafe extern "C" fn accumulate(
+            _cif: &low::ffi_cif,
+            result: &mut u64,
+            args: *const *const c_void,
+            userdata: &AtomicU64,
+        ) {
+            *result = userdata.fetch_add(**(args as *const &u64), Ordering::Relaxed);
+        }
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ClosureSync>();
+        assert_send_sync::<ClosurePool>();
+
+        let total = AtomicU64::new(0);
+        let pool = ClosurePool::new(1);
+        let closure = pool
+            .closure_sync(Cif::new([Type::u64()], Type::u64()), accumulate, &total)
+            .unwrap();
+
+        std::thread::scope(|scope| {
+            for n in 1..=4u64 {
+                let closure = &closure;
+                scope.spawn(move || {
+                    let fun: &extern "C" fn(u64) -> u64 = unsafe { closure.instantiate_code_ptr() };
+                    fun(n);
+                });
+            }
+        });
+        assert_eq!(10, total.load(Ordering::Relaxed));
+
+        // The closure can also be moved to, and dropped on, another thread.
+        std::thread::scope(|scope| {
+            scope.spawn(move || drop(closure));
+        });
+        assert_eq!(1, pool.stats().free);
+    }
+
     unsafe extern "C" fn callback2<F: Fn(u64, u64) -> u64>(
         _cif: &low::ffi_cif,
         result: &mut u64,
@@ -813,6 +1614,75 @@ mod test {
         }
     }
 
+    #[test]
+    fn cif_accessors() {
+        let args = [Type::i64(), Type::structure([Type::u8(), Type::f64()])];
+        let cif = Cif::new_with_abi(args.clone(), Type::f32(), ffi_abi_FFI_DEFAULT_ABI);
+        assert_eq!(ffi_abi_FFI_DEFAULT_ABI, cif.abi());
+        assert_eq!(args, cif.arg_types());
+        assert_eq!(&Type::f32(), cif.result_type());
+        assert_eq!(2, cif.nargs());
+        assert_eq!(None, cif.fixed_args());
+        assert!(!cif.is_variadic());
+        assert_eq!(args, cif.clone().arg_types());
+
+        let cif = Cif::new_variadic(
+            [Type::pointer(), Type::f64(), Type::i32()],
+            1,
+            Type::c_int(),
+        );
+        assert_eq!(3, cif.nargs());
+        assert_eq!(Some(1), cif.fixed_args());
+        assert!(cif.is_variadic());
+
+        let cif = Cif::new([], Type::void());
+        assert!(cif.arg_types().is_empty());
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", unix))]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn structure_with_non_default_abi() {
+        #[repr(C)]
+        struct Pair {
+            a: u32,
+            b: u64,
+        }
+
+        extern "win64" fn sum(pair: Pair) -> u64 {
+            u64::from(pair.a) + pair.b
+        }
+
+        let mut pair = Type::structure([Type::u32(), Type::u64()]);
+        let offsets = pair.struct_offsets(ffi_abi_FFI_DEFAULT_ABI).unwrap();
+        let cif = Cif::new_with_abi([pair.clone()], Type::u64(), crate::raw::ffi_abi_FFI_WIN64);
+
+        assert_eq!(16, cif.arg_types()[0].size());
+        assert_eq!(8, cif.arg_types()[0].alignment());
+        assert_eq!(
+            offsets,
+            pair.struct_offsets(crate::raw::ffi_abi_FFI_WIN64).unwrap()
+        );
+
+        let value = Pair { a: 2, b: 40 };
+        let result: u64 = unsafe { cif.call(CodePtr(sum as *mut c_void), &[arg(&value)]) };
+        assert_eq!(42, result);
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", unix))]
+    #[cfg_attr(miri, ignore = "Stack usage is computed by libffi")]
+    fn cif_stack_bytes() {
+        // Six integers are passed in registers, and the rest on the stack.
+        let cif = Cif::new((0..6).map(|_| Type::i64()), Type::void());
+        assert_eq!(0, cif.stack_bytes());
+        let cif = Cif::new((0..10).map(|_| Type::i64()), Type::void());
+        assert_eq!(32, cif.stack_bytes());
+    }
+
     /// Test variadic functions by calling `snprintf`
     #[test]
     #[cfg_attr(
@@ -866,4 +1736,36 @@ mod test {
         assert_eq!(result, expected.to_bytes().len().try_into().unwrap());
         assert_eq!(expected, output_cstr);
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn closure_owned_round_trip() {
+        use std::sync::Arc;
+
+        unsafe extern "C" fn callback(
+            _cif: &low::ffi_cif,
+            result: &mut i64,
+            args: *const *const c_void,
+            userdata: &Arc<i64>,
+        ) {
+            *result = *(*args as *const i64) * **userdata;
+        }
+
+        let factor = Arc::new(3i64);
+        let userdata = Arc::clone(&factor);
+        let closure = std::thread::spawn(move || {
+            ClosureOwned::new(Cif::new([Type::i64()], Type::i64()), callback, userdata)
+        })
+        .join()
+        .unwrap();
+
+        let (code, handle) = closure.into_raw();
+        let triple: extern "C" fn(i64) -> i64 = unsafe { core::mem::transmute(code.0) };
+
+        assert_eq!(21, triple(7));
+        assert_eq!(2, Arc::strong_count(&factor));
+
+        drop(unsafe { ClosureOwned::from_raw(handle) });
+        assert_eq!(1, Arc::strong_count(&factor));
+    }
 }
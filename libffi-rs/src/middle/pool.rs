@@ -0,0 +1,368 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::{
+    Callback, CallbackMut, CallbackOnce, Cif, Closure, ClosureAlloc, ClosureOnce, ClosureOwned,
+    ClosureSync, CodePtr,
+};
+use crate::low;
+
+/// Statistics about the allocations of a [`ClosurePool`], returned by
+/// [`ClosurePool::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PoolStats {
+    /// The number of allocations in use by closures.
+    pub live: usize,
+    /// The number of allocations kept for reuse.
+    pub free: usize,
+    /// The number of allocations the pool has made with
+    /// [`low::closure_alloc`], including those it has since freed.
+    pub allocated: usize,
+}
+
+#[derive(Debug)]
+struct State {
+    free: Vec<(NonNull<low::ffi_closure>, CodePtr)>,
+    max_free: usize,
+    live: usize,
+    allocated: usize,
+}
+
+// The free allocations are not used by any closure, so they can be handed
+// out and freed on any thread.
+unsafe impl Send for State {}
+
+impl State {
+    /// Frees the allocations beyond `max_free`.
+    fn trim(&mut self) {
+        for (closure, _) in self.free.drain(self.max_free.min(self.free.len())..) {
+            unsafe {
+                low::closure_free(closure.as_ptr());
+            }
+        }
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        self.max_free = 0;
+        self.trim();
+    }
+}
+
+/// A pool of closure allocations that are reused for new closures.
+///
+/// Allocating a closure with [`low::closure_alloc`] maps executable
+/// memory for its trampoline, which is slow compared to preparing the
+/// closure. Closures created through a pool return their allocation to it
+/// when they are dropped, and later closures prepare it again for their
+/// own CIF, callback and userdata. The pool keeps at most
+/// [`max_free`](ClosurePool::max_free) unused allocations, and frees the
+/// rest.
+///
+/// Cloning a `ClosurePool` is cheap and shares the allocations. The
+/// allocations are freed once the pool and all closures created through
+/// it are dropped. This type requires the `std` feature. The closures of
+/// the [`high`](crate::high) layer take their allocations from a pool set
+/// with [`high::Builder::pool`](crate::high::Builder::pool).
+///
+/// # Examples
+///
+/// ```
+/// use std::os::raw::c_void;
+///
+/// use libffi::low;
+/// use libffi::middle::*;
+///
+/// unsafe extern "C" fn callback(
+///     _cif: &low::ffi_cif,
+///     result: &mut u64,
+///     args: *const *const c_void,
+///     userdata: &u64,
+/// ) {
+///     *result = *(*args as *const u64) + userdata;
+/// }
+///
+/// let pool = ClosurePool::new(16);
+///
+/// for addend in 0..100 {
+///     let cif = Cif::new([Type::u64()], Type::u64());
+///     let closure = pool.closure(cif, callback, &addend).unwrap();
+///     let add: &extern "C" fn(u64) -> u64 = unsafe { closure.instantiate_code_ptr() };
+///     assert_eq!(addend + 1, add(1));
+/// }
+///
+/// let stats = pool.stats();
+/// assert_eq!(0, stats.live);
+/// assert_eq!(1, stats.free);
+/// assert_eq!(1, stats.allocated);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClosurePool {
+    state: Arc<Mutex<State>>,
+}
+
+impl ClosurePool {
+    /// Creates an empty pool that keeps at most `max_free` unused
+    /// allocations.
+    pub fn new(max_free: usize) -> Self {
+        ClosurePool {
+            state: Arc::new(Mutex::new(State {
+                free: Vec::new(),
+                max_free,
+                live: 0,
+                allocated: 0,
+            })),
+        }
+    }
+
+    /// Returns the number of unused allocations the pool keeps.
+    pub fn max_free(&self) -> usize {
+        self.lock().max_free
+    }
+
+    /// Sets the number of unused allocations the pool keeps, freeing the
+    /// allocations beyond it.
+    pub fn set_max_free(&self, max_free: usize) {
+        let mut state = self.lock();
+        state.max_free = max_free;
+        state.trim();
+    }
+
+    /// Returns statistics about the pool’s allocations.
+    pub fn stats(&self) -> PoolStats {
+        let state = self.lock();
+        PoolStats {
+            live: state.live,
+            free: state.free.len(),
+            allocated: state.allocated,
+        }
+    }
+
+    /// Creates a closure with immutable userdata, as by [`Closure::new`].
+    pub fn closure<'a, U, R>(
+        &self,
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: &'a U,
+    ) -> low::Result<Closure<'a>> {
+        Closure::with_alloc(self.alloc()?, cif, callback, userdata)
+    }
+
+    /// Creates a closure with mutable userdata, as by
+    /// [`Closure::new_mut`].
+    pub fn closure_mut<'a, U, R>(
+        &self,
+        cif: Cif,
+        callback: CallbackMut<U, R>,
+        userdata: &'a mut U,
+    ) -> low::Result<Closure<'a>> {
+        Closure::with_alloc_mut(self.alloc()?, cif, callback, userdata)
+    }
+
+    /// Creates a closure with immutable, `Sync` userdata, as by
+    /// [`ClosureSync::new`].
+    pub fn closure_sync<'a, U: Sync, R>(
+        &self,
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: &'a U,
+    ) -> low::Result<ClosureSync<'a>> {
+        Ok(ClosureSync {
+            untyped: Closure::with_alloc(self.alloc()?, cif, callback, userdata)?,
+        })
+    }
+
+    /// Creates a closure with owned userdata, as by [`ClosureOnce::new`].
+    pub fn closure_once<U: Any, R>(
+        &self,
+        cif: Cif,
+        callback: CallbackOnce<U, R>,
+        userdata: U,
+    ) -> low::Result<ClosureOnce> {
+        ClosureOnce::with_alloc(self.alloc()?, cif, callback, userdata)
+    }
+
+    /// Creates a closure with owned, shared userdata, as by
+    /// [`ClosureOwned::new`].
+    pub fn closure_owned<U: Any + Send + Sync, R>(
+        &self,
+        cif: Cif,
+        callback: Callback<U, R>,
+        userdata: U,
+    ) -> low::Result<ClosureOwned> {
+        ClosureOwned::with_alloc(self.alloc()?, cif, callback, userdata)
+    }
+
+    // A panic while the lock is held leaves the counts consistent, so a
+    // poisoned lock is used as is.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes an unused allocation, or makes a new one if there is none.
+    ///
+    /// A new allocation is made without holding the lock, as mapping its
+    /// memory is slow.
+    fn alloc(&self) -> low::Result<ClosureAlloc> {
+        let free = {
+            let mut state = self.lock();
+            let free = state.free.pop();
+            if free.is_some() {
+                state.live += 1;
+            }
+            free
+        };
+
+        let mut alloc = match free {
+            Some((closure, code)) => ClosureAlloc {
+                closure,
+                code,
+                pool: None,
+            },
+            None => {
+                let alloc = ClosureAlloc::new()?;
+                let mut state = self.lock();
+                state.allocated += 1;
+                state.live += 1;
+                alloc
+            }
+        };
+
+        alloc.pool = Some(self.clone());
+        Ok(alloc)
+    }
+
+    /// Takes back an allocation from a dropped closure.
+    pub(super) fn release(&self, closure: NonNull<low::ffi_closure>, code: CodePtr) {
+        let mut state = self.lock();
+        state.live -= 1;
+        state.free.push((closure, code));
+        state.trim();
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::middle::Type;
+    use core::ffi::c_void;
+    use std::vec;
+
+    unsafe extern "C" fn scale(
+        _cif: &low::ffi_cif,
+        result: &mut i32,
+        args: *const *const c_void,
+        userdata: &i32,
+    ) {
+        *result = *(*args as *const i32) * userdata;
+    }
+
+    unsafe extern "C" fn count(
+        _cif: &low::ffi_cif,
+        result: &mut i32,
+        _args: *const *const c_void,
+        userdata: &mut i32,
+    ) {
+        *userdata += 1;
+        *result = *userdata;
+    }
+
+    fn cif() -> Cif {
+        Cif::new([Type::i32()], Type::i32())
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn reuse_allocations() {
+        let pool = ClosurePool::new(2);
+        let factors = [2, 3, 4];
+
+        let closures: Vec<_> = factors
+            .iter()
+            .map(|factor| pool.closure(cif(), scale, factor).unwrap())
+            .collect();
+        for (closure, factor) in closures.iter().zip(factors) {
+            let fun: &extern "C" fn(i32) -> i32 = unsafe { closure.instantiate_code_ptr() };
+            assert_eq!(5 * factor, fun(5));
+        }
+        let codes: Vec<_> = closures
+            .iter()
+            .map(|closure| *closure.code_ptr() as usize)
+            .collect();
+        assert_eq!(
+            PoolStats {
+                live: 3,
+                free: 0,
+                allocated: 3,
+            },
+            pool.stats()
+        );
+
+        drop(closures);
+        assert_eq!(
+            PoolStats {
+                live: 0,
+                free: 2,
+                allocated: 3,
+            },
+            pool.stats()
+        );
+
+        // A reused allocation is prepared for the new callback and userdata.
+        let mut counter = 10;
+        let closure = pool.closure_mut(cif(), count, &mut counter).unwrap();
+        assert!(codes.contains(&(*closure.code_ptr() as usize)));
+        let fun: &extern "C" fn(i32) -> i32 = unsafe { closure.instantiate_code_ptr() };
+        assert_eq!(11, fun(0));
+        assert_eq!(12, fun(0));
+
+        let owned = pool.closure_owned(cif(), scale, 7).unwrap();
+        let once = pool.closure_once(cif(), scale_once, vec![1, 2]).unwrap();
+        let fun: &extern "C" fn(i32) -> i32 = unsafe { once.instantiate_code_ptr() };
+        assert_eq!(12, fun(6));
+        assert_eq!(0, fun(6));
+        assert_eq!(
+            PoolStats {
+                live: 3,
+                free: 0,
+                allocated: 4,
+            },
+            pool.stats()
+        );
+
+        drop((closure, owned, once));
+        assert_eq!(2, pool.stats().free);
+
+        pool.set_max_free(0);
+        assert_eq!(
+            PoolStats {
+                live: 0,
+                free: 0,
+                allocated: 4,
+            },
+            pool.stats()
+        );
+
+        // A closure may outlive its pool.
+        let closure = pool.closure(cif(), scale, &factors[0]).unwrap();
+        drop(pool);
+        let fun: &extern "C" fn(i32) -> i32 = unsafe { closure.instantiate_code_ptr() };
+        assert_eq!(10, fun(5));
+    }
+
+    unsafe extern "C" fn scale_once(
+        _cif: &low::ffi_cif,
+        result: &mut i32,
+        args: *const *const c_void,
+        userdata: &mut Option<Vec<i32>>,
+    ) {
+        *result = *(*args as *const i32) * userdata.take().map_or(0, |v| v.len() as i32);
+    }
+}
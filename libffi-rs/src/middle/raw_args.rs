@@ -0,0 +1,357 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::fmt;
+use core::mem;
+use core::ptr;
+
+use super::{Cif, CodePtr, Culprit, Error, TypeKind};
+use crate::low;
+
+/// The size of a slot of the raw argument format.
+const SLOT: usize = mem::size_of::<low::ffi_raw>();
+
+/// Where an argument is stored in a [`RawArgs`] buffer.
+#[derive(Clone, Copy, Debug)]
+struct Slot {
+    /// The byte offset of the value in the buffer.
+    offset: usize,
+    /// The size of the argument’s type.
+    size: usize,
+    /// Whether the slot holds a pointer to the value rather than the value.
+    indirect: bool,
+    /// For integers narrower than a slot, whether they are sign-extended
+    /// to fill it.
+    extend: Option<bool>,
+}
+
+/// A buffer of arguments in libffi’s raw format, for calling functions
+/// with [`Cif::raw_call`].
+///
+/// The raw format stores the arguments in consecutive word-sized
+/// [`ffi_raw`](low::ffi_raw) slots, rather than as an array of pointers
+/// to the arguments. Interpreters that keep their values in such slots can
+/// pass them to libffi without building that array. See [`low::raw_size`]
+/// for the layout, and [`low::NATIVE_RAW_API`] for whether it is faster
+/// than [`Cif::call`] on the target.
+///
+/// Arguments are stored with [`RawArgs::set`], except structures, which
+/// the raw format passes by pointer and are stored with
+/// [`RawArgs::set_indirect`].
+///
+/// # Examples
+///
+/// ```
+/// use libffi::middle::{Cif, CodePtr, RawArgs, Type};
+///
+/// extern "C" fn scale(x: f64, factor: u8) -> f64 {
+///     x * factor as f64
+/// }
+///
+/// let cif = Cif::new([Type::f64(), Type::u8()], Type::f64());
+/// let mut args = RawArgs::new(&cif).unwrap();
+/// args.set(0, 1.5f64);
+/// args.set(1, 4u8);
+///
+/// let result: f64 = unsafe { cif.raw_call(CodePtr(scale as *mut _), &mut args) };
+/// assert_eq!(6.0, result);
+/// ```
+#[derive(Clone)]
+pub struct RawArgs {
+    slots: Box<[low::ffi_raw]>,
+    args: Box<[Slot]>,
+}
+
+impl RawArgs {
+    /// Creates a zeroed buffer for the arguments of `cif`.
+    ///
+    /// # Errors
+    ///
+    /// Where the raw API is [native](low::NATIVE_RAW_API), structures and
+    /// complex numbers are passed by value in a layout that this type does
+    /// not describe, so a CIF with such an argument is rejected with
+    /// [`low::Error::Typedef`].
+    pub fn new(cif: &Cif) -> Result<Self, Error> {
+        let mut args = Vec::with_capacity(cif.nargs());
+        let mut offset = 0;
+        for (index, type_) in cif.arg_types().iter().enumerate() {
+            let kind = type_.kind();
+            let indirect = matches!(
+                kind,
+                TypeKind::Struct | TypeKind::Union | TypeKind::Array { .. } | TypeKind::Complex
+            );
+            if indirect && low::NATIVE_RAW_API {
+                return Err(Error::new(
+                    low::Error::Typedef,
+                    Some(Culprit::Arg {
+                        index,
+                        type_: type_.clone(),
+                    }),
+                ));
+            }
+
+            let size = if indirect {
+                mem::size_of::<*mut c_void>()
+            } else {
+                type_.size()
+            };
+            let extend = match kind {
+                TypeKind::Int { signed, .. } if size < SLOT => Some(signed),
+                _ => None,
+            };
+            // Narrow integers are read from the end of their slot on big
+            // endian targets.
+            let padding = if extend.is_some() && cfg!(target_endian = "big") {
+                SLOT - size
+            } else {
+                0
+            };
+
+            args.push(Slot {
+                offset: offset + padding,
+                size: type_.size(),
+                indirect,
+                extend,
+            });
+            offset += size.div_ceil(SLOT).max(1) * SLOT;
+        }
+
+        // libffi counts the bytes of complex numbers rather than of the
+        // pointers to them, so it may ask for more.
+        let bytes = offset.max(unsafe { low::raw_size(cif.as_raw_ptr()) });
+        Ok(Self {
+            slots: alloc::vec![low::ffi_raw::default(); bytes / SLOT].into_boxed_slice(),
+            args: args.into_boxed_slice(),
+        })
+    }
+
+    /// Returns the slot for the argument at `index`, checking how it is
+    /// accessed.
+    fn slot(&self, index: usize, size: usize, indirect: bool, method: &str) -> Slot {
+        let slot = self.args[index];
+        assert!(
+            slot.indirect == indirect,
+            "RawArgs::{method}: argument {index} is {} by pointer",
+            if slot.indirect {
+                "passed"
+            } else {
+                "not passed"
+            }
+        );
+        assert!(
+            indirect || slot.size == size,
+            "RawArgs::{method}: argument {index} has a different size"
+        );
+        slot
+    }
+
+    /// Stores `value` as the argument at `index`.
+    ///
+    /// Integers narrower than a slot are extended to fill it, as libffi
+    /// does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no argument at `index`, if `T` does not have the
+    /// size of its type, or if it is a structure.
+    pub fn set<T: Copy>(&mut self, index: usize, value: T) {
+        let slot = self.slot(index, mem::size_of::<T>(), false, "set");
+        let bytes = (&value as *const T).cast::<u8>();
+
+        match slot.extend {
+            Some(signed) => {
+                self.slots[slot.offset / SLOT] = unsafe { extend(bytes, slot.size, signed) }
+            }
+            None => unsafe {
+                let dest = self.slots.as_mut_ptr().cast::<u8>().add(slot.offset);
+                ptr::copy_nonoverlapping(bytes, dest, slot.size);
+            },
+        }
+    }
+
+    /// Reads the argument at `index`, as stored by [`RawArgs::set`] or by
+    /// the caller of a raw closure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no argument at `index`, if `T` does not have the
+    /// size of its type, or if it is a structure.
+    ///
+    /// # Safety
+    ///
+    /// The stored bytes must be a valid `T`.
+    pub unsafe fn get<T: Copy>(&self, index: usize) -> T {
+        let slot = self.slot(index, mem::size_of::<T>(), false, "get");
+        let src = self.slots.as_ptr().cast::<u8>().add(slot.offset);
+        ptr::read_unaligned(src.cast::<T>())
+    }
+
+    /// Stores a pointer to the value of the structure argument at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no argument at `index`, or if it is not a
+    /// structure.
+    pub fn set_indirect(&mut self, index: usize, value: *const c_void) {
+        let slot = self.slot(index, 0, true, "set_indirect");
+        self.slots[slot.offset / SLOT].ptr = value.cast_mut();
+    }
+
+    /// Returns a pointer to the slots, for passing the arguments to
+    /// [`low::raw_call`].
+    pub fn as_mut_ptr(&mut self) -> *mut low::ffi_raw {
+        self.slots.as_mut_ptr()
+    }
+}
+
+impl fmt::Debug for RawArgs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The slots are untyped, so only their layout is shown.
+        f.debug_struct("RawArgs")
+            .field("slots", &self.slots.len())
+            .field("args", &self.args)
+            .finish()
+    }
+}
+
+// The buffer may hold pointers to structures, which are only read during
+// calls, and only written through `&mut self`.
+unsafe impl Send for RawArgs {}
+unsafe impl Sync for RawArgs {}
+
+/// Extends the integer of `size` bytes at `bytes` to a full slot.
+///
+/// # Safety
+///
+/// `bytes` must point to `size` readable bytes, where `size` is 1, 2 or 4.
+unsafe fn extend(bytes: *const u8, size: usize, signed: bool) -> low::ffi_raw {
+    let mut raw = low::ffi_raw::default();
+    match (size, signed) {
+        (1, false) => raw.uint = ptr::read_unaligned(bytes) as low::ffi_arg,
+        (1, true) => raw.sint = ptr::read_unaligned(bytes.cast::<i8>()) as low::ffi_sarg,
+        (2, false) => raw.uint = ptr::read_unaligned(bytes.cast::<u16>()) as low::ffi_arg,
+        (2, true) => raw.sint = ptr::read_unaligned(bytes.cast::<i16>()) as low::ffi_sarg,
+        (4, false) => raw.uint = ptr::read_unaligned(bytes.cast::<u32>()) as low::ffi_arg,
+        (4, true) => raw.sint = ptr::read_unaligned(bytes.cast::<i32>()) as low::ffi_sarg,
+        _ => unreachable!("integers narrower than a slot have 1, 2 or 4 bytes"),
+    }
+    raw
+}
+
+impl Cif {
+    /// Calls a function with arguments in libffi’s raw format.
+    ///
+    /// As for [`Cif::call`], `R` must be the result type of the CIF.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `args` was not created for a CIF with the same number of
+    /// arguments.
+    ///
+    /// # Safety
+    ///
+    /// `args` must have been created for this CIF and hold valid values of
+    /// its argument types, with valid pointers to its structure arguments,
+    /// and the function must have the signature of the CIF.
+    pub unsafe fn raw_call<R>(&self, fun: CodePtr, args: &mut RawArgs) -> R {
+        assert_eq!(
+            self.nargs(),
+            args.args.len(),
+            "Cif::raw_call: passed wrong number of arguments"
+        );
+
+        low::raw_call::<R>(self.as_raw_ptr(), fun, args.as_mut_ptr())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::middle::Type;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Pair {
+        tag: u8,
+        value: f64,
+    }
+
+    extern "C" fn mix(a: i8, b: u16, c: f32, d: i64, e: *const u32, f: f64) -> i64 {
+        a as i64 + b as i64 + c as i64 + d + unsafe { *e } as i64 + f as i64
+    }
+
+    extern "C" fn weigh(pair: Pair, scale: i16) -> f64 {
+        pair.tag as f64 * pair.value * scale as f64
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn raw_call() {
+        let cif = Cif::new(
+            [
+                Type::i8(),
+                Type::u16(),
+                Type::f32(),
+                Type::i64(),
+                Type::pointer(),
+                Type::f64(),
+            ],
+            Type::i64(),
+        );
+        let mut args = RawArgs::new(&cif).unwrap();
+        let e = 1000u32;
+        args.set(0, -3i8);
+        args.set(1, 40000u16);
+        args.set(2, 0.5f32);
+        args.set(3, 1i64 << 40);
+        args.set(4, &e as *const u32);
+        args.set(5, 2.0f64);
+
+        assert_eq!(-3, unsafe { args.get::<i8>(0) });
+        assert_eq!(40000, unsafe { args.get::<u16>(1) });
+        assert_eq!(0.5, unsafe { args.get::<f32>(2) });
+        let result: i64 = unsafe { cif.raw_call(CodePtr(mix as *mut _), &mut args) };
+        assert_eq!(-3 + 40000 + (1 << 40) + 1000 + 2, result);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "x86"))]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn raw_call_with_structures() {
+        let cif = Cif::new(
+            [Type::structure([Type::u8(), Type::f64()]), Type::i16()],
+            Type::f64(),
+        );
+        let mut args = RawArgs::new(&cif).unwrap();
+        let pair = Pair { tag: 3, value: 1.5 };
+        args.set_indirect(0, (&pair as *const Pair).cast());
+        args.set(1, -2i16);
+
+        let result: f64 = unsafe { cif.raw_call(CodePtr(weigh as *mut _), &mut args) };
+        assert_eq!(-9.0, result);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "x86"))]
+    #[should_panic(expected = "argument 1 is passed by pointer")]
+    fn raw_args_check_structures() {
+        let cif = Cif::new(
+            [Type::u8(), Type::structure([Type::u8(), Type::u32()])],
+            Type::void(),
+        );
+        RawArgs::new(&cif).unwrap().set(1, 0u64);
+    }
+
+    #[test]
+    #[should_panic(expected = "argument 0 has a different size")]
+    fn raw_args_check_sizes() {
+        let cif = Cif::new([Type::u16()], Type::void());
+        RawArgs::new(&cif).unwrap().set(0, 0u32);
+    }
+}
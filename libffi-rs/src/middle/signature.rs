@@ -0,0 +1,1133 @@
+//! A textual representation of [`Type`]s and [`Cif`]s.
+//!
+//! A type is written as its Rust-like name (`void`, `u8` to `u64`, `i8` to
+//! `i64`, `f32`, `f64`, `longdouble`, `ptr`, and with the `complex` feature
+//! `c32`, `c64` and `complex_longdouble`), as a brace-enclosed list of
+//! field types for a structure, such as `{u8,u64}`, as `union` followed by
+//! its variant types for a union, such as `union{i32,f32}`, or as its
+//! element type and length for an array, such as `[u8;16]`. Packed and
+//! over-aligned structures are prefixed with their packing or alignment, as
+//! in `packed(1){u8,u32}` and `aligned(16){f32,f32,f32}`.
+//!
+//! A CIF is written as its ABI, its parenthesized argument types and its
+//! result type, such as `sysv (i32, {u8,u64}, ptr) -> f64`. In a variadic
+//! CIF, `...` separates the fixed arguments from the variadic ones, as in
+//! `unix64 (ptr, ..., i32, f64) -> i32`.
+//!
+//! The ABI is written as its lowercase libffi name without the `FFI_`
+//! prefix, or as its number if it has no known name on the current
+//! platform. It may be omitted when parsing, in which case the default ABI
+//! is used.
+//!
+//! [`Cif::parse`] additionally accepts C declarations, C type names and a
+//! compact form without parentheses.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use core::str::FromStr;
+
+use super::{ffi_abi_FFI_DEFAULT_ABI, Cif, FfiAbi, Type, TypeKind};
+use crate::{low, raw};
+
+/// The names of the ABIs supported on the current platform.
+///
+/// When several names map to the same ABI, the first one is used for
+/// display.
+#[cfg(all(target_arch = "x86_64", unix))]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[
+    ("unix64", raw::ffi_abi_FFI_UNIX64),
+    ("win64", raw::ffi_abi_FFI_WIN64),
+    ("efi64", raw::ffi_abi_FFI_EFI64),
+    ("gnuw64", raw::ffi_abi_FFI_GNUW64),
+];
+
+#[cfg(all(target_arch = "x86_64", windows))]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[
+    ("win64", raw::ffi_abi_FFI_WIN64),
+    ("gnuw64", raw::ffi_abi_FFI_GNUW64),
+];
+
+#[cfg(target_arch = "x86")]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[
+    ("sysv", raw::ffi_abi_FFI_SYSV),
+    ("stdcall", raw::ffi_abi_FFI_STDCALL),
+    ("thiscall", raw::ffi_abi_FFI_THISCALL),
+    ("fastcall", raw::ffi_abi_FFI_FASTCALL),
+    ("ms_cdecl", raw::ffi_abi_FFI_MS_CDECL),
+    ("pascal", raw::ffi_abi_FFI_PASCAL),
+    ("register", raw::ffi_abi_FFI_REGISTER),
+];
+
+#[cfg(target_arch = "arm")]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[
+    ("sysv", raw::ffi_abi_FFI_SYSV),
+    ("vfp", raw::ffi_abi_FFI_VFP),
+];
+
+#[cfg(target_arch = "aarch64")]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[
+    ("sysv", raw::ffi_abi_FFI_SYSV),
+    ("win64", raw::ffi_abi_FFI_WIN64),
+];
+
+#[cfg(any(
+    target_arch = "riscv32",
+    target_arch = "riscv64",
+    target_arch = "s390x"
+))]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[("sysv", raw::ffi_abi_FFI_SYSV)];
+
+#[cfg(target_arch = "sparc64")]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[("v9", raw::ffi_abi_FFI_V9)];
+
+#[cfg(target_arch = "loongarch64")]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[
+    ("lp64s", raw::ffi_abi_FFI_LP64S),
+    ("lp64f", raw::ffi_abi_FFI_LP64F),
+    ("lp64d", raw::ffi_abi_FFI_LP64D),
+];
+
+// ABIs on other platforms are written as numbers.
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "x86",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64",
+    target_arch = "s390x",
+    target_arch = "sparc64",
+    target_arch = "loongarch64"
+)))]
+const ABI_NAMES: &[(&str, FfiAbi)] = &[];
+
+/// The error returned when parsing a [`Type`] or [`Cif`] fails.
+///
+/// # Examples
+///
+/// ```
+/// use libffi::middle::{Cif, ParseErrorKind};
+///
+/// let error = "(i32, half) -> void".parse::<Cif>().unwrap_err();
+///
+/// assert_eq!(&ParseErrorKind::UnknownType, error.kind());
+/// assert_eq!(6..10, error.span());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    span: Range<usize>,
+}
+
+/// The kinds of [`ParseError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// A token was found where a different one was expected.
+    UnexpectedToken,
+    /// The input ended before the signature was complete.
+    UnexpectedEnd,
+    /// A type name was not recognized.
+    UnknownType,
+    /// An ABI name was not recognized.
+    UnknownAbi,
+    /// Types were nested more deeply than the parser allows.
+    TooDeep,
+    /// libffi rejected the parsed signature.
+    Prep(low::Error),
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, span: Range<usize>) -> Self {
+        Self { kind, span }
+    }
+
+    /// Returns the kind of error.
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    /// Returns the byte range of the input where the error occurred.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnexpectedToken => f.write_str("unexpected token")?,
+            ParseErrorKind::UnexpectedEnd => f.write_str("unexpected end of input")?,
+            ParseErrorKind::UnknownType => f.write_str("unknown type")?,
+            ParseErrorKind::UnknownAbi => f.write_str("unknown ABI")?,
+            ParseErrorKind::TooDeep => f.write_str("types nested too deeply")?,
+            ParseErrorKind::Prep(error) => write!(f, "libffi rejected the signature: {error}")?,
+        }
+
+        write!(f, " at {}..{}", self.span.start, self.span.end)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+fn fmt_abi(abi: FfiAbi, f: &mut fmt::Formatter) -> fmt::Result {
+    match ABI_NAMES.iter().find(|&&(_, value)| value == abi) {
+        Some((name, _)) => f.write_str(name),
+        None => write!(f, "{abi}"),
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind() {
+            TypeKind::Void => f.write_str("void"),
+            TypeKind::Int { bits, signed: true } => write!(f, "i{bits}"),
+            TypeKind::Int {
+                bits,
+                signed: false,
+            } => write!(f, "u{bits}"),
+            TypeKind::Float => f.write_str("f32"),
+            TypeKind::Double => f.write_str("f64"),
+            TypeKind::LongDouble => f.write_str("longdouble"),
+            TypeKind::Pointer => f.write_str("ptr"),
+            TypeKind::Struct | TypeKind::Union => {
+                match (self.kind(), self.custom_layout()) {
+                    (TypeKind::Union, _) => f.write_str("union")?,
+                    (_, (0, 0)) => {}
+                    (_, (0, align)) => write!(f, "aligned({align})")?,
+                    (_, (pack, _)) => write!(f, "packed({pack})")?,
+                }
+                f.write_str("{")?;
+                for (i, field) in self.fields().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{field}")?;
+                }
+                f.write_str("}")
+            }
+            TypeKind::Array { len } => {
+                let element = self.fields().next().expect("arrays are not empty");
+                write!(f, "[{element};{len}]")
+            }
+            TypeKind::Complex => {
+                // The element array of a complex type holds its component
+                // type.
+                let component = unsafe { (**(*self.as_raw_ptr()).elements).type_ };
+                match component {
+                    raw::FFI_TYPE_FLOAT => f.write_str("c32"),
+                    raw::FFI_TYPE_DOUBLE => f.write_str("c64"),
+                    _ => f.write_str("complex_longdouble"),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cif {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_abi(self.cif.abi, f)?;
+        f.write_str(" (")?;
+
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            if self.fixed_args == Some(i) {
+                f.write_str("..., ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+
+        match self.fixed_args {
+            Some(0) if self.cif.nargs == 0 => f.write_str("...")?,
+            Some(fixed_args) if fixed_args == self.cif.nargs as usize => {
+                f.write_str(", ...")?;
+            }
+            _ => {}
+        }
+
+        write!(f, ") -> {}", self.result)
+    }
+}
+
+/// Parses a type, such as `{u8,u64}` or `unsigned long`.
+///
+/// Types are written as for [`Cif::parse`].
+///
+/// # Examples
+///
+/// ```
+/// use libffi::middle::Type;
+///
+/// let parsed: Type = "{u8,u64}".parse().unwrap();
+///
+/// assert_eq!(Type::structure([Type::u8(), Type::u64()]), parsed);
+/// assert_eq!("{u8,u64}", parsed.to_string());
+/// ```
+impl FromStr for Type {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new(input)?;
+        let type_ = parser.type_()?;
+        parser.end()?;
+        Ok(type_)
+    }
+}
+
+impl Cif {
+    /// Parses a CIF from a textual signature.
+    ///
+    /// Three forms of signature are accepted:
+    ///
+    /// - a C declaration, such as `int (const char *, double, ...)` or
+    ///   `int printf(const char *format, ...);`
+    /// - a compact form with arguments and result separated by `->`, such
+    ///   as `i32,f64,ptr->i32`
+    /// - the form produced by [`Cif`]’s `Display` implementation, such as
+    ///   `sysv (i32, {u8,u64}, ptr) -> f64`
+    ///
+    /// Types may be written with the names used by `Display`, with C type
+    /// names such as `long`, `unsigned char`, `size_t` or `int32_t`, which
+    /// resolve to the corresponding constructors such as [`Type::c_long`]
+    /// and [`Type::usize`], or as pointers such as `const char *`.
+    /// Structures are written as a brace-enclosed list of field types, such
+    /// as `{u8,u64}` or `struct { short x; short y; }`, unions in the same
+    /// way after `union`, such as `union { int i; float f; }`, and arrays as
+    /// their element type and length, such as `[u8;16]`. Parameter and
+    /// field names are ignored, but may be followed by C array dimensions,
+    /// as in
+    /// `struct { char name[16]; float m[4][4]; }`. As in C, a parameter
+    /// declared as an array is passed as a pointer.
+    ///
+    /// In every form, the signature may start with the name of an ABI, such
+    /// as `sysv` or `win64`, and `...` marks where the variadic arguments
+    /// begin.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] locating the problem if `signature` cannot
+    /// be parsed, nests types more than 256 deep, or if libffi rejects the
+    /// parsed CIF.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{Cif, Type};
+    ///
+    /// let printf = Cif::parse("int (const char *, double, ...)").unwrap();
+    /// assert_eq!(
+    ///     Cif::new_variadic([Type::pointer(), Type::f64()], 2, Type::c_int()),
+    ///     printf
+    /// );
+    ///
+    /// let compact = Cif::parse("i32,f64,ptr->i32").unwrap();
+    /// assert_eq!(
+    ///     Cif::new([Type::i32(), Type::f64(), Type::pointer()], Type::i32()),
+    ///     compact
+    /// );
+    ///
+    /// let error = Cif::parse("unsigned float (void)").unwrap_err();
+    /// assert_eq!(0..14, error.span());
+    /// ```
+    pub fn parse(signature: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new(signature)?;
+        let cif = parser.cif()?;
+        parser.end()?;
+        Ok(cif)
+    }
+}
+
+/// Parses a CIF with [`Cif::parse`].
+///
+/// # Examples
+///
+/// ```
+/// use libffi::middle::{Cif, Type};
+///
+/// let parsed: Cif = "(ptr, ..., i32) -> i32".parse().unwrap();
+///
+/// assert_eq!(
+///     Cif::new_variadic([Type::pointer(), Type::i32()], 1, Type::i32()),
+///     parsed
+/// );
+/// ```
+impl FromStr for Cif {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, ParseError> {
+        Cif::parse(input)
+    }
+}
+
+/// The C type specifiers that may be combined into a type name, as in
+/// `unsigned long int`.
+const SPECIFIERS: &[&str] = &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "_Bool",
+    "bool",
+];
+
+/// The C type qualifiers, which are ignored.
+const QUALIFIERS: &[&str] = &["const", "volatile"];
+
+/// The depth to which types may be nested in a signature.
+const MAX_DEPTH: usize = 256;
+
+/// Resolves a single-word type name.
+fn named_type(name: &str) -> Option<Type> {
+    Some(match name {
+        "u8" | "uint8_t" => Type::u8(),
+        "i8" | "int8_t" => Type::i8(),
+        "u16" | "uint16_t" => Type::u16(),
+        "i16" | "int16_t" => Type::i16(),
+        "u32" | "uint32_t" => Type::u32(),
+        "i32" | "int32_t" => Type::i32(),
+        "u64" | "uint64_t" => Type::u64(),
+        "i64" | "int64_t" => Type::i64(),
+        "usize" | "size_t" | "uintptr_t" => Type::usize(),
+        "isize" | "ssize_t" | "ptrdiff_t" | "intptr_t" => Type::isize(),
+        "f32" => Type::f32(),
+        "f64" => Type::f64(),
+        "longdouble" => Type::longdouble(),
+        "ptr" => Type::pointer(),
+        #[cfg(all(feature = "complex", not(windows)))]
+        "c32" => Type::c32(),
+        #[cfg(all(feature = "complex", not(windows)))]
+        "c64" => Type::c64(),
+        #[cfg(all(feature = "complex", not(windows)))]
+        "complex_longdouble" => Type::complex_longdouble(),
+        _ => return None,
+    })
+}
+
+/// Resolves a combination of C type specifiers, such as
+/// `["unsigned", "long"]`.
+fn specified_type(specifiers: &[&str]) -> Option<Type> {
+    let count = |specifier| specifiers.iter().filter(|&&s| s == specifier).count();
+    let (signed, unsigned) = (count("signed"), count("unsigned"));
+    let (short, long) = (count("short"), count("long"));
+    let mut bases = specifiers
+        .iter()
+        .filter(|s| !["signed", "unsigned", "short", "long"].contains(s));
+    let base = bases.next().copied();
+
+    if bases.next().is_some() || signed + unsigned > 1 || short > 1 || long > 2 || short + long > 2
+    {
+        return None;
+    }
+
+    let sign = signed + unsigned > 0;
+    Some(match (base, short, long) {
+        (Some("void"), 0, 0) if !sign => Type::void(),
+        (Some("_Bool" | "bool"), 0, 0) if !sign => Type::u8(),
+        (Some("float"), 0, 0) if !sign => Type::f32(),
+        (Some("double"), 0, 0) if !sign => Type::f64(),
+        (Some("double"), 0, 1) if !sign => Type::longdouble(),
+        (Some("char"), 0, 0) if unsigned > 0 => Type::c_uchar(),
+        (Some("char"), 0, 0) if signed > 0 || core::ffi::c_char::MIN != 0 => Type::c_schar(),
+        (Some("char"), 0, 0) => Type::c_uchar(),
+        (None | Some("int"), 1, 0) if unsigned > 0 => Type::c_ushort(),
+        (None | Some("int"), 1, 0) => Type::c_short(),
+        (None | Some("int"), 0, 0) if unsigned > 0 => Type::c_uint(),
+        (None | Some("int"), 0, 0) => Type::c_int(),
+        (None | Some("int"), 0, 1) if unsigned > 0 => Type::c_ulong(),
+        (None | Some("int"), 0, 1) => Type::c_long(),
+        (None | Some("int"), 0, 2) if unsigned > 0 => Type::c_ulonglong(),
+        (None | Some("int"), 0, 2) => Type::c_longlong(),
+        _ => return None,
+    })
+}
+
+/// Whether `word` can start a type.
+fn starts_type(word: &str) -> bool {
+    SPECIFIERS.contains(&word)
+        || QUALIFIERS.contains(&word)
+        || word == "struct"
+        || word == "union"
+        || named_type(word).is_some()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Punct(&'static str),
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token<'a>, Range<usize>)>,
+    next: usize,
+    len: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Self, ParseError> {
+        let bytes = input.as_bytes();
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            let start = pos;
+            let byte = bytes[pos];
+
+            if byte.is_ascii_whitespace() {
+                pos += 1;
+                continue;
+            }
+
+            let token = if byte.is_ascii_alphanumeric() || byte == b'_' {
+                while pos < bytes.len()
+                    && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_')
+                {
+                    pos += 1;
+                }
+                Token::Ident(&input[start..pos])
+            } else if input[pos..].starts_with("...") {
+                pos += 3;
+                Token::Punct("...")
+            } else if input[pos..].starts_with("->") {
+                pos += 2;
+                Token::Punct("->")
+            } else if let Some(punct) = ["(", ")", "{", "}", "[", "]", ",", ";", "*"]
+                .into_iter()
+                .find(|punct| punct.as_bytes()[0] == byte)
+            {
+                pos += 1;
+                Token::Punct(punct)
+            } else {
+                let len = input[pos..].chars().next().map_or(1, char::len_utf8);
+                return Err(ParseError::new(
+                    ParseErrorKind::UnexpectedToken,
+                    pos..pos + len,
+                ));
+            };
+
+            tokens.push((token, start..pos));
+        }
+
+        Ok(Self {
+            tokens,
+            next: 0,
+            len: input.len(),
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.next).map(|(token, _)| *token)
+    }
+
+    fn peek_punct(&self, punct: &'static str) -> bool {
+        self.peek() == Some(Token::Punct(punct))
+    }
+
+    fn bump(&mut self) -> Result<(Token<'a>, Range<usize>), ParseError> {
+        let token =
+            self.tokens.get(self.next).cloned().ok_or_else(|| {
+                ParseError::new(ParseErrorKind::UnexpectedEnd, self.len..self.len)
+            })?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<(), ParseError> {
+        match self.bump()? {
+            (Token::Punct(found), _) if found == punct => Ok(()),
+            (_, span) => Err(ParseError::new(ParseErrorKind::UnexpectedToken, span)),
+        }
+    }
+
+    fn end(&self) -> Result<(), ParseError> {
+        match self.tokens.get(self.next) {
+            None => Ok(()),
+            Some((_, span)) => Err(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                span.clone(),
+            )),
+        }
+    }
+
+    /// Skips type qualifiers.
+    fn qualifiers(&mut self) {
+        while matches!(self.peek(), Some(Token::Ident(word)) if QUALIFIERS.contains(&word)) {
+            self.next += 1;
+        }
+    }
+
+    /// Skips a parameter or field name.
+    fn name(&mut self) {
+        if matches!(self.peek(), Some(Token::Ident(word)) if !starts_type(word)) {
+            self.next += 1;
+        }
+    }
+
+    /// Parses a type, failing if it is nested in more than
+    /// [`MAX_DEPTH`] types, as parsing recurses into nested types.
+    fn type_(&mut self) -> Result<Type, ParseError> {
+        if self.depth == MAX_DEPTH {
+            let span = self
+                .tokens
+                .get(self.next)
+                .map_or(self.len..self.len, |(_, span)| span.clone());
+            return Err(ParseError::new(ParseErrorKind::TooDeep, span));
+        }
+
+        self.depth += 1;
+        let type_ = self.unnested_type();
+        self.depth -= 1;
+        type_
+    }
+
+    fn unnested_type(&mut self) -> Result<Type, ParseError> {
+        self.qualifiers();
+        let (token, span) = self.bump()?;
+        let mut type_ = match token {
+            Token::Punct("{") => Type::structure(self.fields()?),
+            Token::Punct("[") => {
+                let element = self.type_()?;
+                self.expect(";")?;
+                let len = self.len()?;
+                self.expect("]")?;
+                Type::array(element, len)
+            }
+            Token::Ident("struct") => {
+                self.expect("{")?;
+                Type::structure(self.fields()?)
+            }
+            Token::Ident(layout @ ("packed" | "aligned")) => {
+                self.expect("(")?;
+                let (value, value_span) = match self.bump()? {
+                    (Token::Ident(value), span) => (value.parse::<usize>().ok(), span),
+                    (_, span) => (None, span),
+                };
+                let value = value
+                    .filter(|value| value.is_power_of_two() && *value <= 1 << 15)
+                    .ok_or_else(|| ParseError::new(ParseErrorKind::UnexpectedToken, value_span))?;
+                self.expect(")")?;
+                let fields = self.members(span.start)?;
+                if layout == "packed" {
+                    Type::structure_packed(fields, value)
+                } else {
+                    Type::structure_aligned(fields, value)
+                }
+            }
+            Token::Ident("union") => Type::union(self.members(span.start)?),
+            Token::Ident(word) if SPECIFIERS.contains(&word) => {
+                let mut specifiers = alloc::vec![word];
+                let mut end = span.end;
+                loop {
+                    match self.tokens.get(self.next) {
+                        Some((Token::Ident(word), word_span)) if SPECIFIERS.contains(word) => {
+                            specifiers.push(word);
+                            end = word_span.end;
+                        }
+                        Some((Token::Ident(word), _)) if QUALIFIERS.contains(word) => {}
+                        _ => break,
+                    }
+                    self.next += 1;
+                }
+                specified_type(&specifiers)
+                    .ok_or_else(|| ParseError::new(ParseErrorKind::UnknownType, span.start..end))?
+            }
+            Token::Ident(name) => named_type(name)
+                .ok_or_else(|| ParseError::new(ParseErrorKind::UnknownType, span))?,
+            Token::Punct(_) => {
+                return Err(ParseError::new(ParseErrorKind::UnexpectedToken, span));
+            }
+        };
+
+        loop {
+            self.qualifiers();
+            if !self.peek_punct("*") {
+                return Ok(type_);
+            }
+            self.next += 1;
+            type_ = Type::pointer();
+        }
+    }
+
+    /// Parses an array length.
+    fn len(&mut self) -> Result<usize, ParseError> {
+        match self.bump()? {
+            (Token::Ident(len), span) => match len.parse() {
+                Ok(0) | Err(_) => Err(ParseError::new(ParseErrorKind::UnexpectedToken, span)),
+                Ok(len) => Ok(len),
+            },
+            (_, span) => Err(ParseError::new(ParseErrorKind::UnexpectedToken, span)),
+        }
+    }
+
+    /// Parses C array dimensions following a declarator name, such as the
+    /// `[4][4]` of `float m[4][4]`, around `type_`.
+    fn dimensions(&mut self, type_: Type) -> Result<Type, ParseError> {
+        if !self.peek_punct("[") {
+            return Ok(type_);
+        }
+
+        self.next += 1;
+        let len = self.len()?;
+        self.expect("]")?;
+        Ok(Type::array(self.dimensions(type_)?, len))
+    }
+
+    /// Parses the fields of a structure or union, after its opening brace.
+    fn fields(&mut self) -> Result<Vec<Type>, ParseError> {
+        let mut fields = Vec::new();
+        while !self.peek_punct("}") {
+            let field = self.type_()?;
+            self.name();
+            fields.push(self.dimensions(field)?);
+            if !self.peek_punct(",") && !self.peek_punct(";") {
+                break;
+            }
+            self.next += 1;
+        }
+        self.expect("}")?;
+        Ok(fields)
+    }
+
+    /// Parses the braced members of a union or of a structure with a custom
+    /// layout, whose type starts at byte `start`. Unlike a plain structure,
+    /// such a type needs at least one member, and every member must have
+    /// a layout.
+    fn members(&mut self, start: usize) -> Result<Vec<Type>, ParseError> {
+        self.expect("{")?;
+        let first = self.next;
+        let members = self.fields()?;
+        if members.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::UnexpectedToken,
+                self.tokens[first].1.clone(),
+            ));
+        }
+        if members.iter().any(|member| member.size() == 0) {
+            return Err(ParseError::new(
+                ParseErrorKind::Prep(low::Error::Typedef),
+                start..self.tokens[self.next - 1].1.end,
+            ));
+        }
+        Ok(members)
+    }
+
+    fn abi(&mut self) -> Result<FfiAbi, ParseError> {
+        let Some((Token::Ident(name), span)) = self.tokens.get(self.next).cloned() else {
+            return Ok(ffi_abi_FFI_DEFAULT_ABI);
+        };
+
+        let abi = ABI_NAMES
+            .iter()
+            .find(|&&(abi_name, _)| abi_name == name)
+            .map(|&(_, abi)| abi)
+            .or_else(|| name.parse().ok());
+        if let Some(abi) = abi {
+            self.next += 1;
+            return Ok(abi);
+        }
+
+        // Only an ABI can directly precede the argument list.
+        let before_args = matches!(self.tokens.get(self.next + 1), Some((Token::Punct("("), _)));
+        let layout = name == "packed" || name == "aligned";
+        if before_args && !starts_type(name) && !layout {
+            return Err(ParseError::new(ParseErrorKind::UnknownAbi, span));
+        }
+
+        Ok(ffi_abi_FFI_DEFAULT_ABI)
+    }
+
+    /// Parses a comma-separated list of arguments, recording the position
+    /// of a `...` marker in `fixed_args`.
+    fn args(
+        &mut self,
+        args: &mut Vec<Type>,
+        fixed_args: &mut Option<usize>,
+    ) -> Result<(), ParseError> {
+        loop {
+            if self.peek_punct("...") && fixed_args.is_none() {
+                self.next += 1;
+                *fixed_args = Some(args.len());
+            } else {
+                let arg = self.type_()?;
+                self.name();
+
+                // Array parameters decay to pointers.
+                let arg = self.dimensions(arg)?;
+                args.push(match arg.kind() {
+                    TypeKind::Array { .. } => Type::pointer(),
+                    _ => arg,
+                });
+            }
+
+            if !self.peek_punct(",") {
+                return Ok(());
+            }
+            self.next += 1;
+        }
+    }
+
+    fn cif(&mut self) -> Result<Cif, ParseError> {
+        let start = self.tokens.first().map_or(0, |(_, span)| span.start);
+        let abi = self.abi()?;
+
+        let mut args = Vec::new();
+        let mut fixed_args = None;
+        let result = if self.peek_punct("(") {
+            // `(args) -> result`
+            self.next += 1;
+            if !self.peek_punct(")") {
+                self.args(&mut args, &mut fixed_args)?;
+            }
+            self.expect(")")?;
+            self.expect("->")?;
+            self.type_()?
+        } else if self.peek_punct("->") || self.peek_punct("...") {
+            // `args->result`, starting with no arguments or a `...` marker
+            if !self.peek_punct("->") {
+                self.args(&mut args, &mut fixed_args)?;
+            }
+            self.expect("->")?;
+            self.type_()?
+        } else {
+            let first = self.type_()?;
+            self.name();
+
+            if self.peek_punct("(") {
+                // `result name(args);`
+                self.next += 1;
+                if !self.peek_punct(")") {
+                    self.args(&mut args, &mut fixed_args)?;
+                }
+                self.expect(")")?;
+                if self.peek_punct(";") {
+                    self.next += 1;
+                }
+
+                // `(void)` declares a function without arguments.
+                if fixed_args.is_none() && args.len() == 1 && args[0].kind() == TypeKind::Void {
+                    args.clear();
+                }
+                first
+            } else {
+                // `args->result`
+                args.push(first);
+                if self.peek_punct(",") {
+                    self.next += 1;
+                    self.args(&mut args, &mut fixed_args)?;
+                }
+                self.expect("->")?;
+                self.type_()?
+            }
+        };
+
+        let end = self
+            .tokens
+            .get(self.next - 1)
+            .map_or(0, |(_, span)| span.end);
+        match fixed_args {
+            None => Cif::try_new_with_abi(args, result, abi),
+            Some(fixed_args) => Cif::try_new_variadic_with_abi(args, fixed_args, result, abi),
+        }
+        .map_err(|error| ParseError::new(ParseErrorKind::Prep(error), start..end))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use alloc::string::{String, ToString};
+
+    use super::*;
+
+    fn default_abi() -> String {
+        ABI_NAMES
+            .iter()
+            .find(|&&(_, abi)| abi == ffi_abi_FFI_DEFAULT_ABI)
+            .map_or(ffi_abi_FFI_DEFAULT_ABI.to_string(), |(name, _)| {
+                name.to_string()
+            })
+    }
+
+    fn round_trip(cif: Cif) {
+        let text = cif.to_string();
+        let parsed: Cif = text.parse().unwrap();
+        assert_eq!(cif, parsed, "{text}");
+        assert_eq!(text, parsed.to_string());
+    }
+
+    #[test]
+    fn display_cif() {
+        let cif = Cif::new(
+            [
+                Type::i32(),
+                Type::structure([Type::u8(), Type::u64()]),
+                Type::pointer(),
+            ],
+            Type::f64(),
+        );
+        let abi = ABI_NAMES
+            .iter()
+            .find(|&&(_, abi)| abi == ffi_abi_FFI_DEFAULT_ABI)
+            .map_or(ffi_abi_FFI_DEFAULT_ABI.to_string(), |(name, _)| {
+                name.to_string()
+            });
+
+        assert_eq!(
+            alloc::format!("{abi} (i32, {{u8,u64}}, ptr) -> f64"),
+            cif.to_string()
+        );
+    }
+
+    #[test]
+    fn round_trip_cifs() {
+        round_trip(Cif::new([], Type::void()));
+        round_trip(Cif::new(
+            [
+                Type::structure([Type::u32()]),
+                Type::structure([Type::i8(), Type::structure([Type::f32()])]),
+            ],
+            Type::structure([Type::u16(), Type::pointer()]),
+        ));
+        round_trip(Cif::new_variadic([], 0, Type::i32()));
+        round_trip(Cif::new_variadic([Type::pointer()], 1, Type::i32()));
+        round_trip(Cif::new_variadic(
+            [Type::pointer(), Type::i32(), Type::f64()],
+            1,
+            Type::i32(),
+        ));
+    }
+
+    #[test]
+    fn round_trip_types() {
+        for text in [
+            "void",
+            "u8",
+            "i64",
+            "ptr",
+            "{}",
+            "{f32,{},{i16,f64}}",
+            "[u8;16]",
+            "{[[f32;4];4],[{u8,ptr};2]}",
+        ] {
+            let parsed: Type = text.parse().unwrap();
+            assert_eq!(text, parsed.to_string());
+        }
+
+        #[cfg(all(feature = "complex", not(windows)))]
+        for text in ["c32", "c64", "{c32,u8}"] {
+            let parsed: Type = text.parse().unwrap();
+            assert_eq!(text, parsed.to_string());
+        }
+    }
+
+    #[test]
+    fn parse_whitespace_and_default_abi() {
+        let parsed: Cif = " ( ptr ,...,i64 )->{ u8 , u8 } ".parse().unwrap();
+
+        assert_eq!(
+            Cif::new_variadic(
+                [Type::pointer(), Type::i64()],
+                1,
+                Type::structure([Type::u8(), Type::u8()])
+            ),
+            parsed
+        );
+        assert_eq!(
+            "(ptr, ..., i64) -> {u8,u8}",
+            parsed.to_string().split_once(' ').unwrap().1
+        );
+    }
+
+    #[test]
+    fn parse_c_declarations() {
+        assert_eq!(
+            Cif::new_variadic([Type::pointer()], 1, Type::c_int()),
+            Cif::parse("int printf(const char *format, ...);").unwrap()
+        );
+        assert_eq!(
+            Cif::new([], Type::c_ulong()),
+            Cif::parse("unsigned long int (void)").unwrap()
+        );
+        assert_eq!(
+            Cif::new(
+                [
+                    Type::c_uchar(),
+                    Type::c_longlong(),
+                    Type::usize(),
+                    Type::longdouble(),
+                    Type::pointer(),
+                ],
+                Type::void()
+            ),
+            Cif::parse("void f(unsigned char, long long, size_t n, long double, void **)").unwrap()
+        );
+        assert_eq!(
+            Cif::new(
+                [Type::structure([
+                    Type::c_short(),
+                    Type::structure([Type::f64()])
+                ])],
+                Type::i32()
+            ),
+            Cif::parse("int32_t (struct { short x; struct { double d; } inner; } point)").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_c_arrays() {
+        let record = Type::structure([
+            Type::c_uint(),
+            Type::array(Type::c_schar(), 16),
+            Type::array(Type::array(Type::f32(), 4), 3),
+        ]);
+
+        assert_eq!(
+            Cif::new([record.clone(), Type::pointer()], record),
+            Cif::parse(
+                "struct { unsigned id; signed char name[16]; float m[3][4]; } \
+                 f(struct { unsigned id; signed char name[16]; float m[3][4]; } r, int values[8])"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            (ParseErrorKind::UnexpectedToken, 6..7),
+            Cif::parse("{u8 x[0]} (void)")
+                .map_err(|error| (*error.kind(), error.span()))
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_unions() {
+        let number = Type::union([Type::c_int(), Type::f32(), Type::array(Type::u8(), 4)]);
+
+        assert_eq!("union{i32,f32,[u8;4]}", number.to_string());
+        assert_eq!(number, "union{i32,f32,[u8;4]}".parse().unwrap());
+        assert_eq!(
+            Cif::new([number.clone()], Type::void()),
+            Cif::parse("void f(union { int i; float f; unsigned char b[4]; } n);").unwrap()
+        );
+        round_trip(Cif::new(
+            [Type::structure([Type::u8(), number.clone()])],
+            number,
+        ));
+        assert_eq!(
+            (ParseErrorKind::UnexpectedToken, 7..8),
+            Cif::parse("union {} (void)")
+                .map_err(|error| (*error.kind(), error.span()))
+                .unwrap_err()
+        );
+        assert_eq!(
+            (ParseErrorKind::Prep(low::Error::Typedef), 0..13),
+            Cif::parse("union {u8,{}} (void)")
+                .map_err(|error| (*error.kind(), error.span()))
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_custom_layouts() {
+        let packed = Type::structure_packed([Type::u8(), Type::u32()], 1);
+        let aligned = Type::structure_aligned([Type::f32(), Type::f32(), Type::f32()], 16);
+
+        assert_eq!("packed(1){u8,u32}", packed.to_string());
+        assert_eq!("aligned(16){f32,f32,f32}", aligned.to_string());
+        assert_eq!(packed, "packed(1){u8,u32}".parse().unwrap());
+        round_trip(Cif::new([aligned.clone()], aligned));
+        assert_eq!(
+            (ParseErrorKind::UnexpectedToken, 7..8),
+            Cif::parse("packed(3){u8} (void)")
+                .map_err(|error| (*error.kind(), error.span()))
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn parse_compact() {
+        assert_eq!(
+            Cif::new([Type::i32(), Type::f64(), Type::pointer()], Type::i32()),
+            Cif::parse("i32,f64,ptr->i32").unwrap()
+        );
+        assert_eq!(Cif::new([], Type::void()), Cif::parse("->void").unwrap());
+        assert_eq!(
+            Cif::new_variadic([Type::pointer(), Type::c_int()], 1, Type::f64()),
+            Cif::parse("ptr, ..., int -> double").unwrap()
+        );
+        assert_eq!(
+            Cif::new_variadic([Type::i32()], 0, Type::void()),
+            Cif::parse("...,i32->void").unwrap()
+        );
+        assert_eq!(
+            Cif::new([Type::structure([Type::u8(), Type::u64()])], Type::u8()),
+            Cif::parse(&alloc::format!("{} {{u8,u64}}->u8", default_abi())).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        fn error(input: &str) -> (ParseErrorKind, Range<usize>) {
+            let error = input.parse::<Cif>().unwrap_err();
+            (*error.kind(), error.span())
+        }
+
+        assert_eq!(
+            (ParseErrorKind::UnknownAbi, 0..5),
+            error("bogus (i32) -> void")
+        );
+        assert_eq!((ParseErrorKind::UnknownType, 1..4), error("(f16) -> void"));
+        assert_eq!(
+            (ParseErrorKind::UnexpectedToken, 4..5),
+            error("(i32; i32) -> void")
+        );
+        assert_eq!((ParseErrorKind::UnexpectedEnd, 8..8), error("(i32) ->"));
+        assert_eq!(
+            (ParseErrorKind::UnexpectedToken, 13..14),
+            error("(i32) -> void)")
+        );
+        assert_eq!(
+            (ParseErrorKind::UnexpectedToken, 6..9),
+            error("(..., ...) -> void")
+        );
+        assert_eq!(
+            (ParseErrorKind::UnknownType, 0..10),
+            error("short long (int)")
+        );
+        assert_eq!(
+            (ParseErrorKind::UnexpectedToken, 7..8),
+            error("i32,f64;->i32")
+        );
+        assert!(matches!(
+            error("void (int, struct {})").0,
+            ParseErrorKind::Prep(_)
+        ));
+
+        let nested =
+            |depth| alloc::format!("({}i32{}) -> void", "{".repeat(depth), "}".repeat(depth));
+        assert!(nested(255).parse::<Cif>().is_ok());
+        assert_eq!((ParseErrorKind::TooDeep, 257..260), error(&nested(256)));
+        assert_eq!((ParseErrorKind::TooDeep, 257..258), error(&nested(100_000)));
+    }
+
+    #[test]
+    fn equality_and_hashing() {
+        use core::hash::BuildHasher;
+        use std::collections::hash_map::RandomState;
+
+        let state = RandomState::new();
+        let a = Cif::new([Type::structure([Type::u8(), Type::u64()])], Type::u32());
+        let b = a.clone();
+
+        assert_eq!(a, b);
+        assert_eq!(state.hash_one(&a), state.hash_one(&b));
+        assert_ne!(
+            a,
+            Cif::new([Type::structure([Type::u8(), Type::u32()])], Type::u32())
+        );
+        assert_ne!(
+            a,
+            Cif::new_variadic([Type::structure([Type::u8(), Type::u64()])], 1, Type::u32())
+        );
+        assert_ne!(Type::u32(), Type::i32());
+        assert_eq!(Type::c_int(), Type::i32());
+    }
+}
@@ -7,7 +7,10 @@
 //! a call to a function with those types.
 
 use alloc::vec::Vec;
+use core::alloc::Layout;
 use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
 use core::mem;
 use core::ptr::{addr_of_mut, null_mut};
 use libc;
@@ -44,6 +47,48 @@ type TypeArray_ = *mut Type_;
 // the given reference.
 type Owned<T> = T;
 
+// libffi only knows structures, so every structure type we allocate is
+// followed by a `Shape` recording what it describes. Structure types are
+// never allocated anywhere else, so a `Type_` tagged as a structure can
+// always be cast to a `StructType_`.
+
+/// What a structure type describes.
+#[derive(Clone, Copy, Debug)]
+enum Shape {
+    Struct,
+    Array,
+    /// A union, whose elements are a representation chosen to be passed
+    /// like the union, and whose variants are kept here.
+    Union {
+        variants: TypeArray_,
+    },
+    /// A packed or over-aligned structure, whose elements are a
+    /// representation chosen as for unions, and whose fields are kept here.
+    /// A `pack` or `align` of 0 leaves the layout natural.
+    Layout {
+        fields: TypeArray_,
+        pack: u16,
+        align: u16,
+        passable: bool,
+    },
+}
+
+impl Shape {
+    /// The packing and alignment of a structure with a custom layout.
+    fn layout(self) -> (u16, u16) {
+        match self {
+            Self::Layout { pack, align, .. } => (pack, align),
+            _ => (0, 0),
+        }
+    }
+}
+
+#[repr(C)]
+struct StructType_ {
+    type_: low::ffi_type,
+    shape: Shape,
+}
+
 /// Represents a single C type.
 ///
 /// # Example
@@ -68,8 +113,84 @@ type Owned<T> = T;
 ///     Type::u16(),
 /// ]);
 /// ```
+// `Type` is `repr(transparent)` so that the elements of a struct type’s
+// element array can be borrowed as `&Type`s; see `Type::fields`.
+#[repr(transparent)]
 pub struct Type(Unique<low::ffi_type>);
 
+/// The kind of a C type, as returned by [`Type::kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TypeKind {
+    /// The C `void` type.
+    Void,
+    /// An integer type with the given width in bits and signedness.
+    Int {
+        /// The width of the integer in bits.
+        bits: u32,
+        /// Whether the integer is signed.
+        signed: bool,
+    },
+    /// The C `float` type.
+    Float,
+    /// The C `double` type.
+    Double,
+    /// The C `long double` type, on platforms where it differs from
+    /// `double`.
+    LongDouble,
+    /// A pointer type.
+    Pointer,
+    /// A structure type.
+    Struct,
+    /// A union type, as created by [`Type::union`].
+    Union,
+    /// A fixed-size array type, as created by [`Type::array`].
+    Array {
+        /// The number of elements of the array.
+        len: usize,
+    },
+    /// A complex floating point type.
+    Complex,
+}
+
+/// How [`Type::opaque`] asks for an opaque type to be passed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OpaqueClass {
+    /// Pass the type like a structure of integers, in general purpose
+    /// registers where the ABI allows.
+    #[default]
+    Integer,
+    /// Pass the type like a structure of `float`s or `double`s, in floating
+    /// point registers where the ABI allows.
+    Float,
+}
+
+/// An iterator over the field types of a structure type, the element types
+/// of an array type or the variant types of a union type, as returned by
+/// [`Type::fields`].
+#[derive(Clone, Debug)]
+pub struct Fields<'a> {
+    next: *const Type_,
+    _marker: PhantomData<&'a Type>,
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = &'a Type;
+
+    fn next(&mut self) -> Option<&'a Type> {
+        if self.next.is_null() || unsafe { (*self.next).is_null() } {
+            return None;
+        }
+
+        // Safety: `Type` is a transparent wrapper around a `Type_`, and
+        // the element array lives as long as the structure type.
+        let field = unsafe { &*(self.next as *const Type) };
+        self.next = unsafe { self.next.add(1) };
+        Some(field)
+    }
+}
+
 /// Represents a sequence of C types.
 ///
 /// This can be used to construct a struct type or as the arguments
@@ -135,25 +256,85 @@ unsafe fn ffi_type_struct_create_raw(
     elements: Owned<TypeArray_>,
     size: usize,
     alignment: u16,
+    shape: Shape,
 ) -> Owned<Type_> {
-    let new = libc::malloc(mem::size_of::<low::ffi_type>()) as Type_;
+    let new = libc::malloc(mem::size_of::<StructType_>()) as *mut StructType_;
     assert!(!new.is_null(), "ffi_type_struct_create_raw: out of memory");
 
-    (*new).size = size;
-    (*new).alignment = alignment;
-    (*new).type_ = low::type_tag::STRUCT;
-    (*new).elements = elements;
+    new.write(StructType_ {
+        type_: low::ffi_type {
+            size,
+            alignment,
+            type_: low::type_tag::STRUCT,
+            elements,
+        },
+        shape,
+    });
 
-    new
+    new.cast()
 }
 
-/// Creates a struct `ffi_type` with the given elements. Takes ownership
-/// of the elements.
-unsafe fn ffi_type_struct_create<I>(elements: I) -> Owned<Type_>
+/// Creates a struct `ffi_type` with the given elements, laid out for the
+/// default ABI. Takes ownership of the elements.
+unsafe fn ffi_type_struct_create<I>(elements: I, shape: Shape) -> Owned<Type_>
 where
     I: ExactSizeIterator<Item = Type>,
 {
-    ffi_type_struct_create_raw(ffi_type_array_create(elements), 0, 0)
+    let new = ffi_type_struct_create_raw(ffi_type_array_create(elements), 0, 0, shape);
+    ffi_type_lay_out(new);
+    new
+}
+
+/// Lays out a new struct type, so that libffi never writes its size and
+/// alignment once it may be shared.
+///
+/// Structures are laid out as they are constructed, so a field with size 0
+/// is one that libffi could not lay out. libffi would lay such a field out
+/// again, leaving a partial layout behind when it fails, so the structure
+/// is left at size and alignment 0 without asking libffi. Those are what
+/// `Type::size` and `Type::alignment` report in that case, and they make
+/// `prep_cif` fail. Miri cannot call into libffi, so structures are not
+/// laid out there.
+unsafe fn ffi_type_lay_out(type_: Type_) {
+    let mut field = (*type_).elements;
+    while !(*field).is_null() {
+        if (**field).size == 0 {
+            return;
+        }
+        field = field.add(1);
+    }
+
+    #[cfg(not(miri))]
+    if low::get_struct_offsets(super::ffi_abi_FFI_DEFAULT_ABI, type_, null_mut()).is_err() {
+        (*type_).size = 0;
+        (*type_).alignment = 0;
+    }
+}
+
+/// Gets the shape of a struct type.
+unsafe fn ffi_type_shape(type_: Type_) -> Shape {
+    (*type_.cast::<StructType_>()).shape
+}
+
+/// Makes a copy of a shape.
+unsafe fn shape_clone(shape: Shape) -> Shape {
+    match shape {
+        Shape::Union { variants } => Shape::Union {
+            variants: ffi_type_array_clone(variants),
+        },
+        Shape::Layout {
+            fields,
+            pack,
+            align,
+            passable,
+        } => Shape::Layout {
+            fields: ffi_type_array_clone(fields),
+            pack,
+            align,
+            passable,
+        },
+        shape => shape,
+    }
 }
 
 /// Makes a copy of a type array.
@@ -178,7 +359,12 @@ unsafe fn ffi_type_clone(old: Type_) -> Owned<Type_> {
             ..
         } = *old;
         // Create new
-        ffi_type_struct_create_raw(ffi_type_array_clone(elements), size, alignment)
+        ffi_type_struct_create_raw(
+            ffi_type_array_clone(elements),
+            size,
+            alignment,
+            shape_clone(ffi_type_shape(old)),
+        )
     } else {
         old
     }
@@ -199,10 +385,25 @@ unsafe fn ffi_type_array_destroy(victim: Owned<TypeArray_>) {
 unsafe fn ffi_type_destroy(victim: Owned<Type_>) {
     if (*victim).type_ == low::type_tag::STRUCT {
         ffi_type_array_destroy((*victim).elements);
+        match ffi_type_shape(victim) {
+            Shape::Union { variants: fields } | Shape::Layout { fields, .. } => {
+                ffi_type_array_destroy(fields);
+            }
+            Shape::Struct | Shape::Array => {}
+        }
         libc::free(victim as *mut libc::c_void);
     }
 }
 
+// A `Type` or `TypeArray` uniquely owns its allocations, and is only
+// written through `&mut self` once constructed: structures are laid out
+// eagerly, so reading a size or an alignment never asks libffi to lay one
+// out. Sharing or sending them across threads is therefore sound.
+unsafe impl Send for Type {}
+unsafe impl Sync for Type {}
+unsafe impl Send for TypeArray {}
+unsafe impl Sync for TypeArray {}
+
 impl Drop for Type {
     fn drop(&mut self) {
         unsafe { ffi_type_destroy(*self.0) }
@@ -227,6 +428,62 @@ impl Clone for TypeArray {
     }
 }
 
+// Types are compared structurally: two scalar types are equal if they have
+// the same tag and size, and two structure types are equal if they have the
+// same shape, packing and alignment, and their fields, elements or variants
+// are equal.
+impl PartialEq for Type {
+    fn eq(&self, other: &Self) -> bool {
+        let (this, other_) = unsafe { (&**self.0, &**other.0) };
+
+        this.type_ == other_.type_
+            && if this.type_ == low::type_tag::STRUCT {
+                let same_shape = unsafe {
+                    let (shape, other_shape) = (ffi_type_shape(*self.0), ffi_type_shape(*other.0));
+                    mem::discriminant(&shape) == mem::discriminant(&other_shape)
+                        && shape.layout() == other_shape.layout()
+                };
+                same_shape && self.fields().eq(other.fields())
+            } else {
+                this.size == other_.size
+            }
+    }
+}
+
+impl Eq for Type {}
+
+impl Hash for Type {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let type_ = unsafe { &**self.0 };
+        type_.type_.hash(state);
+
+        if type_.type_ == low::type_tag::STRUCT {
+            let shape = unsafe { ffi_type_shape(*self.0) };
+            mem::discriminant(&shape).hash(state);
+            shape.layout().hash(state);
+            self.fields().count().hash(state);
+            self.fields().for_each(|field| field.hash(state));
+        } else {
+            type_.size.hash(state);
+        }
+    }
+}
+
+impl PartialEq for TypeArray {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for TypeArray {}
+
+impl Hash for TypeArray {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.iter().count().hash(state);
+        self.iter().for_each(|type_| type_.hash(state));
+    }
+}
+
 macro_rules! match_size_signed {
     ( $name:ident ) => {
         match mem::size_of::<core::ffi::$name>() {
@@ -431,20 +688,343 @@ impl Type {
     }
 
     /// Constructs a structure type whose fields have the given types.
+    ///
+    /// The structure is laid out for the default ABI as it is constructed,
+    /// and keeps that layout when it is used with another ABI, as described
+    /// on [`Cif::new_with_abi`](super::Cif::new_with_abi).
     pub fn structure<I>(fields: I) -> Self
     where
         I: IntoIterator<Item = Self>,
         I::IntoIter: ExactSizeIterator<Item = Self>,
     {
-        Self(unsafe { Unique::new(ffi_type_struct_create(fields.into_iter())) })
+        Self(unsafe { Unique::new(ffi_type_struct_create(fields.into_iter(), Shape::Struct)) })
+    }
+
+    /// Constructs a structure type whose fields have the given types, laid
+    /// out as by `#pragma pack(pack)`: each field is aligned to the smaller
+    /// of its own alignment and `pack` bytes.
+    ///
+    /// libffi cannot lay out such structures, so the structure is passed to
+    /// libffi with the size and alignment of the packed structure, and with
+    /// fields chosen as for [`Type::union`]. On x86-64 System V, C passes
+    /// structures with misaligned fields in memory, which libffi cannot
+    /// describe; creating a [`Cif`](super::Cif) that passes or returns such
+    /// a structure by value fails with [`low::Error::Typedef`].
+    ///
+    /// [`Type::struct_offsets`] returns the packed offsets for any ABI.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pack` is not a power of two below 65536, if there are no
+    /// fields, or if a field cannot be laid out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{ffi_abi_FFI_DEFAULT_ABI, Type};
+    ///
+    /// // #pragma pack(1)
+    /// // struct { uint8_t tag; uint32_t value; }
+    /// let mut packed = Type::structure_packed([Type::u8(), Type::u32()], 1);
+    ///
+    /// assert_eq!(5, packed.size());
+    /// assert_eq!(1, packed.alignment());
+    /// assert_eq!(vec![0, 1], packed.struct_offsets(ffi_abi_FFI_DEFAULT_ABI).unwrap());
+    /// ```
+    pub fn structure_packed<I>(fields: I, pack: usize) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+        I::IntoIter: ExactSizeIterator<Item = Self>,
+    {
+        assert!(
+            pack.is_power_of_two() && pack <= 1 << 15,
+            "Type::structure_packed: pack must be a power of two below 65536"
+        );
+        Self::structure_with_layout(fields, pack, 0, "Type::structure_packed")
+    }
+
+    /// Constructs a structure type whose fields have the given types, and
+    /// which is aligned to at least `align` bytes, as by
+    /// `__attribute__((aligned(align)))`. Its size is padded to a multiple
+    /// of its alignment.
+    ///
+    /// The structure is passed to libffi with its alignment and padded
+    /// size, and with fields chosen as for [`Type::union`]. On x86-64
+    /// System V, libffi cannot pass a structure aligned to more than 16
+    /// bytes as an argument, so creating a `Cif` with such an argument
+    /// fails with [`low::Error::Typedef`]. It can still be returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two below 65536, if there are no
+    /// fields, or if a field cannot be laid out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::Type;
+    ///
+    /// // struct { float x, y, z; } __attribute__((aligned(16)))
+    /// let vector = Type::structure_aligned([Type::f32(), Type::f32(), Type::f32()], 16);
+    ///
+    /// assert_eq!(16, vector.size());
+    /// assert_eq!(16, vector.alignment());
+    /// ```
+    pub fn structure_aligned<I>(fields: I, align: usize) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+        I::IntoIter: ExactSizeIterator<Item = Self>,
+    {
+        assert!(
+            align.is_power_of_two() && align <= 1 << 15,
+            "Type::structure_aligned: align must be a power of two below 65536"
+        );
+        Self::structure_with_layout(fields, 0, align, "Type::structure_aligned")
+    }
+
+    /// Constructs a structure type with the given layout and no known
+    /// fields, for passing opaque values by value.
+    ///
+    /// The structure is given fields of the widest integer type, or of
+    /// `double` or `float` for [`OpaqueClass::Float`], that is no more
+    /// aligned than `layout`, and is aligned as `layout`. Its size is
+    /// `layout` padded to its alignment, as for C types.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layout` is empty or aligned to 65536 bytes or more, or for
+    /// [`OpaqueClass::Float`] if `layout` is aligned to less than 4 bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use core::alloc::Layout;
+    /// use libffi::middle::{OpaqueClass, Type};
+    ///
+    /// let handle = Type::opaque(Layout::new::<[u32; 3]>(), OpaqueClass::Integer);
+    ///
+    /// assert_eq!(12, handle.size());
+    /// assert_eq!(4, handle.alignment());
+    /// assert_eq!(Type::structure_aligned([Type::u32(), Type::u32(), Type::u32()], 4), handle);
+    /// ```
+    pub fn opaque(layout: Layout, class: OpaqueClass) -> Self {
+        let layout = layout.pad_to_align();
+        assert!(
+            layout.size() > 0,
+            "Type::opaque: opaque types must not be empty"
+        );
+        assert!(
+            layout.align() <= 1 << 15,
+            "Type::opaque: align must be below 65536"
+        );
+
+        let (field, field_size): (fn() -> Self, usize) = match (class, layout.align()) {
+            (OpaqueClass::Integer, 1) => (Self::u8, 1),
+            (OpaqueClass::Integer, 2) => (Self::u16, 2),
+            (OpaqueClass::Integer, 4) => (Self::u32, 4),
+            (OpaqueClass::Integer, _) => (Self::u64, 8),
+            (OpaqueClass::Float, 1 | 2) => {
+                panic!("Type::opaque: float-class types must be aligned to at least 4 bytes")
+            }
+            (OpaqueClass::Float, 4) => (Self::f32, 4),
+            (OpaqueClass::Float, _) => (Self::f64, 8),
+        };
+
+        let fields = (0..layout.size() / field_size).map(|_| field());
+        Self::structure_aligned(fields, layout.align())
+    }
+
+    /// Constructs a structure type with a custom layout, for
+    /// `structure_packed` and `structure_aligned`.
+    fn structure_with_layout<I>(fields: I, pack: usize, align: usize, name: &str) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+        I::IntoIter: ExactSizeIterator<Item = Self>,
+    {
+        let fields = TypeArray::new(fields);
+        assert!(
+            fields.iter().next().is_some(),
+            "{name}: structures must have at least one field"
+        );
+        for (index, field) in fields.iter().enumerate() {
+            assert!(field.size() > 0, "{name}: field {index} cannot be laid out");
+        }
+
+        let (offsets, size, alignment) = custom_layout(fields.iter(), pack, align);
+        let mut scalars_ = Vec::new();
+        for (field, offset) in fields.iter().zip(&offsets) {
+            scalars(field, *offset, &mut scalars_);
+        }
+
+        let aligned = scalars_.iter().all(|scalar| scalar.aligned);
+        let passable = (aligned || !MISALIGNED_IN_MEMORY) && fields.iter().all(Self::passable);
+        let representation = if !aligned {
+            let mut representation = Vec::new();
+            fill_ints(size, &mut representation);
+            representation
+        } else if scalars_.iter().any(|scalar| scalar.class == Class::Other) {
+            // The fields are at their natural offsets, where libffi finds
+            // them.
+            fields.iter().cloned().collect()
+        } else {
+            representation(size, &scalars_)
+        };
+
+        unsafe {
+            let elements = ffi_type_array_create(representation.into_iter());
+            let fields = mem::ManuallyDrop::new(fields);
+            Self(Unique::new(ffi_type_struct_create_raw(
+                elements,
+                size,
+                alignment as u16,
+                Shape::Layout {
+                    fields: *fields.0,
+                    pack: pack as u16,
+                    align: align as u16,
+                    passable,
+                },
+            )))
+        }
+    }
+
+    /// Constructs a fixed-size array type with `len` elements of type
+    /// `element`, for describing array fields of structures such as
+    /// `char name[16]`.
+    ///
+    /// libffi has no array types, so the array is passed to libffi as a
+    /// structure with `len` fields of type `element`, which has the same
+    /// layout and is classified the same way. Unlike such a structure, the
+    /// resulting type is reported as a [`TypeKind::Array`] by
+    /// [`Type::kind`].
+    ///
+    /// Note that C functions cannot take or return arrays by value, so an
+    /// array type should only be used as a field of a structure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is 0, as libffi cannot lay out empty arrays.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{Type, TypeKind};
+    ///
+    /// // struct { char name[16]; float m[4][4]; }
+    /// let record = Type::structure([
+    ///     Type::array(Type::c_schar(), 16),
+    ///     Type::array(Type::array(Type::f32(), 4), 4),
+    /// ]);
+    ///
+    /// assert_eq!(80, record.size());
+    /// assert_eq!(
+    ///     TypeKind::Array { len: 16 },
+    ///     record.fields().next().unwrap().kind()
+    /// );
+    /// ```
+    pub fn array(element: Self, len: usize) -> Self {
+        assert!(
+            len > 0,
+            "Type::array: arrays must have at least one element"
+        );
+
+        let elements = (0..len).map(|_| element.clone());
+        Self(unsafe { Unique::new(ffi_type_struct_create(elements, Shape::Array)) })
+    }
+
+    /// Constructs a union type whose variants have the given types.
+    ///
+    /// libffi has no union types, so the union is passed to libffi as a
+    /// structure with the size and alignment of the union, whose fields are
+    /// chosen so that libffi passes it the way C does:
+    ///
+    /// - on x86-64 System V, each eightbyte of the union is represented by
+    ///   an integer if any variant has an integer or pointer there, and by
+    ///   floating point fields otherwise, as in the ABI’s classification
+    ///   of unions;
+    /// - on AArch64, 32-bit Arm and 64-bit PowerPC, a union whose variants
+    ///   only contain `float`s, or only contain `double`s, is represented
+    ///   as an array of them, which makes it a homogeneous floating point
+    ///   aggregate, and other unions are represented by integers;
+    /// - elsewhere, the union is represented by integers.
+    ///
+    /// Unions with `long double` or complex members are represented by
+    /// their largest variant instead, as the libffi documentation suggests,
+    /// which may not match how C passes them.
+    ///
+    /// [`Type::fields`] iterates over the variants, not over the
+    /// representation.
+    ///
+    /// Like a [structure](Type::structure), the union is laid out for the
+    /// default ABI and keeps that layout with other ABIs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no variants, or if a variant cannot be laid out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{Type, TypeKind};
+    ///
+    /// // union { int32_t i; float f; double d; }
+    /// let number = Type::union([Type::i32(), Type::f32(), Type::f64()]);
+    ///
+    /// assert_eq!(TypeKind::Union, number.kind());
+    /// assert_eq!(8, number.size());
+    /// assert_eq!(3, number.fields().count());
+    /// ```
+    pub fn union<I>(variants: I) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+        I::IntoIter: ExactSizeIterator<Item = Self>,
+    {
+        let variants = TypeArray::new(variants);
+        assert!(
+            variants.iter().next().is_some(),
+            "Type::union: unions must have at least one variant"
+        );
+
+        let mut size = 0;
+        let mut alignment = 0;
+        let mut scalars_ = Vec::new();
+        for (index, variant) in variants.iter().enumerate() {
+            assert!(
+                variant.size() > 0,
+                "Type::union: variant {index} cannot be laid out"
+            );
+            size = size.max(variant.size());
+            alignment = alignment.max(variant.alignment());
+            scalars(variant, 0, &mut scalars_);
+        }
+        let size = size.next_multiple_of(alignment);
+
+        let representation = if scalars_.iter().any(|scalar| scalar.class == Class::Other) {
+            let largest = variants.iter().max_by_key(|variant| variant.size());
+            alloc::vec![largest.expect("unions are not empty").clone()]
+        } else {
+            representation(size, &scalars_)
+        };
+
+        unsafe {
+            let elements = ffi_type_array_create(representation.into_iter());
+            let variants = mem::ManuallyDrop::new(variants);
+            Self(Unique::new(ffi_type_struct_create_raw(
+                elements,
+                size,
+                alignment as u16,
+                Shape::Union {
+                    variants: *variants.0,
+                },
+            )))
+        }
     }
 
     /// Computes the offsets of this structure's fields for the given ABI.
     ///
     /// The returned offsets are in the same order as the fields passed to
-    /// [`Type::structure`]. This also initializes the structure's size and
-    /// alignment. A structure type should not be laid out for one ABI and then
-    /// reused with another ABI.
+    /// [`Type::structure`]. They are computed for `abi` from the sizes and
+    /// alignments of the fields, whose own fields were laid out for the
+    /// default ABI.
     ///
     /// # Errors
     ///
@@ -467,6 +1047,15 @@ impl Type {
     /// assert!(offsets[1] >= 1);
     /// ```
     pub fn struct_offsets(&mut self, abi: super::FfiAbi) -> low::Result<Vec<usize>> {
+        if let TypeKind::Union = self.kind() {
+            // libffi would lay out the union’s representation as a structure,
+            // overwriting the union’s size and alignment.
+            return Err(low::Error::Typedef);
+        }
+        if let Shape::Layout { pack, align, .. } = unsafe { ffi_type_shape(*self.0) } {
+            return Ok(custom_layout(self.fields(), pack.into(), align.into()).0);
+        }
+
         let struct_type = self.as_raw_ptr();
 
         unsafe {
@@ -482,6 +1071,131 @@ impl Type {
         }
     }
 
+    /// Returns the kind of this type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{Type, TypeKind};
+    ///
+    /// assert_eq!(TypeKind::Int { bits: 16, signed: true }, Type::i16().kind());
+    /// assert_eq!(TypeKind::Pointer, Type::pointer().kind());
+    /// assert_eq!(TypeKind::Struct, Type::structure([Type::u8()]).kind());
+    /// ```
+    pub fn kind(&self) -> TypeKind {
+        let type_ = unsafe { &**self.0 };
+        let int = |signed| TypeKind::Int {
+            bits: type_.size as u32 * 8,
+            signed,
+        };
+
+        match type_.type_ {
+            low::type_tag::STRUCT => match unsafe { ffi_type_shape(*self.0) } {
+                Shape::Struct | Shape::Layout { .. } => TypeKind::Struct,
+                Shape::Array => TypeKind::Array {
+                    len: self.fields().count(),
+                },
+                Shape::Union { .. } => TypeKind::Union,
+            },
+            crate::raw::FFI_TYPE_VOID => TypeKind::Void,
+            crate::raw::FFI_TYPE_FLOAT => TypeKind::Float,
+            crate::raw::FFI_TYPE_DOUBLE => TypeKind::Double,
+            crate::raw::FFI_TYPE_LONGDOUBLE => TypeKind::LongDouble,
+            crate::raw::FFI_TYPE_POINTER => TypeKind::Pointer,
+            crate::raw::FFI_TYPE_COMPLEX => TypeKind::Complex,
+            crate::raw::FFI_TYPE_INT
+            | crate::raw::FFI_TYPE_SINT8
+            | crate::raw::FFI_TYPE_SINT16
+            | crate::raw::FFI_TYPE_SINT32
+            | crate::raw::FFI_TYPE_SINT64 => int(true),
+            crate::raw::FFI_TYPE_UINT8
+            | crate::raw::FFI_TYPE_UINT16
+            | crate::raw::FFI_TYPE_UINT32
+            | crate::raw::FFI_TYPE_UINT64 => int(false),
+            tag => unreachable!("unknown libffi type tag {tag}"),
+        }
+    }
+
+    /// Returns the size of this type in bytes.
+    ///
+    /// Structure types are laid out for the default ABI when they are
+    /// constructed. Returns 0 for `void`, and for structures that libffi
+    /// cannot lay out.
+    pub fn size(&self) -> usize {
+        unsafe { (**self.0).size }
+    }
+
+    /// Returns the alignment of this type in bytes.
+    ///
+    /// Structure types are laid out for the default ABI when they are
+    /// constructed. Returns 0 for structures that libffi cannot lay out.
+    pub fn alignment(&self) -> usize {
+        unsafe { (**self.0).alignment as usize }
+    }
+
+    /// Returns an iterator over the field types of a structure type, or the
+    /// element types of an array type.
+    ///
+    /// The iterator is empty for any other kind of type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::{Type, TypeKind};
+    ///
+    /// let structure = Type::structure([Type::f64(), Type::pointer()]);
+    /// let kinds: Vec<_> = structure.fields().map(Type::kind).collect();
+    ///
+    /// assert_eq!(vec![TypeKind::Double, TypeKind::Pointer], kinds);
+    /// ```
+    pub fn fields(&self) -> Fields<'_> {
+        let type_ = unsafe { &**self.0 };
+        let next = if type_.type_ == low::type_tag::STRUCT {
+            match unsafe { ffi_type_shape(*self.0) } {
+                Shape::Union { variants: fields } | Shape::Layout { fields, .. } => {
+                    fields as *const Type_
+                }
+                Shape::Struct | Shape::Array => type_.elements as *const Type_,
+            }
+        } else {
+            core::ptr::null()
+        };
+
+        Fields {
+            next,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the packing and minimum alignment of a structure created by
+    /// [`Type::structure_packed`] or [`Type::structure_aligned`], where 0
+    /// means natural.
+    pub(crate) fn custom_layout(&self) -> (usize, usize) {
+        if unsafe { (**self.0).type_ } != low::type_tag::STRUCT {
+            return (0, 0);
+        }
+
+        let (pack, align) = unsafe { ffi_type_shape(*self.0) }.layout();
+        (pack.into(), align.into())
+    }
+
+    /// Whether libffi can pass this type by value as C does.
+    pub(crate) fn passable(&self) -> bool {
+        if unsafe { (**self.0).type_ } != low::type_tag::STRUCT {
+            return true;
+        }
+
+        match unsafe { ffi_type_shape(*self.0) } {
+            Shape::Layout { passable, .. } => passable,
+            _ => self.fields().all(Self::passable),
+        }
+    }
+
+    /// Whether libffi can pass this type as an argument by value as C does.
+    pub(crate) fn passable_as_arg(&self) -> bool {
+        self.passable() && (!OVERALIGNED_MISPLACED || self.alignment() <= 16)
+    }
+
     /// Gets a raw pointer to the underlying [`low::ffi_type`].
     ///
     /// This method may be useful for interacting with the
@@ -491,6 +1205,183 @@ impl Type {
     }
 }
 
+/// How a scalar in an aggregate is passed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Int,
+    F32,
+    F64,
+    Other,
+}
+
+/// A scalar within a union or a structure with a custom layout.
+#[derive(Clone, Copy, Debug)]
+struct Scalar {
+    offset: usize,
+    size: usize,
+    class: Class,
+    aligned: bool,
+}
+
+/// Returns the offsets of the fields of a structure or array type, without
+/// asking libffi, which would write to the type.
+fn field_offsets(type_: &Type) -> Vec<usize> {
+    let (pack, _) = unsafe { ffi_type_shape(*type_.0) }.layout();
+    custom_layout(type_.fields(), pack.into(), 0).0
+}
+
+/// Lays out a structure whose fields are aligned to at most `pack` bytes
+/// unless it is 0, and which is aligned to at least `align` bytes,
+/// returning its field offsets, size and alignment.
+fn custom_layout(fields: Fields, pack: usize, align: usize) -> (Vec<usize>, usize, usize) {
+    let mut offsets = Vec::new();
+    let mut end: usize = 0;
+    let mut alignment = 1;
+
+    for field in fields {
+        let field_alignment = match pack {
+            0 => field.alignment(),
+            pack => field.alignment().min(pack),
+        };
+        let offset = end.next_multiple_of(field_alignment);
+        offsets.push(offset);
+        end = offset + field.size();
+        alignment = alignment.max(field_alignment);
+    }
+
+    let alignment = alignment.max(align);
+    (offsets, end.next_multiple_of(alignment), alignment)
+}
+
+/// Collects the scalars in `type_`, which starts at `offset` within a union
+/// or a structure with a custom layout.
+fn scalars(type_: &Type, offset: usize, scalars_: &mut Vec<Scalar>) {
+    let class = match type_.kind() {
+        TypeKind::Int { .. } | TypeKind::Pointer => Class::Int,
+        TypeKind::Float => Class::F32,
+        TypeKind::Double => Class::F64,
+        TypeKind::Struct | TypeKind::Array { .. } => {
+            for (field, field_offset) in type_.fields().zip(field_offsets(type_)) {
+                scalars(field, offset + field_offset, scalars_);
+            }
+            return;
+        }
+        TypeKind::Union => {
+            for variant in type_.fields() {
+                scalars(variant, offset, scalars_);
+            }
+            return;
+        }
+        _ => Class::Other,
+    };
+
+    scalars_.push(Scalar {
+        offset,
+        size: type_.size(),
+        class,
+        aligned: offset % type_.alignment() == 0,
+    });
+}
+
+/// Appends integer types covering `len` bytes to `representation`.
+fn fill_ints(mut len: usize, representation: &mut Vec<Type>) {
+    for (size, int) in [
+        (8, Type::u64 as fn() -> Type),
+        (4, Type::u32),
+        (2, Type::u16),
+        (1, Type::u8),
+    ] {
+        while len >= size {
+            representation.push(int());
+            len -= size;
+        }
+    }
+}
+
+/// Whether the C ABI passes an aggregate containing a misaligned scalar
+/// in memory, which libffi cannot describe.
+const MISALIGNED_IN_MEMORY: bool = cfg!(all(target_arch = "x86_64", not(windows)));
+
+/// Whether libffi misplaces arguments aligned to more than 16 bytes, which
+/// the C ABI passes on the stack, as it aligns them within a buffer that it
+/// then copies to a stack only aligned to 16 bytes.
+const OVERALIGNED_MISPLACED: bool = cfg!(all(target_arch = "x86_64", not(windows)));
+
+/// Chooses the fields of a structure that libffi passes like an aggregate
+/// of size `size` with the given scalars.
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+fn representation(size: usize, scalars_: &[Scalar]) -> Vec<Type> {
+    let mut representation = Vec::new();
+
+    for start in (0..size).step_by(8) {
+        let end = size.min(start + 8);
+        let in_eightbyte = || {
+            scalars_
+                .iter()
+                .filter(move |scalar| scalar.offset < end && scalar.offset + scalar.size > start)
+                .map(|scalar| scalar.class)
+        };
+
+        if in_eightbyte().next().is_none() {
+            // Padding is not passed, which libffi handles by classifying
+            // the eightbyte as having no class.
+        } else if in_eightbyte().any(|class| class == Class::Int) {
+            fill_ints(end - start, &mut representation);
+        } else if in_eightbyte().any(|class| class == Class::F64) {
+            representation.push(Type::f64());
+        } else {
+            representation.extend((start..end).step_by(4).map(|_| Type::f32()));
+        }
+    }
+
+    representation
+}
+
+/// Chooses the fields of a structure that libffi passes like an aggregate
+/// of size `size` with the given scalars.
+#[cfg(any(
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "powerpc64"
+))]
+fn representation(size: usize, scalars_: &[Scalar]) -> Vec<Type> {
+    let mut representation = Vec::new();
+
+    let (float, float_size) = match scalars_.first().map(|scalar| scalar.class) {
+        Some(Class::F32) => (Type::f32 as fn() -> Type, 4),
+        Some(Class::F64) => (Type::f64 as fn() -> Type, 8),
+        _ => (Type::void as fn() -> Type, 0),
+    };
+    let homogeneous = float_size > 0
+        && scalars_
+            .iter()
+            .all(|scalar| scalar.class == scalars_[0].class)
+        && size % float_size == 0
+        && size / float_size <= 4;
+
+    if homogeneous {
+        representation.extend((0..size / float_size).map(|_| float()));
+    } else {
+        fill_ints(size, &mut representation);
+    }
+
+    representation
+}
+
+/// Chooses the fields of a structure that libffi passes like an aggregate
+/// of size `size` with the given scalars.
+#[cfg(not(any(
+    all(target_arch = "x86_64", not(windows)),
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "powerpc64"
+)))]
+fn representation(size: usize, _scalars: &[Scalar]) -> Vec<Type> {
+    let mut representation = Vec::new();
+    fill_ints(size, &mut representation);
+    representation
+}
+
 impl TypeArray {
     /// Constructs an array the given `Type`s.
     pub fn new<I>(elements: I) -> Self
@@ -501,6 +1392,21 @@ impl TypeArray {
         Self(unsafe { Unique::new(ffi_type_array_create(elements.into_iter())) })
     }
 
+    /// Returns an iterator over the types in the array.
+    pub(crate) fn iter(&self) -> Fields<'_> {
+        Fields {
+            next: *self.0 as *const Type_,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Borrows the types in the array as a slice.
+    pub(crate) fn as_slice(&self) -> &[Type] {
+        // Safety: `Type` is a transparent wrapper around a `Type_`, and the
+        // array holds `ffi_type_array_len` of them before its terminator.
+        unsafe { core::slice::from_raw_parts(*self.0 as *const Type, ffi_type_array_len(*self.0)) }
+    }
+
     /// Gets a raw pointer to the underlying C array of
     /// [`low::ffi_type`]s.
     ///
@@ -577,6 +1483,259 @@ mod test {
         let _string = format!("{ffi_type:?}");
     }
 
+    #[test]
+    fn type_kind() {
+        assert_eq!(TypeKind::Void, Type::void().kind());
+        assert_eq!(
+            TypeKind::Int {
+                bits: 8,
+                signed: false
+            },
+            Type::u8().kind()
+        );
+        assert_eq!(
+            TypeKind::Int {
+                bits: 64,
+                signed: true
+            },
+            Type::i64().kind()
+        );
+        assert_eq!(
+            TypeKind::Int {
+                bits: 8 * mem::size_of::<usize>() as u32,
+                signed: false
+            },
+            Type::usize().kind()
+        );
+        assert_eq!(TypeKind::Float, Type::f32().kind());
+        assert_eq!(TypeKind::Double, Type::f64().kind());
+        assert_eq!(TypeKind::Pointer, Type::pointer().kind());
+        assert_eq!(TypeKind::Struct, Type::structure([]).kind());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Structures are laid out by libffi")]
+    fn array_type() {
+        #[repr(C)]
+        struct WithArrays {
+            tag: u8,
+            name: [u8; 5],
+            m: [[f32; 3]; 2],
+        }
+
+        let array = Type::array(Type::u16(), 3);
+        assert_eq!(TypeKind::Array { len: 3 }, array.kind());
+        assert_eq!(3, array.fields().count());
+        assert_eq!(6, array.size());
+        assert_eq!(array, array.clone());
+        assert_eq!(TypeKind::Array { len: 3 }, array.clone().kind());
+        assert_ne!(
+            array,
+            Type::structure([Type::u16(), Type::u16(), Type::u16()])
+        );
+
+        let mut ffi_type = Type::structure([
+            Type::u8(),
+            Type::array(Type::u8(), 5),
+            Type::array(Type::array(Type::f32(), 3), 2),
+        ]);
+        assert_eq!(mem::size_of::<WithArrays>(), ffi_type.size());
+        assert_eq!(mem::align_of::<WithArrays>(), ffi_type.alignment());
+        assert_eq!(
+            alloc::vec![
+                mem::offset_of!(WithArrays, tag),
+                mem::offset_of!(WithArrays, name),
+                mem::offset_of!(WithArrays, m),
+            ],
+            ffi_type
+                .struct_offsets(low::ffi_abi_FFI_DEFAULT_ABI)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Type::array")]
+    fn empty_array_type() {
+        let _ = Type::array(Type::u8(), 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Structures are laid out by libffi")]
+    fn union_type() {
+        #[repr(C)]
+        union Number {
+            b: u8,
+            f: f32,
+            pair: [u16; 3],
+        }
+
+        #[repr(C)]
+        struct Tagged {
+            tag: u8,
+            value: Number,
+        }
+
+        let number = Type::union([Type::u8(), Type::f32(), Type::array(Type::u16(), 3)]);
+        assert_eq!(TypeKind::Union, number.kind());
+        assert_eq!(
+            alloc::vec![Type::u8(), Type::f32(), Type::array(Type::u16(), 3)],
+            number.fields().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(mem::size_of::<Number>(), number.size());
+        assert_eq!(mem::align_of::<Number>(), number.alignment());
+        assert_eq!(number, number.clone());
+        assert_eq!(TypeKind::Union, number.clone().kind());
+        assert_ne!(
+            number,
+            Type::structure([Type::u8(), Type::f32(), Type::array(Type::u16(), 3)])
+        );
+        assert_eq!(
+            Err(low::Error::Typedef),
+            number.clone().struct_offsets(low::ffi_abi_FFI_DEFAULT_ABI)
+        );
+
+        let mut tagged = Type::structure([Type::u8(), number]);
+        assert_eq!(mem::size_of::<Tagged>(), tagged.size());
+        assert_eq!(mem::align_of::<Tagged>(), tagged.alignment());
+        assert_eq!(
+            alloc::vec![mem::offset_of!(Tagged, tag), mem::offset_of!(Tagged, value)],
+            tagged.struct_offsets(low::ffi_abi_FFI_DEFAULT_ABI).unwrap()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Type::union")]
+    fn empty_union_type() {
+        let _ = Type::union([]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Structures are laid out by libffi")]
+    fn custom_layout_types() {
+        #[repr(C, packed(2))]
+        struct Packed {
+            tag: u8,
+            value: u64,
+        }
+
+        #[repr(C, align(16))]
+        struct Aligned {
+            tag: u8,
+            value: u32,
+        }
+
+        let mut packed = Type::structure_packed([Type::u8(), Type::u64()], 2);
+        assert_eq!(TypeKind::Struct, packed.kind());
+        assert_eq!(2, packed.fields().count());
+        assert_eq!(mem::size_of::<Packed>(), packed.size());
+        assert_eq!(mem::align_of::<Packed>(), packed.alignment());
+        assert_eq!(
+            alloc::vec![mem::offset_of!(Packed, tag), mem::offset_of!(Packed, value)],
+            packed.struct_offsets(low::ffi_abi_FFI_DEFAULT_ABI).unwrap()
+        );
+
+        let mut aligned = Type::structure_aligned([Type::u8(), Type::u32()], 16);
+        assert_eq!(mem::size_of::<Aligned>(), aligned.size());
+        assert_eq!(mem::align_of::<Aligned>(), aligned.alignment());
+        assert_eq!(
+            alloc::vec![
+                mem::offset_of!(Aligned, tag),
+                mem::offset_of!(Aligned, value)
+            ],
+            aligned
+                .struct_offsets(low::ffi_abi_FFI_DEFAULT_ABI)
+                .unwrap()
+        );
+
+        let natural = Type::structure([Type::u8(), Type::u32()]);
+        assert_eq!(aligned, aligned.clone());
+        assert_eq!(16, aligned.clone().alignment());
+        assert_ne!(natural, aligned);
+        assert_ne!(
+            Type::structure_aligned([Type::u8(), Type::u32()], 8),
+            aligned
+        );
+        assert_ne!(
+            Type::structure_packed([Type::u8(), Type::u32()], 16),
+            aligned
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Structures are laid out by libffi")]
+    fn opaque_type() {
+        use core::alloc::Layout;
+
+        for (layout, class) in [
+            (Layout::new::<u8>(), OpaqueClass::Integer),
+            (Layout::new::<[u16; 3]>(), OpaqueClass::Integer),
+            (Layout::new::<(u64, u8)>(), OpaqueClass::Integer),
+            (Layout::new::<[f32; 3]>(), OpaqueClass::Float),
+            (Layout::from_size_align(24, 16).unwrap(), OpaqueClass::Float),
+        ] {
+            let opaque = Type::opaque(layout, class);
+            let layout = layout.pad_to_align();
+            assert_eq!(TypeKind::Struct, opaque.kind());
+            assert_eq!(layout.size(), opaque.size());
+            assert_eq!(layout.align(), opaque.alignment());
+        }
+
+        let opaque = Type::opaque(Layout::new::<[f64; 2]>(), OpaqueClass::Float);
+        assert_eq!(
+            alloc::vec![TypeKind::Double, TypeKind::Double],
+            opaque.fields().map(Type::kind).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Type::opaque")]
+    fn opaque_float_type_requires_alignment() {
+        let _ = Type::opaque(core::alloc::Layout::new::<[u16; 2]>(), OpaqueClass::Float);
+    }
+
+    #[test]
+    #[should_panic(expected = "Type::structure_packed")]
+    fn structure_packed_requires_power_of_two() {
+        let _ = Type::structure_packed([Type::u8()], 3);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Structures are laid out by libffi")]
+    fn type_size_and_alignment() {
+        assert_eq!(4, Type::u32().size());
+        assert_eq!(4, Type::u32().alignment());
+
+        let ffi_type = Type::structure([Type::u8(), Type::u64(), Type::u16()]);
+        assert_eq!(mem::size_of::<StructWithPadding>(), ffi_type.size());
+        assert_eq!(mem::align_of::<StructWithPadding>(), ffi_type.alignment());
+    }
+
+    #[test]
+    fn type_fields() {
+        let ffi_type =
+            Type::structure([Type::u16(), Type::structure([Type::f32(), Type::pointer()])]);
+
+        let fields: alloc::vec::Vec<_> = ffi_type.fields().collect();
+        assert_eq!(2, fields.len());
+        assert_eq!(
+            TypeKind::Int {
+                bits: 16,
+                signed: false
+            },
+            fields[0].kind()
+        );
+        assert_eq!(
+            alloc::vec![TypeKind::Float, TypeKind::Pointer],
+            fields[1]
+                .fields()
+                .map(Type::kind)
+                .collect::<alloc::vec::Vec<_>>()
+        );
+
+        assert_eq!(0, Type::u32().fields().count());
+        assert_eq!(0, Type::structure([]).fields().count());
+    }
+
     #[test]
     fn verify_type_layout() {
         let ffi_struct = Type::structure([
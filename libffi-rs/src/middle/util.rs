@@ -1,6 +1,7 @@
 use core::marker::PhantomData;
 use core::ops::Deref;
 
+#[repr(transparent)]
 pub struct Unique<T> {
     contents: *mut T,
     _marker: PhantomData<T>,
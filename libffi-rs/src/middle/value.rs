@@ -0,0 +1,520 @@
+//! Dynamically typed values for calling functions through a [`Cif`].
+//!
+//! [`Cif::call`] takes untyped argument pointers and trusts the caller to
+//! pick the right result type. When the signature is only known at run
+//! time, [`Cif::call_values`] instead takes [`Value`]s, checks each of them
+//! against the argument types that the CIF was built with, and returns the
+//! result as a [`Value`] of the CIF’s result type.
+
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::fmt;
+use core::mem;
+use core::ptr::{self, null_mut};
+
+use super::{Cif, CodePtr};
+use crate::{low, raw};
+
+/// A C value whose type is only known at run time.
+///
+/// Each variant corresponds to one kind of [`Type`](super::Type). Structs
+/// are represented by their raw bytes, which must be exactly as many as the
+/// size of the struct type they are passed as.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// The absence of a value, as returned by `void` functions.
+    Void,
+    /// A signed 8-bit integer.
+    I8(i8),
+    /// An unsigned 8-bit integer.
+    U8(u8),
+    /// A signed 16-bit integer.
+    I16(i16),
+    /// An unsigned 16-bit integer.
+    U16(u16),
+    /// A signed 32-bit integer.
+    I32(i32),
+    /// An unsigned 32-bit integer.
+    U32(u32),
+    /// A signed 64-bit integer.
+    I64(i64),
+    /// An unsigned 64-bit integer.
+    U64(u64),
+    /// A C `float`.
+    F32(f32),
+    /// A C `double`.
+    F64(f64),
+    /// A pointer of any type.
+    Pointer(*mut c_void),
+    /// The bytes of a struct passed or returned by value.
+    Struct(Vec<u8>),
+}
+
+impl Value {
+    /// Gets the name of this value’s type, as used in [`CallError`]s.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Void => "void",
+            Value::I8(_) => "i8",
+            Value::U8(_) => "u8",
+            Value::I16(_) => "i16",
+            Value::U16(_) => "u16",
+            Value::I32(_) => "i32",
+            Value::U32(_) => "u32",
+            Value::I64(_) => "i64",
+            Value::U64(_) => "u64",
+            Value::F32(_) => "f32",
+            Value::F64(_) => "f64",
+            Value::Pointer(_) => "pointer",
+            Value::Struct(_) => "struct",
+        }
+    }
+
+    /// Gets a pointer to the scalar stored in this value, or `None` if the
+    /// value does not have type `type_`.
+    fn scalar_ptr(&self, type_: &low::ffi_type) -> Option<*mut c_void> {
+        fn ptr<T>(value: &T) -> Option<*mut c_void> {
+            Some(value as *const T as *mut c_void)
+        }
+
+        match (self, type_.type_) {
+            (Value::I8(v), raw::FFI_TYPE_SINT8) => ptr(v),
+            (Value::U8(v), raw::FFI_TYPE_UINT8) => ptr(v),
+            (Value::I16(v), raw::FFI_TYPE_SINT16) => ptr(v),
+            (Value::U16(v), raw::FFI_TYPE_UINT16) => ptr(v),
+            (Value::I32(v), raw::FFI_TYPE_SINT32) => ptr(v),
+            (Value::I32(v), raw::FFI_TYPE_INT) if type_.size == mem::size_of::<i32>() => ptr(v),
+            (Value::U32(v), raw::FFI_TYPE_UINT32) => ptr(v),
+            (Value::I64(v), raw::FFI_TYPE_SINT64) => ptr(v),
+            (Value::U64(v), raw::FFI_TYPE_UINT64) => ptr(v),
+            (Value::F32(v), raw::FFI_TYPE_FLOAT) => ptr(v),
+            (Value::F64(v), raw::FFI_TYPE_DOUBLE) => ptr(v),
+            (Value::Pointer(v), raw::FFI_TYPE_POINTER) => ptr(v),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_from_for_value {
+    ( $( $type_:ty => $variant:ident ),* $(,)? ) => {
+        $(
+            impl From<$type_> for Value {
+                fn from(value: $type_) -> Self {
+                    Value::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_for_value! {
+    i8 => I8,
+    u8 => U8,
+    i16 => I16,
+    u16 => U16,
+    i32 => I32,
+    u32 => U32,
+    i64 => I64,
+    u64 => U64,
+    f32 => F32,
+    f64 => F64,
+    *mut c_void => Pointer,
+}
+
+impl From<()> for Value {
+    fn from((): ()) -> Self {
+        Value::Void
+    }
+}
+
+/// The errors reported by [`Cif::call_values`].
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallError {
+    /// The number of values differs from the number of arguments of the CIF.
+    ArgCount {
+        /// The number of arguments of the CIF.
+        expected: usize,
+        /// The number of values passed.
+        found: usize,
+    },
+    /// A value does not have the type of the corresponding argument.
+    ArgType {
+        /// The position of the argument.
+        index: usize,
+        /// The name of the argument’s type in the CIF.
+        expected: &'static str,
+        /// The name of the value’s type.
+        found: &'static str,
+    },
+    /// The CIF’s result type cannot be represented as a [`Value`].
+    ResultType {
+        /// The name of the result type.
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::ArgCount { expected, found } => {
+                write!(f, "expected {expected} arguments, found {found}")
+            }
+            CallError::ArgType {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "argument {index} has type {expected}, but a {found} was passed"
+            ),
+            CallError::ResultType { found } => {
+                write!(f, "result type {found} cannot be returned as a value")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CallError {}
+
+/// Gets the name of the [`Value`] variant that matches a libffi type.
+fn ffi_type_name(type_: &low::ffi_type) -> &'static str {
+    match type_.type_ {
+        raw::FFI_TYPE_VOID => "void",
+        raw::FFI_TYPE_INT => "int",
+        raw::FFI_TYPE_FLOAT => "f32",
+        raw::FFI_TYPE_DOUBLE => "f64",
+        raw::FFI_TYPE_LONGDOUBLE => "long double",
+        raw::FFI_TYPE_UINT8 => "u8",
+        raw::FFI_TYPE_SINT8 => "i8",
+        raw::FFI_TYPE_UINT16 => "u16",
+        raw::FFI_TYPE_SINT16 => "i16",
+        raw::FFI_TYPE_UINT32 => "u32",
+        raw::FFI_TYPE_SINT32 => "i32",
+        raw::FFI_TYPE_UINT64 => "u64",
+        raw::FFI_TYPE_SINT64 => "i64",
+        raw::FFI_TYPE_STRUCT => "struct",
+        raw::FFI_TYPE_POINTER => "pointer",
+        raw::FFI_TYPE_COMPLEX => "complex",
+        _ => "unknown",
+    }
+}
+
+/// A chunk of memory aligned for any type libffi can pass by value.
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct Chunk([u8; 16]);
+
+/// Allocates zeroed, maximally aligned memory for at least `size` bytes.
+fn aligned_buffer(size: usize) -> Vec<Chunk> {
+    alloc::vec![Chunk([0; 16]); size.div_ceil(mem::size_of::<Chunk>())]
+}
+
+/// Calls `fun` and reads a result of type `T`.
+///
+/// # Safety
+///
+/// The result type of `cif` must be represented by `T`, and the other
+/// requirements of [`low::call_return_into`] must hold.
+unsafe fn call_scalar<T>(cif: *mut low::ffi_cif, fun: CodePtr, args: *mut *mut c_void) -> T {
+    let mut result = mem::MaybeUninit::<T>::uninit();
+    low::call_return_into(cif, fun, args, result.as_mut_ptr().cast());
+    result.assume_init()
+}
+
+impl Cif {
+    /// Calls a function with dynamically typed arguments.
+    ///
+    /// Each of `args` is checked against the corresponding argument type of
+    /// this CIF before the call is made, and the result is returned as a
+    /// [`Value`] of the CIF’s result type. Small integer results are
+    /// narrowed back to their declared width, as in [`Cif::call`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`CallError`] without calling `fun` if the number or types
+    /// of `args` do not match the CIF, or if the result type is one that
+    /// [`Value`] cannot represent (such as `long double`).
+    ///
+    /// # Safety
+    ///
+    /// There is no checking that the calling convention and types in the
+    /// `Cif` match the actual calling convention and types of `fun`.
+    /// Pointer values are passed through unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libffi::middle::*;
+    ///
+    /// extern "C" fn scale(x: u8, by: f64) -> f64 {
+    ///     f64::from(x) * by
+    /// }
+    ///
+    /// let cif = Cif::new([Type::u8(), Type::f64()], Type::f64());
+    ///
+    /// let result = unsafe {
+    ///     cif.call_values(CodePtr(scale as *mut _), &[Value::U8(3), Value::F64(0.5)])
+    /// };
+    /// assert_eq!(Ok(Value::F64(1.5)), result);
+    ///
+    /// let result = unsafe {
+    ///     cif.call_values(CodePtr(scale as *mut _), &[Value::I32(3), Value::F64(0.5)])
+    /// };
+    /// assert!(matches!(result, Err(CallError::ArgType { index: 0, .. })));
+    /// ```
+    pub unsafe fn call_values(&self, fun: CodePtr, args: &[Value]) -> Result<Value, CallError> {
+        let nargs = self.cif.nargs as usize;
+        if args.len() != nargs {
+            return Err(CallError::ArgCount {
+                expected: nargs,
+                found: args.len(),
+            });
+        }
+
+        let arg_types = if nargs == 0 {
+            &[][..]
+        } else {
+            core::slice::from_raw_parts(self.cif.arg_types, nargs)
+        };
+
+        // Struct arguments are copied into aligned storage, since the bytes
+        // of a `Value::Struct` are only byte-aligned.
+        let mut struct_storage = Vec::new();
+        let mut arg_ptrs = Vec::with_capacity(nargs);
+
+        for (index, (value, &type_)) in args.iter().zip(arg_types).enumerate() {
+            let type_ = &*type_;
+            let arg_ptr = match value {
+                Value::Struct(bytes)
+                    if type_.type_ == raw::FFI_TYPE_STRUCT && bytes.len() == type_.size =>
+                {
+                    let mut buffer = aligned_buffer(bytes.len());
+                    ptr::copy_nonoverlapping(
+                        bytes.as_ptr(),
+                        buffer.as_mut_ptr().cast::<u8>(),
+                        bytes.len(),
+                    );
+                    let arg_ptr = buffer.as_mut_ptr().cast();
+                    struct_storage.push(buffer);
+                    Some(arg_ptr)
+                }
+                _ => value.scalar_ptr(type_),
+            };
+
+            arg_ptrs.push(arg_ptr.ok_or(CallError::ArgType {
+                index,
+                expected: ffi_type_name(type_),
+                found: value.type_name(),
+            })?);
+        }
+
+        let cif = self.as_raw_ptr();
+        let args = arg_ptrs.as_mut_ptr();
+        let rtype = &*self.cif.rtype;
+
+        Ok(match rtype.type_ {
+            raw::FFI_TYPE_VOID => {
+                low::call_return_into(cif, fun, args, null_mut());
+                Value::Void
+            }
+            raw::FFI_TYPE_SINT8 => Value::I8(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_UINT8 => Value::U8(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_SINT16 => Value::I16(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_UINT16 => Value::U16(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_SINT32 => Value::I32(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_INT if rtype.size == mem::size_of::<i32>() => {
+                Value::I32(call_scalar(cif, fun, args))
+            }
+            raw::FFI_TYPE_UINT32 => Value::U32(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_SINT64 => Value::I64(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_UINT64 => Value::U64(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_FLOAT => Value::F32(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_DOUBLE => Value::F64(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_POINTER => Value::Pointer(call_scalar(cif, fun, args)),
+            raw::FFI_TYPE_STRUCT => {
+                // libffi may store whole registers when returning small
+                // structs, so leave room for at least two of them.
+                let size = rtype.size;
+                let mut buffer = aligned_buffer(size.max(2 * mem::size_of::<u64>()));
+                low::call_return_into(cif, fun, args, buffer.as_mut_ptr().cast());
+                Value::Struct(
+                    core::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), size).to_vec(),
+                )
+            }
+            _ => {
+                return Err(CallError::ResultType {
+                    found: ffi_type_name(rtype),
+                })
+            }
+        })
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::middle::Type;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Mixed {
+        tag: u8,
+        weight: f64,
+        count: u16,
+    }
+
+    extern "C" fn widen(a: i8, b: u16, c: i32, d: u64) -> i64 {
+        i64::from(a) + i64::from(b) + i64::from(c) + d as i64
+    }
+
+    extern "C" fn negate_i8(x: i8) -> i8 {
+        -x
+    }
+
+    extern "C" fn halve(x: f32) -> f32 {
+        x / 2.0
+    }
+
+    extern "C" fn identity(p: *mut c_void) -> *mut c_void {
+        p
+    }
+
+    extern "C" fn reweigh(mixed: Mixed, weight: f64) -> Mixed {
+        Mixed { weight, ..mixed }
+    }
+
+    extern "C" fn nothing() {}
+
+    fn mixed_type() -> Type {
+        Type::structure([Type::u8(), Type::f64(), Type::u16()])
+    }
+
+    fn mixed_bytes(mixed: &Mixed) -> Vec<u8> {
+        let mut bytes = alloc::vec![0u8; mem::size_of::<Mixed>()];
+        unsafe {
+            ptr::copy_nonoverlapping(
+                (mixed as *const Mixed).cast::<u8>(),
+                bytes.as_mut_ptr(),
+                bytes.len(),
+            );
+        }
+        bytes
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn call_scalars() {
+        let cif = Cif::new(
+            [Type::i8(), Type::u16(), Type::i32(), Type::u64()],
+            Type::i64(),
+        );
+        let result = unsafe {
+            cif.call_values(
+                CodePtr(widen as *mut _),
+                &[Value::I8(-1), Value::U16(2), Value::I32(-3), Value::U64(40)],
+            )
+        };
+        assert_eq!(Ok(Value::I64(38)), result);
+
+        let cif = Cif::new([Type::i8()], Type::i8());
+        let result = unsafe { cif.call_values(CodePtr(negate_i8 as *mut _), &[5i8.into()]) };
+        assert_eq!(Ok(Value::I8(-5)), result);
+
+        let cif = Cif::new([Type::f32()], Type::f32());
+        let result = unsafe { cif.call_values(CodePtr(halve as *mut _), &[3f32.into()]) };
+        assert_eq!(Ok(Value::F32(1.5)), result);
+
+        let mut target = 0u32;
+        let pointer: *mut c_void = ptr::addr_of_mut!(target).cast();
+        let cif = Cif::new([Type::pointer()], Type::pointer());
+        let result = unsafe { cif.call_values(CodePtr(identity as *mut _), &[pointer.into()]) };
+        assert_eq!(Ok(Value::Pointer(pointer)), result);
+
+        let cif = Cif::new([], Type::void());
+        let result = unsafe { cif.call_values(CodePtr(nothing as *mut _), &[]) };
+        assert_eq!(Ok(Value::Void), result);
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "Miri cannot call Rust function pointers through libffi"
+    )]
+    fn call_struct() {
+        let cif = Cif::new([mixed_type(), Type::f64()], mixed_type());
+        let mixed = Mixed {
+            tag: 3,
+            weight: 1.0,
+            count: 500,
+        };
+
+        let result = unsafe {
+            cif.call_values(
+                CodePtr(reweigh as *mut _),
+                &[Value::Struct(mixed_bytes(&mixed)), Value::F64(2.5)],
+            )
+        };
+
+        let expected = Mixed {
+            weight: 2.5,
+            ..mixed
+        };
+        // The padding of the returned structure is unspecified, so only
+        // its fields are compared.
+        let Ok(Value::Struct(bytes)) = result else {
+            panic!("expected a structure, got {result:?}");
+        };
+        assert_eq!(mem::size_of::<Mixed>(), bytes.len());
+        let returned = unsafe { ptr::read_unaligned(bytes.as_ptr().cast::<Mixed>()) };
+        assert_eq!(expected, returned);
+    }
+
+    #[test]
+    fn rejects_mismatched_arguments() {
+        let cif = Cif::new([Type::i32(), mixed_type()], Type::void());
+        let fun = CodePtr(nothing as *mut _);
+
+        assert_eq!(
+            unsafe { cif.call_values(fun, &[Value::I32(1)]) },
+            Err(CallError::ArgCount {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            unsafe { cif.call_values(fun, &[Value::U32(1), Value::Struct(Vec::new())]) },
+            Err(CallError::ArgType {
+                index: 0,
+                expected: "i32",
+                found: "u32"
+            })
+        );
+        assert_eq!(
+            unsafe { cif.call_values(fun, &[Value::I32(1), Value::Struct(alloc::vec![0; 3])]) },
+            Err(CallError::ArgType {
+                index: 1,
+                expected: "struct",
+                found: "struct"
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unrepresentable_result() {
+        let cif = Cif::new([], Type::longdouble());
+        // On some platforms `long double` is the same type as `double`.
+        if unsafe { (*cif.cif.rtype).type_ } != raw::FFI_TYPE_LONGDOUBLE {
+            return;
+        }
+
+        assert!(matches!(
+            unsafe { cif.call_values(CodePtr(nothing as *mut _), &[]) },
+            Err(CallError::ResultType { .. })
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+use core::ffi::c_void;
+use core::{mem, slice};
+
+use crate::low;
+
+/// Iterates over the variadic arguments passed to a closure.
+///
+/// A closure prepared from a variadic CIF (see [`Cif::new_variadic`] and
+/// [`Builder::variadic`]) receives its arguments as an array of pointers,
+/// just like any other closure. `VarArgs` skips the fixed arguments and
+/// yields a pointer to each of the remaining arguments, much like C’s
+/// `va_list`. Unlike a `va_list`, the types of the variadic arguments are
+/// known from the CIF and can be inspected with [`VarArgs::next_type`].
+///
+/// Note that a closure built from a variadic CIF can only be called with
+/// variadic arguments whose types match the ones the CIF was prepared
+/// with.
+///
+/// [`Cif::new_variadic`]: super::Cif::new_variadic
+/// [`Builder::variadic`]: super::Builder::variadic
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_char, c_void};
+///
+/// use libffi::low;
+/// use libffi::middle::*;
+///
+/// unsafe extern "C" fn sum_callback(
+///     cif: &low::ffi_cif,
+///     result: &mut f64,
+///     args: *const *const c_void,
+///     _userdata: &(),
+/// ) {
+///     let mut var_args = VarArgs::new(cif, args, 1);
+///     *result = var_args.next_arg::<i32>().unwrap() as f64
+///         + var_args.next_arg::<f64>().unwrap();
+/// }
+///
+/// let closure = Builder::new()
+///     .args([Type::pointer(), Type::i32(), Type::f64()])
+///     .variadic(1)
+///     .res(Type::f64())
+///     .into_closure(sum_callback, &());
+///
+/// let sum: &unsafe extern "C" fn(*const c_char, ...) -> f64 =
+///     unsafe { closure.instantiate_code_ptr() };
+///
+/// assert_eq!(4.5, unsafe { sum(c"%d %f".as_ptr(), 2i32, 2.5f64) });
+/// ```
+#[derive(Clone, Debug)]
+pub struct VarArgs<'a> {
+    types: slice::Iter<'a, *mut low::ffi_type>,
+    args: slice::Iter<'a, *const c_void>,
+}
+
+impl<'a> VarArgs<'a> {
+    /// Creates an iterator over the variadic arguments of a closure
+    /// invocation.
+    ///
+    /// # Arguments
+    ///
+    /// - `cif` — the CIF passed to the closure’s callback
+    /// - `args` — the argument array passed to the closure’s callback
+    /// - `fixed_args` — the number of fixed arguments to skip
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fixed_args` is greater than the number of arguments in
+    /// `cif`.
+    ///
+    /// # Safety
+    ///
+    /// `cif` and `args` must be the values passed to a closure callback,
+    /// so that `args` points to one argument pointer for each argument
+    /// type in `cif`.
+    pub unsafe fn new(
+        cif: &'a low::ffi_cif,
+        args: *const *const c_void,
+        fixed_args: usize,
+    ) -> Self {
+        let nargs = cif.nargs as usize;
+        assert!(
+            fixed_args <= nargs,
+            "VarArgs::new: {fixed_args} fixed arguments, but the CIF has only {nargs}"
+        );
+
+        let (types, args): (&[_], &[_]) = if nargs == 0 {
+            (&[], &[])
+        } else {
+            (
+                slice::from_raw_parts(cif.arg_types, nargs),
+                slice::from_raw_parts(args, nargs),
+            )
+        };
+
+        Self {
+            types: types[fixed_args..].iter(),
+            args: args[fixed_args..].iter(),
+        }
+    }
+
+    /// Returns the type of the next variadic argument without consuming
+    /// it, or `None` if there are no arguments left.
+    pub fn next_type(&self) -> Option<&'a low::ffi_type> {
+        self.types
+            .as_slice()
+            .first()
+            .map(|&type_| unsafe { &*type_ })
+    }
+
+    /// Reads the next variadic argument as a `T`.
+    ///
+    /// Returns `None` if there are no arguments left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size of `T` differs from the size of the argument’s
+    /// type.
+    ///
+    /// # Safety
+    ///
+    /// The argument must be a valid value of type `T`.
+    pub unsafe fn next_arg<T: Copy>(&mut self) -> Option<T> {
+        let size = self.next_type()?.size;
+        assert_eq!(
+            mem::size_of::<T>(),
+            size,
+            "VarArgs::next_arg: argument has size {size}"
+        );
+
+        self.next().map(|arg| *(arg as *const T))
+    }
+}
+
+impl Iterator for VarArgs<'_> {
+    type Item = *const c_void;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.types.next()?;
+        self.args.next().copied()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.args.size_hint()
+    }
+}
+
+impl ExactSizeIterator for VarArgs<'_> {}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::middle::{Builder, Type};
+    use alloc::vec::Vec;
+    use core::ffi::{c_char, c_int};
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Point {
+        x: i16,
+        y: i16,
+    }
+
+    unsafe extern "C" fn describe_callback(
+        cif: &low::ffi_cif,
+        result: &mut c_int,
+        args: *const *const c_void,
+        userdata: &core::cell::RefCell<Vec<(u16, usize)>>,
+    ) {
+        let mut var_args = VarArgs::new(cif, args, 1);
+        assert_eq!(3, var_args.len());
+
+        while let Some(type_) = var_args.next_type() {
+            userdata.borrow_mut().push((type_.type_, type_.size));
+            var_args.next();
+        }
+
+        let mut var_args = VarArgs::new(cif, args, 1);
+        let count = var_args.next_arg::<c_int>().unwrap();
+        let point = var_args.next_arg::<Point>().unwrap();
+        let scale = var_args.next_arg::<f64>().unwrap();
+        assert!(var_args.next_arg::<u8>().is_none());
+
+        *result = count * (point.x + point.y) as c_int * scale as c_int;
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri cannot execute libffi-generated closures")]
+    fn variadic_closure() {
+        let point_type = Type::structure([Type::i16(), Type::i16()]);
+        let seen = core::cell::RefCell::new(Vec::new());
+
+        let closure = Builder::new()
+            .args([Type::pointer(), Type::c_int(), point_type, Type::f64()])
+            .variadic(1)
+            .res(Type::c_int())
+            .into_closure(describe_callback, &seen);
+
+        let fun: &unsafe extern "C" fn(*const c_char, ...) -> c_int =
+            unsafe { closure.instantiate_code_ptr() };
+        let result = unsafe { fun(c"unused".as_ptr(), 2 as c_int, Point { x: 3, y: 4 }, 3.0f64) };
+
+        assert_eq!(42, result);
+        assert_eq!(
+            alloc::vec![
+                (crate::raw::FFI_TYPE_SINT32, mem::size_of::<c_int>()),
+                (low::type_tag::STRUCT, mem::size_of::<Point>()),
+                (crate::raw::FFI_TYPE_DOUBLE, mem::size_of::<f64>()),
+            ],
+            *seen.borrow()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "VarArgs::new")]
+    fn too_many_fixed_arguments() {
+        let cif = Builder::new().arg(Type::i32()).into_cif();
+        let args = [&0i32 as *const i32 as *const c_void];
+        let _ = unsafe { VarArgs::new(&*cif.as_raw_ptr(), args.as_ptr(), 2) };
+    }
+}
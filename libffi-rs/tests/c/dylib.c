@@ -0,0 +1,15 @@
+#include <stdint.h>
+
+int32_t dylib_add(int32_t a, int32_t b) {
+    return a + b;
+}
+
+double dylib_scale(double x, uint8_t factor, int16_t offset) {
+    return x * factor + offset;
+}
+
+uint64_t dylib_counter = 7;
+
+uint64_t dylib_count(void) {
+    return dylib_counter++;
+}
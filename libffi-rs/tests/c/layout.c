@@ -0,0 +1,64 @@
+#include <stdint.h>
+
+#pragma pack(push, 1)
+struct tagged {
+    uint8_t tag;
+    uint32_t value;
+};
+
+struct bytes {
+    uint8_t a;
+    uint8_t b;
+    uint16_t c;
+};
+#pragma pack(pop)
+
+#pragma pack(push, 2)
+struct shorts {
+    uint16_t a;
+    uint16_t b;
+    uint8_t c;
+};
+#pragma pack(pop)
+
+struct vector {
+    float x, y, z;
+} __attribute__((aligned(16)));
+
+struct padded {
+    int32_t i;
+} __attribute__((aligned(16)));
+
+struct wide {
+    double d;
+    int64_t l;
+} __attribute__((aligned(32)));
+
+uint32_t tagged_value(const struct tagged *t) {
+    return t->tag == 1 ? t->value : 0;
+}
+
+struct bytes bytes_swap(struct bytes b) {
+    struct bytes result = { b.b, b.a, (uint16_t)(b.c + 1) };
+    return result;
+}
+
+struct shorts shorts_rotate(struct shorts s) {
+    struct shorts result = { s.b, s.c, (uint8_t)s.a };
+    return result;
+}
+
+struct vector vector_scale(struct vector v, float k) {
+    struct vector result = { v.x * k, v.y * k, v.z * k };
+    return result;
+}
+
+struct padded padded_add(struct padded p, int32_t k) {
+    struct padded result = { p.i + k };
+    return result;
+}
+
+struct wide wide_mix(const struct wide *w, int64_t k) {
+    struct wide result = { w->d * 2.0, w->l + k };
+    return result;
+}
@@ -0,0 +1,77 @@
+#include <stdint.h>
+
+union ints {
+    uint8_t b;
+    int32_t i;
+    int64_t l;
+};
+
+union floats {
+    float f;
+    float pair[2];
+};
+
+union mixed {
+    float f;
+    int32_t i;
+};
+
+union int_sse {
+    struct { double d; int64_t l; } a;
+    struct { double e; double f; } b;
+};
+
+union memory {
+    double d[3];
+    int64_t l;
+};
+
+struct tagged {
+    uint8_t tag;
+    union mixed value;
+};
+
+union ints ints_add(union ints a, union ints b) {
+    union ints result;
+    result.l = a.l + b.l;
+    return result;
+}
+
+union floats floats_swap(union floats a) {
+    union floats result;
+    result.pair[0] = a.pair[1];
+    result.pair[1] = a.pair[0];
+    return result;
+}
+
+union mixed mixed_negate(union mixed a, int is_float) {
+    union mixed result;
+    if (is_float) {
+        result.f = -a.f;
+    } else {
+        result.i = -a.i;
+    }
+    return result;
+}
+
+union int_sse int_sse_mix(union int_sse a) {
+    union int_sse result;
+    result.b.e = a.a.d * 2.0;
+    result.a.l = a.a.l + 1;
+    return result;
+}
+
+union memory memory_sum(union memory a, double scale) {
+    union memory result;
+    result.d[0] = (a.d[0] + a.d[1] + a.d[2]) * scale;
+    result.d[1] = a.d[1];
+    result.d[2] = a.d[0];
+    return result;
+}
+
+struct tagged tagged_negate(struct tagged a) {
+    struct tagged result;
+    result.tag = a.tag;
+    result.value = mixed_negate(a.value, a.tag);
+    return result;
+}
@@ -0,0 +1,42 @@
+//! Helpers for tests that call C fixtures in `tests/c`.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use libffi::middle::CodePtr;
+use libloading::Library;
+
+/// Compiles the fixture `tests/c/{name}.c` into a shared library and loads
+/// it.
+pub fn fixture(name: &str) -> Library {
+    unsafe { Library::new(compile(name)).unwrap() }
+}
+
+/// Compiles the fixture `tests/c/{name}.c` into a shared library and
+/// returns its path.
+pub fn compile(name: &str) -> PathBuf {
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/c/{name}.c"));
+    let library =
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("libffi_{name}_fixture.so"));
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+    let status = Command::new(compiler)
+        .args(["-shared", "-fPIC", "-O2", "-Wno-psabi", "-o"])
+        .arg(&library)
+        .arg(&source)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile {}", source.display());
+
+    library
+}
+
+/// Looks up a function in a fixture.
+pub fn code_ptr(library: &Library, name: &str) -> CodePtr {
+    unsafe {
+        let symbol = library
+            .get::<unsafe extern "C" fn()>(name.as_bytes())
+            .unwrap();
+        CodePtr(*symbol as *mut _)
+    }
+}
@@ -0,0 +1,83 @@
+//! Loads the functions of `tests/c/dylib.c` with [`libffi::dylib`] and
+//! calls them.
+
+#![cfg(all(feature = "dlopen", unix))]
+
+// Only `compile` is used here.
+#[allow(dead_code)]
+mod common;
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use libffi::dylib::Library;
+use libffi::middle::{arg, Cif, Type};
+
+/// Compiles the fixture once, as the tests run in parallel.
+fn fixture() -> &'static PathBuf {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| common::compile("dylib"))
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "miri cannot load shared libraries")]
+fn call_typed_functions() {
+    let library = Library::open(fixture()).unwrap();
+
+    let add = unsafe { library.function::<(i32, i32), i32>("dylib_add") }.unwrap();
+    let scale = unsafe { library.function::<(f64, u8, i16), f64>("dylib_scale") }.unwrap();
+    let count = unsafe { library.function::<(), u64>("dylib_count") }.unwrap();
+
+    // The functions keep the library loaded.
+    drop(library);
+
+    assert_eq!(-3, add.call(4, -7));
+    assert_eq!(3.0, scale.call(1.25, 4, -2));
+    assert_eq!(7, count.call());
+    assert_eq!(8, count.clone().call());
+    assert_eq!(9, unsafe { count.as_extern_fn() }.unwrap()());
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "miri cannot load shared libraries")]
+fn call_after_library_is_dropped() {
+    let add = {
+        let library = Library::open(fixture()).unwrap();
+        unsafe { library.function::<(i32, i32), i32>("dylib_add") }.unwrap()
+    };
+
+    // The `Function` and its clones keep the library loaded.
+    let clone = add.clone();
+    drop(add);
+    assert_eq!(5, clone.call(2, 3));
+    assert_eq!(1, unsafe { clone.as_extern_fn() }.unwrap()(3, -2));
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "miri cannot load shared libraries")]
+fn call_dynamic_functions() {
+    let library = Library::open(fixture()).unwrap();
+
+    let cif = Cif::new([Type::f64(), Type::u8(), Type::i16()], Type::f64());
+    let scale = library.function_dyn("dylib_scale", cif).unwrap();
+    drop(library);
+
+    let result: f64 = unsafe { scale.call(&[arg(&0.5), arg(&3u8), arg(&1i16)]) };
+    assert_eq!(2.5, result);
+    assert_eq!(3, scale.cif().nargs());
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "miri cannot load shared libraries")]
+fn report_loading_errors() {
+    let error = Library::open("/nonexistent/libffi_dylib.so").unwrap_err();
+    assert!(error.message().contains("/nonexistent/libffi_dylib.so"));
+
+    let library = Library::open(fixture()).unwrap();
+    let error = library
+        .function_dyn("dylib_missing", Cif::new([], Type::void()))
+        .unwrap_err();
+    assert!(error.to_string().contains("dylib_missing"));
+    assert!(library.symbol("dylib_a\0b").is_err());
+    assert!(library.symbol("dylib_counter").is_ok());
+}
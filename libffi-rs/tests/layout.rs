@@ -0,0 +1,278 @@
+//! Calls C functions taking and returning packed and over-aligned
+//! structures, compiled from `tests/c/layout.c`, through [`middle::Cif`]s
+//! using [`Type::structure_packed`], [`Type::structure_aligned`] and
+//! [`Type::opaque`].
+
+#![cfg(all(unix, target_arch = "x86_64"))]
+
+mod common;
+
+use std::alloc::Layout;
+use std::mem;
+
+use libffi::low;
+use libffi::middle::{arg, ffi_abi_FFI_DEFAULT_ABI, Cif, Culprit, OpaqueClass, Type};
+
+use common::{code_ptr, fixture};
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Tagged {
+    tag: u8,
+    value: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Bytes {
+    a: u8,
+    b: u8,
+    c: u16,
+}
+
+#[repr(C, packed(2))]
+#[derive(Clone, Copy)]
+struct Shorts {
+    a: u16,
+    b: u16,
+    c: u8,
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Vector {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct Padded {
+    i: i32,
+}
+
+#[repr(C, align(32))]
+#[derive(Clone, Copy)]
+struct Wide {
+    d: f64,
+    l: i64,
+}
+
+fn tagged() -> Type {
+    Type::structure_packed([Type::u8(), Type::u32()], 1)
+}
+
+fn bytes() -> Type {
+    Type::structure_packed([Type::u8(), Type::u8(), Type::u16()], 1)
+}
+
+fn shorts() -> Type {
+    Type::structure_packed([Type::u16(), Type::u16(), Type::u8()], 2)
+}
+
+fn vector() -> Type {
+    Type::structure_aligned([Type::f32(), Type::f32(), Type::f32()], 16)
+}
+
+fn padded() -> Type {
+    Type::structure_aligned([Type::i32()], 16)
+}
+
+fn wide() -> Type {
+    Type::structure_aligned([Type::f64(), Type::i64()], 32)
+}
+
+#[test]
+fn custom_layouts() {
+    let mut tagged = tagged();
+    assert_eq!(mem::size_of::<Tagged>(), tagged.size());
+    assert_eq!(mem::align_of::<Tagged>(), tagged.alignment());
+    assert_eq!(
+        vec![mem::offset_of!(Tagged, tag), mem::offset_of!(Tagged, value)],
+        tagged.struct_offsets(ffi_abi_FFI_DEFAULT_ABI).unwrap()
+    );
+
+    let mut shorts = shorts();
+    assert_eq!(mem::size_of::<Shorts>(), shorts.size());
+    assert_eq!(mem::align_of::<Shorts>(), shorts.alignment());
+    assert_eq!(
+        vec![
+            mem::offset_of!(Shorts, a),
+            mem::offset_of!(Shorts, b),
+            mem::offset_of!(Shorts, c),
+        ],
+        shorts.struct_offsets(ffi_abi_FFI_DEFAULT_ABI).unwrap()
+    );
+
+    for (ffi_type, size, alignment) in [
+        (bytes(), mem::size_of::<Bytes>(), mem::align_of::<Bytes>()),
+        (
+            vector(),
+            mem::size_of::<Vector>(),
+            mem::align_of::<Vector>(),
+        ),
+        (
+            padded(),
+            mem::size_of::<Padded>(),
+            mem::align_of::<Padded>(),
+        ),
+        (wide(), mem::size_of::<Wide>(), mem::align_of::<Wide>()),
+    ] {
+        assert_eq!(size, ffi_type.size(), "size of {ffi_type}");
+        assert_eq!(alignment, ffi_type.alignment(), "alignment of {ffi_type}");
+    }
+
+    // A packed structure as a field keeps its layout.
+    let mut outer = Type::structure([Type::u8(), tagged, Type::u16()]);
+    assert_eq!(
+        vec![0, 1, 6],
+        outer.struct_offsets(ffi_abi_FFI_DEFAULT_ABI).unwrap()
+    );
+    assert_eq!(8, outer.size());
+}
+
+#[test]
+fn misaligned_fields_cannot_be_passed_by_value() {
+    for cif in [
+        Cif::try_new([tagged()], Type::void()),
+        Cif::try_new([], tagged()),
+        Cif::try_new([Type::structure([Type::u8(), tagged()])], Type::void()),
+        Cif::try_new([Type::union([tagged(), Type::u64()])], Type::void()),
+    ] {
+        assert_eq!(Some(low::Error::Typedef), cif.err());
+    }
+}
+
+#[test]
+fn overaligned_structures_cannot_be_passed_by_value() {
+    let error = Cif::try_new_detailed([Type::i64(), wide()], Type::void()).unwrap_err();
+    assert_eq!(low::Error::Typedef, error.kind());
+    assert_eq!(
+        Some(&Culprit::Arg {
+            index: 1,
+            type_: wide()
+        }),
+        error.culprit()
+    );
+
+    // The caller provides the memory for a returned structure.
+    assert!(Cif::try_new([Type::pointer(), Type::i64()], wide()).is_ok());
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "miri cannot load shared libraries")]
+fn call_with_custom_layouts() {
+    let library = fixture("layout");
+
+    let cif = Cif::new([Type::pointer()], Type::u32());
+    let result: u32 = unsafe {
+        cif.call(
+            code_ptr(&library, "tagged_value"),
+            &[arg(&&Tagged {
+                tag: 1,
+                value: 0xdead_beef,
+            })],
+        )
+    };
+    assert_eq!(0xdead_beef, result);
+
+    let cif = Cif::new([bytes()], bytes());
+    let result: Bytes = unsafe {
+        cif.call(
+            code_ptr(&library, "bytes_swap"),
+            &[arg(&Bytes { a: 1, b: 2, c: 3 })],
+        )
+    };
+    assert_eq!((2, 1, 4), (result.a, result.b, { result.c }));
+
+    let cif = Cif::new([shorts()], shorts());
+    let result: Shorts = unsafe {
+        cif.call(
+            code_ptr(&library, "shorts_rotate"),
+            &[arg(&Shorts { a: 7, b: 300, c: 9 })],
+        )
+    };
+    assert_eq!((300, 9, 7), ({ result.a }, { result.b }, result.c));
+
+    let cif = Cif::new([vector(), Type::f32()], vector());
+    let result: Vector = unsafe {
+        cif.call(
+            code_ptr(&library, "vector_scale"),
+            &[
+                arg(&Vector {
+                    x: 1.0,
+                    y: 2.0,
+                    z: 3.0,
+                }),
+                arg(&2.0f32),
+            ],
+        )
+    };
+    assert_eq!(
+        Vector {
+            x: 2.0,
+            y: 4.0,
+            z: 6.0
+        },
+        result
+    );
+
+    let cif = Cif::new([padded(), Type::i32()], padded());
+    let result: Padded = unsafe {
+        cif.call(
+            code_ptr(&library, "padded_add"),
+            &[arg(&Padded { i: 40 }), arg(&2i32)],
+        )
+    };
+    assert_eq!(42, result.i);
+
+    let cif = Cif::new([Type::pointer(), Type::i64()], wide());
+    let result: Wide = unsafe {
+        cif.call(
+            code_ptr(&library, "wide_mix"),
+            &[arg(&&Wide { d: 1.25, l: 5 }), arg(&6i64)],
+        )
+    };
+    assert_eq!((2.5, 11), (result.d, result.l));
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "miri cannot load shared libraries")]
+fn call_with_opaque_types() {
+    let library = fixture("layout");
+
+    let shorts = Type::opaque(Layout::new::<Shorts>(), OpaqueClass::Integer);
+    let cif = Cif::new([shorts.clone()], shorts);
+    let result: Shorts = unsafe {
+        cif.call(
+            code_ptr(&library, "shorts_rotate"),
+            &[arg(&Shorts { a: 7, b: 300, c: 9 })],
+        )
+    };
+    assert_eq!((300, 9, 7), ({ result.a }, { result.b }, result.c));
+
+    let vector = Type::opaque(Layout::new::<Vector>(), OpaqueClass::Float);
+    let cif = Cif::new([vector.clone(), Type::f32()], vector);
+    let result: Vector = unsafe {
+        cif.call(
+            code_ptr(&library, "vector_scale"),
+            &[
+                arg(&Vector {
+                    x: 1.0,
+                    y: 2.0,
+                    z: 3.0,
+                }),
+                arg(&2.0f32),
+            ],
+        )
+    };
+    assert_eq!(
+        Vector {
+            x: 2.0,
+            y: 4.0,
+            z: 6.0
+        },
+        result
+    );
+}
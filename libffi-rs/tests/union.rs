@@ -0,0 +1,201 @@
+//! Calls C functions taking and returning unions by value, compiled from
+//! `tests/c/union.c`, through [`middle::Cif`]s using [`Type::union`].
+
+#![cfg(all(unix, target_arch = "x86_64"))]
+
+mod common;
+
+use std::mem;
+
+use libffi::middle::{arg, Cif, Type};
+
+use common::{code_ptr, fixture};
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Ints {
+    b: u8,
+    i: i32,
+    l: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Floats {
+    f: f32,
+    pair: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Mixed {
+    f: f32,
+    i: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IntDouble {
+    d: f64,
+    l: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Doubles {
+    e: f64,
+    f: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union IntSse {
+    a: IntDouble,
+    b: Doubles,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Memory {
+    d: [f64; 3],
+    l: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Tagged {
+    tag: u8,
+    value: Mixed,
+}
+
+fn ints() -> Type {
+    Type::union([Type::u8(), Type::i32(), Type::i64()])
+}
+
+fn floats() -> Type {
+    Type::union([Type::f32(), Type::array(Type::f32(), 2)])
+}
+
+fn mixed() -> Type {
+    Type::union([Type::f32(), Type::i32()])
+}
+
+fn int_sse() -> Type {
+    Type::union([
+        Type::structure([Type::f64(), Type::i64()]),
+        Type::structure([Type::f64(), Type::f64()]),
+    ])
+}
+
+fn memory() -> Type {
+    Type::union([Type::array(Type::f64(), 3), Type::i64()])
+}
+
+fn tagged() -> Type {
+    Type::structure([Type::u8(), mixed()])
+}
+
+#[test]
+fn union_layout() {
+    for (ffi_type, size, alignment) in [
+        (ints(), mem::size_of::<Ints>(), mem::align_of::<Ints>()),
+        (
+            floats(),
+            mem::size_of::<Floats>(),
+            mem::align_of::<Floats>(),
+        ),
+        (mixed(), mem::size_of::<Mixed>(), mem::align_of::<Mixed>()),
+        (
+            int_sse(),
+            mem::size_of::<IntSse>(),
+            mem::align_of::<IntSse>(),
+        ),
+        (
+            memory(),
+            mem::size_of::<Memory>(),
+            mem::align_of::<Memory>(),
+        ),
+        (
+            tagged(),
+            mem::size_of::<Tagged>(),
+            mem::align_of::<Tagged>(),
+        ),
+    ] {
+        assert_eq!(size, ffi_type.size(), "size of {ffi_type}");
+        assert_eq!(alignment, ffi_type.alignment(), "alignment of {ffi_type}");
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "miri cannot load shared libraries")]
+fn call_with_unions() {
+    let library = fixture("union");
+
+    let cif = Cif::new([ints(), ints()], ints());
+    let result: Ints = unsafe {
+        cif.call(
+            code_ptr(&library, "ints_add"),
+            &[arg(&Ints { l: 1 << 40 }), arg(&Ints { l: 5 })],
+        )
+    };
+    assert_eq!((1 << 40) + 5, unsafe { result.l });
+
+    let cif = Cif::new([floats()], floats());
+    let result: Floats = unsafe {
+        cif.call(
+            code_ptr(&library, "floats_swap"),
+            &[arg(&Floats { pair: [1.5, -2.5] })],
+        )
+    };
+    assert_eq!([-2.5, 1.5], unsafe { result.pair });
+
+    let cif = Cif::new([mixed(), Type::c_int()], mixed());
+    let result: Mixed = unsafe {
+        cif.call(
+            code_ptr(&library, "mixed_negate"),
+            &[arg(&Mixed { f: 3.25 }), arg(&1)],
+        )
+    };
+    assert_eq!(-3.25, unsafe { result.f });
+    let result: Mixed = unsafe {
+        cif.call(
+            code_ptr(&library, "mixed_negate"),
+            &[arg(&Mixed { i: 7 }), arg(&0)],
+        )
+    };
+    assert_eq!(-7, unsafe { result.i });
+
+    let cif = Cif::new([int_sse()], int_sse());
+    let result: IntSse = unsafe {
+        cif.call(
+            code_ptr(&library, "int_sse_mix"),
+            &[arg(&IntSse {
+                a: IntDouble { d: 0.75, l: 41 },
+            })],
+        )
+    };
+    assert_eq!(1.5, unsafe { result.a.d });
+    assert_eq!(42, unsafe { result.a.l });
+
+    let cif = Cif::new([memory(), Type::f64()], memory());
+    let result: Memory = unsafe {
+        cif.call(
+            code_ptr(&library, "memory_sum"),
+            &[arg(&Memory { d: [1.0, 2.0, 3.0] }), arg(&0.5)],
+        )
+    };
+    assert_eq!([3.0, 2.0, 1.0], unsafe { result.d });
+
+    let cif = Cif::new([tagged()], tagged());
+    let result: Tagged = unsafe {
+        cif.call(
+            code_ptr(&library, "tagged_negate"),
+            &[arg(&Tagged {
+                tag: 1,
+                value: Mixed { f: 0.5 },
+            })],
+        )
+    };
+    assert_eq!(1, result.tag);
+    assert_eq!(-0.5, unsafe { result.value.f });
+}
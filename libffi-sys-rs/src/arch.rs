@@ -104,6 +104,9 @@ pub use x86::x86_win32::*;
 #[cfg(all(target_arch = "x86", unix))]
 pub use x86::x86::*;
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub use x86::FFI_GO_CLOSURES;
+
 /// From libffi:src/arm/ffitarget.h.
 /// See: <https://github.com/libffi/libffi/blob/252c0f463641e6100169c3f0a4a590d7df438278/src/arm/ffitarget.h#L41>
 mod arm {
@@ -157,6 +160,8 @@ mod aarch64 {
     // No GO_CLOSURES on iOS or Windows
     #[cfg(not(any(target_os = "windows", target_vendor = "apple")))]
     pub const FFI_GO_CLOSURES: u32 = 1;
+    #[cfg(any(target_os = "windows", target_vendor = "apple"))]
+    pub const FFI_GO_CLOSURES: u32 = 0;
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -393,6 +398,8 @@ mod mips {
 
         pub const FFI_CLOSURES: u32 = 1;
         pub const FFI_GO_CLOSURES: u32 = 1;
+        pub const FFI_NATIVE_RAW_API: u32 = 0;
+        #[deprecated = "Misspelling of `FFI_NATIVE_RAW_API`"]
         pub const FFI_NATIVE_RAW_ABI: u32 = 0;
     }
 